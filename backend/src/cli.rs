@@ -0,0 +1,208 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::{export, import};
+use crate::routes::audit::Actor;
+use crate::routes::export::ExportQuery;
+use crate::routes::import::ImportFormat;
+use crate::{database, indexes, migrations};
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use clap::{Parser, Subcommand};
+use futures::StreamExt;
+use mongodb::bson::DateTime;
+use mongodb::Client;
+use std::fs::{self, File};
+use std::io::{self, Error, Write};
+use std::path::{Path, PathBuf};
+
+/// The product module server and its maintenance commands.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// The maintenance command to run instead of starting the server.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// An enum representing the maintenance commands.
+#[derive(Subcommand)]
+pub enum Command {
+    /// Create or update products in bulk from a CSV or NDJSON file and print the report.
+    Import {
+        /// The import file.
+        path: PathBuf,
+
+        /// The file format, detected from the file extension if missing.
+        #[arg(long, value_enum)]
+        format: Option<ImportFormat>,
+
+        /// Only validate the file without writing anything.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// The actor ID recorded in the audit log.
+        #[arg(long, default_value = "cli")]
+        actor: String,
+    },
+
+    /// Export the catalog as CSV, NDJSON or a Google Merchant Center feed.
+    Export {
+        /// The export format and filters.
+        #[command(flatten)]
+        query: ExportQuery,
+
+        /// The file to write the export to, or the standard output if missing.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Create the missing database indexes and recreate the changed ones.
+    Indexes {
+        /// Only report the differences between the existing indexes and their definitions, failing if there are any.
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Apply the pending database migrations, or migrate up or down to a version.
+    Migrate {
+        /// The version to migrate to, 0 reverting every migration, or the latest version if missing.
+        #[arg(long)]
+        to: Option<u32>,
+
+        /// Only print the applied and pending migrations.
+        #[arg(long, conflicts_with = "to")]
+        status: bool,
+    },
+
+    /// Listen for webhook deliveries on a local port and print them, as a stand-in for a real receiver.
+    WebhookSink {
+        /// The local port to listen on.
+        #[arg(long, default_value_t = 9000)]
+        port: u16,
+    },
+}
+
+/// Run a maintenance command.
+///
+/// # Parameters
+///
+/// - `command`: The command to run.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the command completed, or an `Error` if it failed.
+pub async fn run(command: Command, client: Client) -> Result<(), Error> {
+    match command {
+        Command::Import {
+            path,
+            format,
+            dry_run,
+            actor,
+        } => {
+            let format = format.unwrap_or_else(|| detect_format(&path));
+            let bytes = fs::read(&path)?;
+            let actor = Actor {
+                id: actor,
+                endpoint: "cli import".to_string(),
+            };
+
+            let report = import::import_products(
+                import::parse_rows(&bytes, format),
+                dry_run,
+                actor,
+                client,
+                database::establish_search_connection(),
+            )
+            .await;
+
+            println!("{}", serde_json::to_string_pretty(&report)?);
+
+            Ok(())
+        }
+        Command::Export { query, output } => {
+            let mut writer: Box<dyn Write> = match output {
+                Some(path) => Box::new(File::create(path)?),
+                None => Box::new(io::stdout().lock()),
+            };
+            let mut stream = export::export_products(query, client)
+                .await
+                .map_err(|error| io::Error::other(error.to_string()))?;
+
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk.map_err(|error| io::Error::other(error.to_string()))?;
+                writer.write_all(&chunk)?;
+            }
+
+            writer.flush()
+        }
+        Command::Indexes { check: false } => indexes::ensure_indexes(&client).await,
+        Command::Indexes { check: true } => {
+            let drifts = indexes::check_indexes(&client).await?;
+
+            for drift in &drifts {
+                println!("{}", drift);
+            }
+
+            if drifts.is_empty() {
+                println!("Indexes match their definitions");
+                Ok(())
+            } else {
+                Err(io::Error::other(format!(
+                    "{} index(es) differ from their definitions",
+                    drifts.len()
+                )))
+            }
+        }
+        Command::Migrate { status: true, .. } => {
+            for status in migrations::status(&client).await? {
+                match status.applied_at {
+                    Some(applied_at) => println!(
+                        "{} {}: applied at {}",
+                        status.version,
+                        status.name,
+                        DateTime::from_millis(applied_at)
+                            .try_to_rfc3339_string()
+                            .unwrap_or_default()
+                    ),
+                    None => println!("{} {}: pending", status.version, status.name),
+                }
+            }
+
+            Ok(())
+        }
+        Command::Migrate { to, .. } => migrations::migrate(to, &client).await,
+        Command::WebhookSink { port } => {
+            println!("Listening for webhooks on http://127.0.0.1:{}", port);
+
+            HttpServer::new(|| App::new().default_service(web::to(print_webhook)))
+                .bind(("127.0.0.1", port))?
+                .run()
+                .await
+        }
+    }
+}
+
+/// Print a webhook delivery received by the webhook sink and acknowledge it.
+async fn print_webhook(request: HttpRequest, body: web::Bytes) -> HttpResponse {
+    println!("{} {}", request.method(), request.uri());
+
+    for (name, value) in request.headers() {
+        println!("{}: {}", name, value.to_str().unwrap_or_default());
+    }
+
+    println!("\n{}\n", String::from_utf8_lossy(&body));
+
+    HttpResponse::Ok().finish()
+}
+
+/// Detect the format of an import file from its extension, defaulting to NDJSON.
+fn detect_format(path: &Path) -> ImportFormat {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("csv") => ImportFormat::Csv,
+        _ => ImportFormat::Ndjson,
+    }
+}
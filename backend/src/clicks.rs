@@ -0,0 +1,37 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::sync::lock;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A buffer of product click increments, flushed to the database and the search index periodically instead of on every click.
+#[derive(Default)]
+pub struct ClickBuffer {
+    /// The number of clicks not yet flushed, keyed by product ID.
+    counts: Mutex<HashMap<String, u32>>,
+}
+
+impl ClickBuffer {
+    /// Count a click on a product.
+    pub fn add(&self, pid: &str) {
+        *lock(&self.counts).entry(pid.to_string()).or_insert(0) += 1;
+    }
+
+    /// Take the buffered clicks, leaving the buffer empty.
+    pub fn take(&self) -> HashMap<String, u32> {
+        std::mem::take(&mut *lock(&self.counts))
+    }
+
+    /// Put back clicks that could not be flushed, so they are retried on the next flush.
+    pub fn restore(&self, counts: impl IntoIterator<Item = (String, u32)>) {
+        let mut buffered = lock(&self.counts);
+
+        for (pid, count) in counts {
+            *buffered.entry(pid).or_insert(0) += count;
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::audit;
+use crate::routes::audit::Actor;
+use crate::structures::Product;
+
+use actix_web::{error, Error};
+use mongodb::bson::{doc, Document};
+use mongodb::Client;
+
+/// Permanently delete a product.
+///
+/// This function removes a product from the database and the search index, along with its analytics, stock alerts, recommendations, recently viewed entries and pending webhook deliveries. Its audit log is kept. Unlike delisting, it cannot be undone.
+///
+/// # Parameters
+///
+/// - `pid`: The `pid` of the product to be purged.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the product is successfully purged, `false` if the product with the given `pid` does not exist, and an `Error` in case of a database or indexing error.
+pub async fn purge_product(
+    pid: String,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let filter = doc! { "pid": pid.clone() };
+
+    if let Ok(Some(product)) = collection.find_one_and_delete(filter, None).await {
+        ms_client
+            .index("products")
+            .delete_document(pid.clone())
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+
+        audit::record(&actor, Some(&product), None, &client).await?;
+        purge_related(&pid, &client).await?;
+
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Delete the data kept about a product outside of the product collection.
+async fn purge_related(pid: &str, client: &Client) -> Result<(), Error> {
+    let database = client.database("ecommerce_db");
+    let filter = doc! { "pid": pid };
+
+    for collection in [
+        "analytics_events",
+        "analytics_rollups",
+        "bought_together",
+        "restock_subscriptions",
+        "stock_levels",
+        "webhook_deliveries",
+    ] {
+        database
+            .collection::<Document>(collection)
+            .delete_many(filter.clone(), None)
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+    }
+
+    for collection in ["bought_together", "recent_views"] {
+        database
+            .collection::<Document>(collection)
+            .update_many(
+                doc! { "products.pid": pid },
+                doc! { "$pull": { "products": { "pid": pid } } },
+                None,
+            )
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+    }
+
+    Ok(())
+}
@@ -3,100 +3,742 @@
 //! This software may be modified and distributed under the terms of the MIT license.
 //! See the LICENSE file for details.
 
-use crate::routes::analytics::Data;
-use crate::structures::Product;
+use crate::clicks::ClickBuffer;
+use crate::crud::recent;
+use crate::guard::{EventGuard, Rejection};
+use crate::ranking::PopularitySettings;
+use crate::routes::analytics::{
+    AnalyticsQuery, Bucket, CategoryPerformance, DashboardQuery, Data, EventOutcome, EventRequest,
+    Performance, ProductPerformance, SellerDashboard, Visitor,
+};
+use crate::structures::{
+    Event, EventCounts, EventKind, Granularity, Product, ProductStatus, Rollup,
+};
 
-use actix_web::Error;
-use mongodb::bson::doc;
+use actix_web::{error, Error};
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::options::{FindOptions, UpdateOptions};
 use mongodb::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// The default length of the analytics time range in milliseconds (30 days).
+const DEFAULT_RANGE: i64 = 30 * 86_400_000;
+
+/// The maximum number of buckets in an analytics series.
+const MAX_BUCKETS: i64 = 1000;
+
+/// The default number of top and bottom performing products of a seller dashboard.
+const DEFAULT_PERFORMERS: u32 = 5;
+
+/// The maximum number of top and bottom performing products of a seller dashboard.
+const MAX_PERFORMERS: u32 = 50;
+
+/// The maximum number of units of a single addition to a cart or purchase event.
+const MAX_EVENT_QUANTITY: u32 = 100;
+
+/// The default stock level at or below which a product is low on stock.
+const DEFAULT_LOW_STOCK: u32 = 5;
+
+/// The maximum number of products whose clicks are written in a single database command.
+const CLICK_BATCH_SIZE: usize = 500;
+
+/// The maximum number of products whose popularity is written in a single database command.
+const POPULARITY_BATCH_SIZE: usize = 500;
+
+/// The weight of a click in the popularity and trending scores.
+pub const CLICK_WEIGHT: f64 = 1.0;
+
+/// The weight of a purchase in the popularity and trending scores.
+pub const PURCHASE_WEIGHT: f64 = 10.0;
+
+/// The product ID and popularity score of a stored product.
+#[derive(Deserialize)]
+struct StoredPopularity {
+    pid: String,
+
+    #[serde(default)]
+    popularity: f32,
+}
+
+/// The summed figures of a seller dashboard.
+const PERFORMANCE_FIELDS: [&str; 7] = [
+    "views",
+    "clicks",
+    "add_to_cart",
+    "wishlist",
+    "purchases",
+    "units",
+    "revenue",
+];
+
+/// The totals of a seller dashboard, as computed by the aggregation pipeline.
+#[derive(Default, Deserialize)]
+struct Totals {
+    #[serde(default)]
+    products: u64,
+
+    #[serde(flatten)]
+    performance: Performance,
+}
+
+/// The facets of a seller dashboard, as computed by the aggregation pipeline.
+#[derive(Deserialize)]
+struct Facets {
+    totals: Vec<Totals>,
+    categories: Vec<CategoryPerformance>,
+    top: Vec<ProductPerformance>,
+    bottom: Vec<ProductPerformance>,
+    low_stock: Vec<ProductPerformance>,
+}
 
 /// Retrieve analytics data for a specific product.
 ///
-/// This function queries the MongoDB database to retrieve analytics data for a product based on its ID.
-
+/// This function queries the MongoDB database to retrieve analytics data for a product based on its ID. The events over the time range are read from the rollups of the requested granularity, and the time range is extended to start at the beginning of its first bucket.
+///
 /// # Parameters
 ///
 /// - `pid`: The product ID to retrieve analytics data for.
+/// - `query`: Query parameters containing the optional time range and granularity.
 /// - `client`: MongoDB client instance used for database access.
-
+///
 /// # Returns
 ///
-/// - Returns a `Result` containing an `Option<Data>`, where `Some(data)` represents success with the analytics data, and `None` indicates that the data was not found. An `Error` is returned in case of a database query error.
-pub async fn retrieve_data(pid: String, client: Client) -> Result<Option<Data>, Error> {
-    let collection = client
-        .database("ecommerce_db")
-        .collection::<Product>("products");
-    let filter = doc! { "pid": pid };
-
-    if let Ok(Some(product)) = collection.find_one(filter, None).await {
-        let data = Data {
-            pid: product.pid.clone(),
-            sid: product.sid.clone(),
-            stock: product.stock,
-            sales: product.sales,
-            rating: product.rating,
-            clicks: product.clicks,
-        };
-
-        Ok(Some(data))
-    } else {
-        Ok(None)
+/// - Returns a `Result` containing an `Option<Data>`, where `Some(data)` represents success with the analytics data, and `None` indicates that the data was not found. A bad request `Error` is returned if the time range is empty, out of bounds or spans too many buckets, and an `Error` in case of a database query error.
+pub async fn retrieve_data(
+    pid: String,
+    query: AnalyticsQuery,
+    client: Client,
+) -> Result<Option<Data>, Error> {
+    let granularity = query.granularity;
+    let (from, to) = time_range(query.from, query.to)?;
+    let (first, buckets) = series_range(granularity, from, to)?;
+    let millis = granularity.millis();
+
+    let database = client.database("ecommerce_db");
+    let product = match database
+        .collection::<Product>("products")
+        .find_one(doc! { "pid": pid.clone() }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+    {
+        Some(product) => product,
+        None => return Ok(None),
+    };
+
+    let options = FindOptions::builder().sort(doc! { "start": 1 }).build();
+    let rollups: Vec<Rollup> = database
+        .collection::<Rollup>("analytics_rollups")
+        .find(
+            doc! {
+                "pid": pid,
+                "granularity": granularity.as_str(),
+                "start": { "$gte": first, "$lt": to },
+            },
+            options,
+        )
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    let mut series: Vec<Bucket> = (0..buckets)
+        .map(|index| Bucket {
+            start: first + index * millis,
+            counts: EventCounts::default(),
+        })
+        .collect();
+    let mut totals = EventCounts::default();
+
+    for rollup in rollups {
+        totals.add(&rollup.counts);
+
+        let index = ((rollup.start - first) / millis) as usize;
+        if let Some(bucket) = series.get_mut(index) {
+            bucket.counts = rollup.counts;
+        }
     }
+
+    let data = Data {
+        pid: product.pid.clone(),
+        sid: product.sid.clone(),
+        stock: product.stock,
+        sales: product.sales,
+        rating: product.rating,
+        clicks: product.clicks,
+        from: first,
+        to,
+        granularity,
+        totals,
+        series,
+    };
+
+    Ok(Some(data))
 }
 
-/// Increment the number of clicks for a specific product.
+/// Record an analytics event for a specific product.
+///
+/// This function first passes the event through the event guard, which rejects floods, bots and repeated events, then stores the event and counts it in the hourly and daily rollups of the product. Click events are also counted in the click buffer, which increments the number of clicks of the products used for sorting the catalog and search results when it is flushed. Admitted views and clicks of an identified shopper also move the product to the front of their recently viewed products.
 ///
-/// This function updates the number of clicks for a product in the MongoDB database based on its ID.
-
 /// # Parameters
 ///
-/// - `pid`: The product ID to increment clicks for.
+/// - `pid`: The product ID to record the event for.
+/// - `event`: The event kind, the anonymous ID of the browsing session and the user ID of the shopper if known, and the number of units added to a cart or purchased, defaulting to 1.
+/// - `visitor`: The client sending the event.
+/// - `guard`: The guard filtering the events.
+/// - `clicks`: The buffer of click increments.
 /// - `client`: MongoDB client instance used for database access.
-
+///
 /// # Returns
 ///
-/// - Returns `true` if the click increment operation is successful, `false` if it fails, and an `Error` in case of a database query error.
-pub async fn increment_clicks(
+/// - Returns a `Result` containing the `EventOutcome`, a bad request `Error` if the quantity is zero or too large, a too many requests `Error` if the client exceeded the rate limit, and an `Error` in case of a database error.
+pub async fn record_event(
     pid: String,
+    event: EventRequest,
+    visitor: Visitor,
+    guard: &EventGuard,
+    clicks: &ClickBuffer,
     client: Client,
-    ms_client: meilisearch_sdk::Client,
-) -> Result<bool, Error> {
-    let collection = client
+) -> Result<EventOutcome, Error> {
+    let EventRequest {
+        kind,
+        session,
+        uid,
+        quantity,
+    } = event;
+    let quantity = match kind {
+        EventKind::AddToCart | EventKind::Purchase => quantity.unwrap_or(1),
+        _ => 1,
+    };
+
+    if quantity == 0 || quantity > MAX_EVENT_QUANTITY {
+        return Err(error::ErrorBadRequest(format!(
+            "Quantity must be between 1 and {}",
+            MAX_EVENT_QUANTITY
+        )));
+    }
+
+    match guard.admit(&visitor) {
+        Ok(()) => {}
+        Err(Rejection::RateLimited) => {
+            return Err(error::ErrorTooManyRequests("Too many analytics events"))
+        }
+        Err(_) => return Ok(EventOutcome::Ignored),
+    }
+
+    let database = client.database("ecommerce_db");
+    let mut product = match database
+        .collection::<Product>("products")
+        .find_one(doc! { "pid": pid.clone() }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+    {
+        Some(product) => product,
+        None => return Ok(EventOutcome::NotFound),
+    };
+
+    if guard.first_seen(&visitor, &pid, kind).is_err() {
+        return Ok(EventOutcome::Ignored);
+    }
+
+    if matches!(kind, EventKind::View | EventKind::Click) {
+        if let Some(viewer) = uid.as_ref().or(session.as_ref()) {
+            recent::record_view(viewer, &pid, &client).await?;
+        }
+    }
+
+    // Apply a sale that started or ended since the last pricing run.
+    product.aggregate();
+
+    let event = Event {
+        kind,
+        pid: pid.clone(),
+        sid: product.sid.clone(),
+        session,
+        quantity,
+        price: (kind == EventKind::Purchase).then_some(product.price),
+        timestamp: DateTime::now().timestamp_millis(),
+    };
+
+    database
+        .collection::<Event>("analytics_events")
+        .insert_one(event.clone(), None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    let options = UpdateOptions::builder().upsert(true).build();
+
+    for granularity in [Granularity::Hour, Granularity::Day] {
+        database
+            .collection::<Rollup>("analytics_rollups")
+            .update_one(
+                doc! {
+                    "pid": pid.clone(),
+                    "granularity": granularity.as_str(),
+                    "start": granularity.bucket(event.timestamp),
+                },
+                doc! {
+                    "$inc": { format!("counts.{}", kind.as_str()): 1_i64 },
+                    "$setOnInsert": { "sid": event.sid.clone() },
+                },
+                options.clone(),
+            )
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+    }
+
+    if kind == EventKind::Click {
+        clicks.add(&pid);
+    }
+
+    Ok(EventOutcome::Recorded)
+}
+
+/// Retrieve the analytics dashboard of a seller.
+///
+/// This function computes the figures of every product of the seller, except archived ones, from the analytics events over the time range, then aggregates them per category and for the seller in a single aggregation pipeline.
+///
+/// # Parameters
+///
+/// - `sid`: The seller ID to retrieve the dashboard for.
+/// - `query`: Query parameters containing the optional time range, number of top and bottom performers and low stock level.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the `SellerDashboard`, a bad request `Error` if the time range is empty or the number of performers is invalid, or an `Error` in case of a database query error.
+pub async fn retrieve_dashboard(
+    sid: String,
+    query: DashboardQuery,
+    client: Client,
+) -> Result<SellerDashboard, Error> {
+    let (from, to) = time_range(query.from, query.to)?;
+    let limit = match query.limit {
+        None => DEFAULT_PERFORMERS,
+        Some(limit) if limit > 0 && limit <= MAX_PERFORMERS => limit,
+        Some(_) => {
+            return Err(error::ErrorBadRequest(format!(
+                "Limit must be between 1 and {}",
+                MAX_PERFORMERS
+            )))
+        }
+    };
+    let low_stock = query.low_stock.unwrap_or(DEFAULT_LOW_STOCK);
+
+    let is_kind = |kind: EventKind| doc! { "$eq": ["$kind", kind.as_str()] };
+    let count = |kind: EventKind| doc! { "$sum": { "$cond": [is_kind(kind), 1, 0] } };
+    // Events recorded before quantities were introduced are for a single unit.
+    let quantity = doc! { "$ifNull": ["$quantity", 1] };
+
+    let mut sums = Document::new();
+    for field in PERFORMANCE_FIELDS {
+        sums.insert(field, doc! { "$sum": format!("${}", field) });
+    }
+
+    let mut category_sums = sums.clone();
+    category_sums.insert("_id", "$category");
+    category_sums.insert("products", doc! { "$sum": 1 });
+
+    let mut seller_sums = sums;
+    seller_sums.insert("_id", Bson::Null);
+    seller_sums.insert("products", doc! { "$sum": 1 });
+
+    let round_revenue = doc! { "$set": { "revenue": { "$round": ["$revenue", 2] } } };
+
+    let pipeline = vec![
+        doc! {
+            "$match": {
+                "sid": sid.clone(),
+                "status": { "$ne": ProductStatus::Archived.as_str() },
+            }
+        },
+        // The figures of each product over the time range.
+        doc! {
+            "$lookup": {
+                "from": "analytics_events",
+                "let": { "pid": "$pid" },
+                "pipeline": [
+                    {
+                        "$match": {
+                            "$expr": { "$eq": ["$pid", "$$pid"] },
+                            "timestamp": { "$gte": from, "$lt": to },
+                        }
+                    },
+                    {
+                        "$group": {
+                            "_id": Bson::Null,
+                            "views": count(EventKind::View),
+                            "clicks": count(EventKind::Click),
+                            "add_to_cart": count(EventKind::AddToCart),
+                            "wishlist": count(EventKind::Wishlist),
+                            "purchases": count(EventKind::Purchase),
+                            "units": {
+                                "$sum": { "$cond": [is_kind(EventKind::Purchase), quantity.clone(), 0] }
+                            },
+                            "revenue": {
+                                "$sum": {
+                                    "$cond": [
+                                        is_kind(EventKind::Purchase),
+                                        { "$multiply": [quantity, { "$ifNull": ["$price", 0] }] },
+                                        0,
+                                    ]
+                                }
+                            },
+                        }
+                    },
+                ],
+                "as": "performance",
+            }
+        },
+        doc! {
+            "$replaceRoot": {
+                "newRoot": {
+                    "$mergeObjects": [
+                        { "pid": "$pid", "name": "$name", "category": "$category", "stock": "$stock" },
+                        { "$arrayElemAt": ["$performance", 0] },
+                    ]
+                }
+            }
+        },
+        doc! { "$unset": "_id" },
+        doc! {
+            "$facet": {
+                "totals": [
+                    { "$group": seller_sums },
+                    round_revenue.clone(),
+                ],
+                "categories": [
+                    { "$group": category_sums },
+                    { "$set": { "category": "$_id" } },
+                    round_revenue.clone(),
+                    { "$sort": { "revenue": -1, "units": -1, "category": 1 } },
+                ],
+                "top": [
+                    { "$sort": { "revenue": -1, "units": -1, "pid": 1 } },
+                    { "$limit": limit as i64 },
+                    round_revenue.clone(),
+                ],
+                "bottom": [
+                    { "$sort": { "revenue": 1, "units": 1, "pid": 1 } },
+                    { "$limit": limit as i64 },
+                    round_revenue.clone(),
+                ],
+                "low_stock": [
+                    { "$match": { "stock": { "$lte": low_stock as i64 } } },
+                    { "$sort": { "stock": 1, "pid": 1 } },
+                    round_revenue,
+                ],
+            }
+        },
+    ];
+
+    let facets: Facets = match client
         .database("ecommerce_db")
-        .collection::<Product>("products");
-    let filter = doc! { "pid": pid.clone() };
-    let update = doc! { "$inc": { "clicks": 1 } };
+        .collection::<Product>("products")
+        .aggregate(pipeline, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_next()
+        .await
+        .map_err(error::ErrorInternalServerError)?
+    {
+        Some(facets) => {
+            mongodb::bson::from_document(facets).map_err(error::ErrorInternalServerError)?
+        }
+        None => return Err(error::ErrorInternalServerError("Missing dashboard facets")),
+    };
+
+    let totals = facets.totals.into_iter().next().unwrap_or_default();
+    let rate = |count: u64, total: u64| {
+        if total == 0 {
+            0.0
+        } else {
+            count as f64 / total as f64
+        }
+    };
+
+    Ok(SellerDashboard {
+        sid,
+        from,
+        to,
+        products: totals.products,
+        click_through_rate: rate(totals.performance.clicks, totals.performance.views),
+        conversion_rate: rate(totals.performance.purchases, totals.performance.clicks),
+        totals: totals.performance,
+        top_products: facets.top,
+        bottom_products: facets.bottom,
+        low_stock: facets.low_stock,
+        categories: facets.categories,
+    })
+}
+
+/// Flush the buffered clicks to the database and the search index.
+///
+/// This function increments the number of clicks of every clicked product with batched database commands, then refreshes only the number of clicks of their search documents with a single partial update. Clicks that could not be written are put back into the buffer and retried on the next flush.
+///
+/// # Parameters
+///
+/// - `clicks`: The buffer of click increments.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the number of products whose clicks were flushed, or an `Error` in case of a database or indexing error.
+pub async fn flush_clicks(
+    clicks: &ClickBuffer,
+    client: &Client,
+    ms_client: &meilisearch_sdk::Client,
+) -> Result<usize, Error> {
+    let counts: Vec<(String, u32)> = clicks.take().into_iter().collect();
 
-    if let Ok(_) = collection.update_one(filter.clone(), update, None).await {
-        let product = collection
-            .find_one(filter.clone(), None)
+    if counts.is_empty() {
+        return Ok(0);
+    }
+
+    let database = client.database("ecommerce_db");
+
+    for (index, batch) in counts.chunks(CLICK_BATCH_SIZE).enumerate() {
+        let updates: Vec<Document> = batch
+            .iter()
+            .map(|(pid, count)| {
+                doc! { "q": { "pid": pid.clone() }, "u": { "$inc": { "clicks": *count as i64 } } }
+            })
+            .collect();
+
+        if let Err(error) = database
+            .run_command(doc! { "update": "products", "updates": updates }, None)
             .await
-            .unwrap()
-            .unwrap();
-
-        ms_client
-            .index("products")
-            .add_or_replace(
-                &[Product {
-                    pid: pid.clone(),
-                    sid: product.sid.clone(),
-                    name: product.name.clone(),
-                    description: product.description.clone(),
-                    image: product.image.clone(),
-                    category: product.category.clone(),
-                    price: product.price,
-                    stock: product.stock,
-                    sales: product.sales,
-                    rating: product.rating,
-                    clicks: product.clicks,
-                }],
-                None,
-            )
+        {
+            clicks.restore(counts[index * CLICK_BATCH_SIZE..].iter().cloned());
+            return Err(error::ErrorInternalServerError(error));
+        }
+    }
+
+    let pids: Vec<String> = counts.iter().map(|(pid, _)| pid.clone()).collect();
+    let options = FindOptions::builder()
+        .projection(doc! { "_id": 0, "pid": 1, "clicks": 1 })
+        .build();
+    let popularity: Vec<Document> = database
+        .collection::<Document>("products")
+        .find(doc! { "pid": { "$in": pids } }, options)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    ms_client
+        .index("products")
+        .add_or_update(&popularity, Some("pid"))
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(counts.len())
+}
+
+/// Recompute the popularity score of the products.
+///
+/// This function sums the clicks and purchases of every product from its daily rollups within the popularity window, each day weighing half as much as the day one half-life later, then stores the scores that changed and refreshes only the popularity of the matching search documents with a single partial update. Products whose events all left the window drop back to a score of 0.
+///
+/// # Parameters
+///
+/// - `settings`: The half-life and window of the popularity score.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the number of products whose score changed, or an `Error` in case of a database or indexing error.
+pub async fn refresh_popularity(
+    settings: &PopularitySettings,
+    client: &Client,
+    ms_client: &meilisearch_sdk::Client,
+) -> Result<usize, Error> {
+    let database = client.database("ecommerce_db");
+    let now = DateTime::now().timestamp_millis();
+    let day = Granularity::Day.millis();
+    let half_life = settings.half_life * day as f64;
+    let weight = doc! {
+        "$add": [
+            { "$multiply": [{ "$ifNull": ["$counts.click", 0] }, CLICK_WEIGHT] },
+            { "$multiply": [{ "$ifNull": ["$counts.purchase", 0] }, PURCHASE_WEIGHT] },
+        ]
+    };
+    let decay =
+        doc! { "$pow": [0.5, { "$divide": [{ "$subtract": [now, "$start"] }, half_life] }] };
+
+    let pipeline = vec![
+        doc! {
+            "$match": {
+                "granularity": Granularity::Day.as_str(),
+                "start": { "$gte": Granularity::Day.bucket(now) - (settings.window - 1) * day },
+            }
+        },
+        doc! {
+            "$group": {
+                "_id": "$pid",
+                "popularity": { "$sum": { "$multiply": [weight, decay] } },
+            }
+        },
+    ];
+
+    let scores: HashMap<String, f32> = database
+        .collection::<Rollup>("analytics_rollups")
+        .aggregate(pipeline, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .map_err(error::ErrorInternalServerError)
+        .try_filter_map(|group| async move {
+            Ok(group.get_str("_id").ok().map(|pid| {
+                let popularity = group.get_f64("popularity").unwrap_or(0.0);
+                // Rounding avoids rewriting products whose score barely moved.
+                (
+                    pid.to_string(),
+                    ((popularity * 1000.0).round() / 1000.0) as f32,
+                )
+            }))
+        })
+        .try_collect()
+        .await?;
+
+    let pids: Vec<String> = scores.keys().cloned().collect();
+    let options = FindOptions::builder()
+        .projection(doc! { "pid": 1, "popularity": 1 })
+        .build();
+    let stored: Vec<StoredPopularity> = database
+        .collection::<StoredPopularity>("products")
+        .find(
+            doc! { "$or": [{ "popularity": { "$gt": 0 } }, { "pid": { "$in": pids } }] },
+            options,
+        )
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    let changes: Vec<(String, f32)> = stored
+        .into_iter()
+        .filter_map(|product| {
+            let popularity = scores.get(&product.pid).copied().unwrap_or(0.0);
+            (popularity != product.popularity).then_some((product.pid, popularity))
+        })
+        .collect();
+
+    if changes.is_empty() {
+        return Ok(0);
+    }
+
+    for batch in changes.chunks(POPULARITY_BATCH_SIZE) {
+        let updates: Vec<Document> = batch
+            .iter()
+            .map(|(pid, popularity)| {
+                doc! { "q": { "pid": pid.clone() }, "u": { "$set": { "popularity": *popularity } } }
+            })
+            .collect();
+
+        database
+            .run_command(doc! { "update": "products", "updates": updates }, None)
             .await
-            .unwrap();
+            .map_err(error::ErrorInternalServerError)?;
+    }
+
+    let documents: Vec<Document> = changes
+        .iter()
+        .map(|(pid, popularity)| doc! { "pid": pid.clone(), "popularity": *popularity })
+        .collect();
+
+    ms_client
+        .index("products")
+        .add_or_update(&documents, Some("pid"))
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(changes.len())
+}
+
+/// Validate an analytics time range, defaulting to the last `DEFAULT_RANGE` milliseconds.
+fn time_range(from: Option<i64>, to: Option<i64>) -> Result<(i64, i64), Error> {
+    let to = to.unwrap_or_else(|| DateTime::now().timestamp_millis());
+    let from = match from {
+        Some(from) => from,
+        None => to.checked_sub(DEFAULT_RANGE).ok_or_else(out_of_range)?,
+    };
+
+    if from >= to {
+        return Err(error::ErrorBadRequest(
+            "The start of the time range must be before its end",
+        ));
+    }
+
+    Ok((from, to))
+}
+
+/// The start of the first bucket of a time range and its number of buckets, which is at most `MAX_BUCKETS`.
+fn series_range(granularity: Granularity, from: i64, to: i64) -> Result<(i64, i64), Error> {
+    let millis = granularity.millis();
+    // The range is given by the client, so the start of its first bucket and its length may not fit.
+    let first = from
+        .checked_sub(from.rem_euclid(millis))
+        .ok_or_else(out_of_range)?;
+    let buckets = (to - 1).checked_sub(first).ok_or_else(out_of_range)? / millis + 1;
+
+    if buckets > MAX_BUCKETS {
+        return Err(error::ErrorBadRequest(format!(
+            "The time range must not span more than {} buckets",
+            MAX_BUCKETS
+        )));
+    }
+
+    Ok((first, buckets))
+}
+
+/// The error of a time range too far from the Unix epoch to compute with.
+fn out_of_range() -> Error {
+    error::ErrorBadRequest("The time range is out of bounds")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test;
+
+    const DAY: i64 = 86_400_000;
+
+    #[test]
+    fn series_start_at_the_bucket_of_the_range_start() {
+        let (first, buckets) = series_range(Granularity::Day, DAY + 5, 3 * DAY).unwrap();
+        assert_eq!((first, buckets), (DAY, 2));
+
+        let (first, buckets) = series_range(Granularity::Day, -5, DAY + 1).unwrap();
+        assert_eq!((first, buckets), (-DAY, 3));
+    }
+
+    #[test]
+    fn series_of_too_many_buckets_are_rejected() {
+        let to = MAX_BUCKETS * DAY;
+        assert!(series_range(Granularity::Day, 0, to).is_ok());
+        assert!(series_range(Granularity::Day, 0, to + 1).is_err());
+        assert!(series_range(Granularity::Hour, 0, i64::MAX).is_err());
+    }
+
+    #[test]
+    fn ranges_too_far_from_the_epoch_are_rejected() {
+        assert!(series_range(Granularity::Day, i64::MIN, 0).is_err());
+        assert!(series_range(Granularity::Hour, i64::MIN + 1, i64::MAX).is_err());
+        assert!(time_range(None, Some(i64::MIN + 1)).is_err());
+    }
 
-        Ok(true)
-    } else {
-        Ok(false)
+    #[test]
+    fn empty_ranges_are_rejected() {
+        assert!(time_range(Some(10), Some(10)).is_err());
+        assert!(time_range(Some(11), Some(10)).is_err());
+        assert_eq!(
+            time_range(None, Some(DEFAULT_RANGE)).unwrap(),
+            (0, DEFAULT_RANGE)
+        );
     }
 }
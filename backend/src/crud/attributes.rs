@@ -0,0 +1,150 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::structures::{AttributeSchema, AttributeValue};
+
+use actix_web::{error, Error};
+use futures::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::options::ReplaceOptions;
+use mongodb::Client;
+use std::collections::BTreeMap;
+
+/// Retrieve the attribute schema of a product category.
+///
+/// # Parameters
+///
+/// - `category`: The product category to retrieve the schema for.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing an `Option<AttributeSchema>`, where `None` indicates that the category has no schema. An `Error` is returned in case of a database query error.
+pub async fn retrieve_schema(
+    category: String,
+    client: Client,
+) -> Result<Option<AttributeSchema>, Error> {
+    client
+        .database("ecommerce_db")
+        .collection::<AttributeSchema>("attribute_schemas")
+        .find_one(doc! { "category": category }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)
+}
+
+/// Create or replace the attribute schema of a product category.
+///
+/// This function stores the schema and refreshes the filterable attributes of the search index, so that newly filterable attributes can be used in search filters and facets.
+///
+/// # Parameters
+///
+/// - `schema`: The schema to be stored.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the schema is successfully stored, `false` if the schema defines an attribute twice, and an `Error` in case of a database or indexing error.
+pub async fn save_schema(
+    schema: AttributeSchema,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    let mut names: Vec<&String> = schema
+        .attributes
+        .iter()
+        .map(|attribute| &attribute.name)
+        .collect();
+    names.sort();
+    names.dedup();
+
+    if schema.category.is_empty() || names.len() != schema.attributes.len() {
+        return Ok(false);
+    }
+
+    let options = ReplaceOptions::builder().upsert(true).build();
+
+    client
+        .database("ecommerce_db")
+        .collection::<AttributeSchema>("attribute_schemas")
+        .replace_one(
+            doc! { "category": schema.category.clone() },
+            schema,
+            options,
+        )
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    ms_client
+        .index("products")
+        .set_filterable_attributes(&filterable_attributes(&client).await?)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(true)
+}
+
+/// Validate product attributes against the schema of their category.
+///
+/// Products of a category without a schema can have any attributes.
+///
+/// # Parameters
+///
+/// - `category`: The product category.
+/// - `attributes`: The product attributes to be validated.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns `Ok(())` if the attributes are valid, a bad request `Error` describing the violation if they are not, and an `Error` in case of a database query error.
+pub async fn validate_attributes(
+    category: &str,
+    attributes: &BTreeMap<String, AttributeValue>,
+    client: &Client,
+) -> Result<(), Error> {
+    match retrieve_schema(category.to_string(), client.clone()).await? {
+        Some(schema) => schema.validate(attributes).map_err(error::ErrorBadRequest),
+        None => Ok(()),
+    }
+}
+
+/// Collect the filterable search attributes.
+///
+/// This function combines the product ID, category, price and status with every attribute marked as filterable in any schema, prefixed with `attributes.` as they are nested in the search documents.
+///
+/// # Parameters
+///
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the filterable attribute names, or an `Error` in case of a database query error.
+pub async fn filterable_attributes(client: &Client) -> Result<Vec<String>, Error> {
+    let schemas: Vec<AttributeSchema> = client
+        .database("ecommerce_db")
+        .collection::<AttributeSchema>("attribute_schemas")
+        .find(None, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    let mut attributes: Vec<String> = schemas
+        .iter()
+        .flat_map(|schema| schema.attributes.iter())
+        .filter(|attribute| attribute.filterable)
+        .map(|attribute| format!("attributes.{}", attribute.name))
+        .collect();
+
+    attributes.push("category".to_string());
+    attributes.push("pid".to_string());
+    attributes.push("price".to_string());
+    attributes.push("status".to_string());
+    attributes.sort();
+    attributes.dedup();
+
+    Ok(attributes)
+}
@@ -0,0 +1,218 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::routes::audit::{Actor, AuditQuery, HistoryQuery};
+use crate::structures::{AuditEntry, Change, Product};
+
+use actix_web::{error, Error};
+use futures::TryStreamExt;
+use mongodb::bson::{self, doc, Bson, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::Client;
+use std::collections::BTreeMap;
+
+/// The maximum number of audit entries per page.
+const PAGE_SIZE: i64 = 20;
+
+/// Fields that change on every modification and are left out of the recorded changes.
+const IGNORED_FIELDS: [&str; 3] = ["_id", "updated_at", "version"];
+
+/// Record a product modification in the audit log.
+///
+/// This function appends an entry with the fields that differ between the product before and after the modification. Modifications that do not change any field are not recorded.
+///
+/// # Parameters
+///
+/// - `actor`: The actor who modified the product.
+/// - `before`: The product before the modification, or `None` if it was created.
+/// - `after`: The product after the modification, or `None` if it was deleted.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns `Ok(())` if the entry is recorded, or an `Error` in case of a database error.
+pub async fn record(
+    actor: &Actor,
+    before: Option<&Product>,
+    after: Option<&Product>,
+    client: &Client,
+) -> Result<(), Error> {
+    let entry = match entry(actor, before, after)? {
+        Some(entry) => entry,
+        None => return Ok(()),
+    };
+
+    client
+        .database("ecommerce_db")
+        .collection::<AuditEntry>("audit_log")
+        .insert_one(entry, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(())
+}
+
+/// Record the modifications of many products in the audit log at once.
+///
+/// # Parameters
+///
+/// - `actor`: The actor who modified the products.
+/// - `changes`: The products before and after their modification.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns `Ok(())` if the entries are recorded, or an `Error` in case of a database error.
+pub async fn record_many(
+    actor: &Actor,
+    changes: &[(Product, Product)],
+    client: &Client,
+) -> Result<(), Error> {
+    let mut entries: Vec<AuditEntry> = Vec::new();
+
+    for (before, after) in changes {
+        entries.extend(entry(actor, Some(before), Some(after))?);
+    }
+
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    client
+        .database("ecommerce_db")
+        .collection::<AuditEntry>("audit_log")
+        .insert_many(entries, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(())
+}
+
+/// Build the audit entry of a product modification, or `None` if no field changed.
+fn entry(
+    actor: &Actor,
+    before: Option<&Product>,
+    after: Option<&Product>,
+) -> Result<Option<AuditEntry>, Error> {
+    let pid = match after.or(before) {
+        Some(product) => product.pid.clone(),
+        None => return Ok(None),
+    };
+
+    let before = to_document(before)?;
+    let after = to_document(after)?;
+    let mut changes: BTreeMap<String, Change> = BTreeMap::new();
+
+    for key in before.keys().chain(after.keys()) {
+        if IGNORED_FIELDS.contains(&key.as_str()) || changes.contains_key(key) {
+            continue;
+        }
+
+        let change = Change {
+            before: before.get(key).cloned().unwrap_or(Bson::Null),
+            after: after.get(key).cloned().unwrap_or(Bson::Null),
+        };
+
+        if change.before != change.after {
+            changes.insert(key.clone(), change);
+        }
+    }
+
+    if changes.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(AuditEntry {
+        pid,
+        actor: actor.id.clone(),
+        endpoint: actor.endpoint.clone(),
+        timestamp: DateTime::now().timestamp_millis(),
+        changes,
+    }))
+}
+
+/// Retrieve the modification history of a product.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID to retrieve the history for.
+/// - `query`: Query parameters containing the page number.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the audit entries of the page, newest first, or an `Error` in case of a database query error.
+pub async fn retrieve_history(
+    pid: String,
+    query: HistoryQuery,
+    client: Client,
+) -> Result<Vec<AuditEntry>, Error> {
+    retrieve_page(doc! { "pid": pid }, query.page, client).await
+}
+
+/// Retrieve audit entries by actor and time range.
+///
+/// # Parameters
+///
+/// - `query`: Query parameters containing the optional actor, the optional time range and the page number.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the audit entries of the page, newest first, or an `Error` in case of a database query error.
+pub async fn retrieve_entries(query: AuditQuery, client: Client) -> Result<Vec<AuditEntry>, Error> {
+    let mut filter = Document::new();
+
+    if let Some(actor) = query.actor {
+        filter.insert("actor", actor);
+    }
+
+    let mut timestamp = Document::new();
+
+    if let Some(from) = query.from {
+        timestamp.insert("$gte", from);
+    }
+
+    if let Some(to) = query.to {
+        timestamp.insert("$lt", to);
+    }
+
+    if !timestamp.is_empty() {
+        filter.insert("timestamp", timestamp);
+    }
+
+    retrieve_page(filter, query.page, client).await
+}
+
+/// Retrieve a page of audit entries matching a filter, newest first.
+async fn retrieve_page(
+    filter: Document,
+    page: u32,
+    client: Client,
+) -> Result<Vec<AuditEntry>, Error> {
+    let options = FindOptions::builder()
+        .sort(doc! { "timestamp": -1 })
+        .skip(page.saturating_sub(1) as u64 * PAGE_SIZE as u64)
+        .limit(PAGE_SIZE)
+        .build();
+
+    client
+        .database("ecommerce_db")
+        .collection::<AuditEntry>("audit_log")
+        .find(filter, options)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)
+}
+
+/// Convert a product to a document, or an empty document if there is no product.
+fn to_document(product: Option<&Product>) -> Result<Document, Error> {
+    match product {
+        Some(product) => bson::to_document(product).map_err(error::ErrorInternalServerError),
+        None => Ok(Document::new()),
+    }
+}
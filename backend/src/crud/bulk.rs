@@ -0,0 +1,364 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::{audit, product, webhooks};
+use crate::routes::audit::Actor;
+use crate::routes::bulk::{
+    BulkAction, BulkOperation, BulkReport, BulkUpdate, ItemReport, ItemStatus,
+};
+use crate::structures::{Product, ProductStatus};
+
+use actix_web::{error, Error};
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOptions;
+use mongodb::Client;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+/// The maximum number of products a bulk operation can modify.
+const MAX_BULK_SIZE: usize = 1000;
+
+/// The product ID, version and modification time of a stored product.
+#[derive(Deserialize)]
+struct StoredVersion {
+    pid: String,
+
+    #[serde(default)]
+    version: u32,
+
+    #[serde(default)]
+    updated_at: i64,
+}
+
+/// Apply partial updates to many products.
+///
+/// This function applies each update to its product in memory, then writes all modified products with a single bulk update and refreshes their search documents at once. Updates are applied independently, so a rejected update does not affect the others.
+///
+/// # Parameters
+///
+/// - `updates`: The partial updates, at most one per product.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the report for every product, a bad request `Error` if there are too many updates, or an `Error` in case of a database or indexing error.
+pub async fn update_products(
+    updates: Vec<BulkUpdate>,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<BulkReport, Error> {
+    if updates.len() > MAX_BULK_SIZE {
+        return Err(error::ErrorBadRequest(format!(
+            "At most {} products can be updated at once",
+            MAX_BULK_SIZE
+        )));
+    }
+
+    let pids: Vec<String> = updates.iter().map(|update| update.pid.clone()).collect();
+    let mut products: HashMap<String, Product> =
+        find_products(doc! { "pid": { "$in": pids } }, &client)
+            .await?
+            .into_iter()
+            .map(|product| (product.pid.clone(), product))
+            .collect();
+
+    let mut report = BulkReport::default();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut changes: Vec<(Product, Product)> = Vec::new();
+
+    for update in updates {
+        if !seen.insert(update.pid.clone()) {
+            fail(&mut report, update.pid, "Product is listed more than once");
+            continue;
+        }
+
+        let before = match products.remove(&update.pid) {
+            Some(product) => product,
+            None => {
+                fail(&mut report, update.pid, "Product does not exist");
+                continue;
+            }
+        };
+
+        match apply_update(before.clone(), update) {
+            Ok(after) => changes.push((before, after)),
+            Err(reason) => fail(&mut report, before.pid, &reason),
+        }
+    }
+
+    write_products(changes, &mut report, &actor, &client, &ms_client).await?;
+
+    Ok(report)
+}
+
+/// Apply an operation to every product matching a filter.
+///
+/// This function applies the operation to the matching products in memory, then writes all modified products with a single bulk update and refreshes their search documents at once. Products the operation does not apply to, such as archived products when delisting, are reported as failed.
+///
+/// # Parameters
+///
+/// - `operation`: The filter and the operation to apply.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the report for every matching product, a bad request `Error` if the filter is empty or matches too many products, or an `Error` in case of a database or indexing error.
+pub async fn apply_operation(
+    operation: BulkOperation,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<BulkReport, Error> {
+    let mut filter = Document::new();
+
+    if let Some(sid) = operation.filter.sid {
+        filter.insert("sid", sid);
+    }
+
+    if let Some(category) = operation.filter.category {
+        filter.insert("category", category);
+    }
+
+    if let Some(status) = operation.filter.status {
+        filter.insert("status", status.filter());
+    }
+
+    if let Some(pids) = operation.filter.pids {
+        filter.insert("pid", doc! { "$in": pids });
+    }
+
+    if filter.is_empty() {
+        return Err(error::ErrorBadRequest("The filter must not be empty"));
+    }
+
+    let products = find_products(filter, &client).await?;
+
+    if products.len() > MAX_BULK_SIZE {
+        return Err(error::ErrorBadRequest(format!(
+            "The filter matches more than {} products",
+            MAX_BULK_SIZE
+        )));
+    }
+
+    let mut report = BulkReport::default();
+    let mut changes: Vec<(Product, Product)> = Vec::new();
+
+    for before in products {
+        match apply_action(before.clone(), &operation.action) {
+            Ok(after) => changes.push((before, after)),
+            Err(reason) => fail(&mut report, before.pid, &reason),
+        }
+    }
+
+    write_products(changes, &mut report, &actor, &client, &ms_client).await?;
+
+    Ok(report)
+}
+
+/// Apply a partial update to a product.
+fn apply_update(mut product: Product, update: BulkUpdate) -> Result<Product, String> {
+    if let Some(price) = update.price {
+        if !price.is_finite() || price < 0.0 {
+            return Err("Price must not be negative".to_string());
+        }
+
+        product.set_regular_price(price);
+    }
+
+    if let Some(stock) = update.stock {
+        if product.variants.is_empty() {
+            product.stock = stock;
+        }
+    }
+
+    if let Some(image) = update.image {
+        if product.images.is_empty() {
+            product.image = image;
+        } else if !product.set_primary_image(Some(image.as_str())) {
+            return Err("Image is not part of the gallery".to_string());
+        }
+    }
+
+    if let Some(name) = update.name {
+        product.name = name;
+    }
+
+    if let Some(description) = update.description {
+        product.description = description;
+    }
+
+    Ok(product)
+}
+
+/// Apply a bulk operation to a product.
+fn apply_action(mut product: Product, action: &BulkAction) -> Result<Product, String> {
+    match action {
+        BulkAction::AdjustPrice { percent } => {
+            let price = (product.regular_price() * (1.0 + percent / 100.0) * 100.0).round() / 100.0;
+
+            if !price.is_finite() || price < 0.0 {
+                return Err("Price must not be negative".to_string());
+            }
+
+            product.set_regular_price(price);
+        }
+        BulkAction::SetPrice { price } => {
+            if !price.is_finite() || *price < 0.0 {
+                return Err("Price must not be negative".to_string());
+            }
+
+            product.set_regular_price(*price);
+        }
+        BulkAction::SetStock { stock } => {
+            if !product.variants.is_empty() {
+                return Err("Stock of a product with variants is set per variant".to_string());
+            }
+
+            product.stock = *stock;
+        }
+        BulkAction::Delist => {
+            if product.status == ProductStatus::Archived {
+                return Err("Product is already archived".to_string());
+            }
+
+            product.status = ProductStatus::Archived;
+        }
+        BulkAction::Restore => {
+            if product.status != ProductStatus::Archived {
+                return Err("Product is not archived".to_string());
+            }
+
+            product.status = ProductStatus::Active;
+        }
+    }
+
+    Ok(product)
+}
+
+/// Store modified products and refresh their search documents.
+///
+/// The products are written with a single unordered update command, each replacing a product only if it is still at the version it was read at. The analytics counters are left out of the write, as for single product modifications. When not every product matched, the stored versions are read back to find out which products were written. Only those are recorded in the audit log and emit their webhook events, each with a single insert, and are indexed in a single batch.
+async fn write_products(
+    changes: Vec<(Product, Product)>,
+    report: &mut BulkReport,
+    actor: &Actor,
+    client: &Client,
+    ms_client: &meilisearch_sdk::Client,
+) -> Result<(), Error> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let database = client.database("ecommerce_db");
+    let mut written: Vec<(Product, Product)> = Vec::new();
+    let mut updates: Vec<Document> = Vec::new();
+
+    for (before, mut after) in changes {
+        let (filter, update) = product::versioned_write(&mut after)?;
+
+        updates.push(doc! { "q": filter, "u": update });
+        written.push((before, after));
+    }
+
+    let result = database
+        .run_command(
+            doc! { "update": "products", "updates": updates, "ordered": false },
+            None,
+        )
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let matched = result
+        .get("n")
+        .and_then(|n| n.as_i32().map(i64::from).or_else(|| n.as_i64()))
+        .unwrap_or(0);
+
+    if matched < written.len() as i64 {
+        let pids: Vec<String> = written.iter().map(|(_, after)| after.pid.clone()).collect();
+        let options = FindOptions::builder()
+            .projection(doc! { "pid": 1, "version": 1, "updated_at": 1 })
+            .build();
+        let stored: HashMap<String, StoredVersion> = database
+            .collection::<StoredVersion>("products")
+            .find(doc! { "pid": { "$in": pids } }, options)
+            .await
+            .map_err(error::ErrorInternalServerError)?
+            .map_ok(|stored| (stored.pid.clone(), stored))
+            .try_collect()
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+
+        // A product modified concurrently kept its version, or was given the same version at another time.
+        written.retain(|(_, after)| {
+            let is_written = stored.get(&after.pid).is_some_and(|stored| {
+                stored.version == after.version && stored.updated_at == after.updated_at
+            });
+
+            if !is_written {
+                fail(
+                    report,
+                    after.pid.clone(),
+                    "Product was modified concurrently",
+                );
+            }
+
+            is_written
+        });
+    }
+
+    for (_, after) in written.iter() {
+        report.updated += 1;
+        report.items.push(ItemReport {
+            pid: after.pid.clone(),
+            status: ItemStatus::Updated,
+            error: None,
+        });
+    }
+
+    if written.is_empty() {
+        return Ok(());
+    }
+
+    audit::record_many(actor, &written, client).await?;
+    webhooks::emit_changes_many(&written, client).await;
+
+    let indexed: Vec<&Product> = written.iter().map(|(_, after)| after).collect();
+
+    ms_client
+        .index("products")
+        .add_or_replace(&indexed, Some("pid"))
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(())
+}
+
+/// Retrieve the products matching a filter.
+async fn find_products(filter: Document, client: &Client) -> Result<Vec<Product>, Error> {
+    client
+        .database("ecommerce_db")
+        .collection::<Product>("products")
+        .find(filter, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)
+}
+
+/// Report a product as not updated.
+fn fail(report: &mut BulkReport, pid: String, reason: &str) {
+    report.failed += 1;
+    report.items.push(ItemReport {
+        pid,
+        status: ItemStatus::Failed,
+        error: Some(reason.to_string()),
+    });
+}
@@ -0,0 +1,449 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::routes::export::{ExportFormat, ExportQuery};
+use crate::structures::{AttributeValue, Product, ProductStatus};
+
+use actix_web::web::Bytes;
+use actix_web::{error, Error};
+use futures::stream::{self, LocalBoxStream, StreamExt, TryStreamExt};
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::Client;
+use std::env;
+
+/// The maximum number of additional images in a product feed item.
+const MAX_ADDITIONAL_IMAGES: usize = 10;
+
+/// Export the catalog as a stream of encoded chunks.
+///
+/// This function reads the matching products from a database cursor and encodes each product as soon as it is read, so the whole catalog is never held in memory. CSV exports first collect the attribute names of the matching products to build the header row.
+///
+/// # Parameters
+///
+/// - `query`: Query parameters containing the export format and the optional filters.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the stream of encoded chunks, or an `Error` in case of a database query error. Errors while streaming end the stream.
+pub async fn export_products(
+    query: ExportQuery,
+    client: Client,
+) -> Result<LocalBoxStream<'static, Result<Bytes, Error>>, Error> {
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let filter = export_filter(&query);
+    let options = FindOptions::builder().sort(doc! { "pid": 1 }).build();
+
+    let products = collection
+        .find(filter.clone(), options)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .map_err(error::ErrorInternalServerError)
+        .map_ok(|mut product| {
+            product.aggregate();
+            product
+        });
+
+    let stream =
+        match query.format {
+            ExportFormat::Csv => {
+                let names = attribute_names(filter, &client).await?;
+                let header = csv_header(&names)?;
+
+                stream::once(async { Ok(header) })
+                    .chain(products.map(move |product| {
+                        product.and_then(|product| csv_product(&product, &names))
+                    }))
+                    .boxed_local()
+            }
+            ExportFormat::Ndjson => products
+                .map(|product| product.and_then(|product| ndjson_product(&product)))
+                .boxed_local(),
+            ExportFormat::Merchant => {
+                let feed = Feed::from_env();
+                let header = feed.header();
+
+                stream::once(async { Ok(header) })
+                    .chain(products.map_ok(move |product| feed.item(&product)))
+                    .chain(stream::once(async { Ok(Feed::footer()) }))
+                    .boxed_local()
+            }
+        };
+
+    Ok(stream)
+}
+
+/// Build the database filter of an export.
+fn export_filter(query: &ExportQuery) -> Document {
+    let mut filter = Document::new();
+
+    if let Some(sid) = &query.sid {
+        filter.insert("sid", sid.clone());
+    }
+
+    if let Some(category) = &query.category {
+        filter.insert("category", category.clone());
+    }
+
+    // Feeds list only the products shoppers can buy unless another status is asked for, while backups hold every product.
+    let status = match query.format {
+        ExportFormat::Merchant => query.status.or(Some(ProductStatus::Active)),
+        _ => query.status,
+    };
+
+    if let Some(status) = status {
+        filter.insert("status", status.filter());
+    }
+
+    filter
+}
+
+/// Collect the sorted attribute names used by the products matching a filter.
+async fn attribute_names(filter: Document, client: &Client) -> Result<Vec<String>, Error> {
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$project": { "attributes": { "$objectToArray": { "$ifNull": ["$attributes", {}] } } } },
+        doc! { "$unwind": "$attributes" },
+        doc! { "$group": { "_id": "$attributes.k" } },
+        doc! { "$sort": { "_id": 1 } },
+    ];
+
+    let groups: Vec<Document> = client
+        .database("ecommerce_db")
+        .collection::<Product>("products")
+        .aggregate(pipeline, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(groups
+        .iter()
+        .filter_map(|group| group.get_str("_id").ok())
+        .map(|name| name.to_string())
+        .collect())
+}
+
+/// Encode the CSV header row, with a column per attribute name.
+fn csv_header(names: &[String]) -> Result<Bytes, Error> {
+    let mut fields: Vec<String> = [
+        "pid",
+        "sid",
+        "name",
+        "description",
+        "image",
+        "category",
+        "price",
+        "stock",
+        "draft",
+        "status",
+    ]
+    .iter()
+    .map(|field| field.to_string())
+    .collect();
+
+    fields.extend(names.iter().map(|name| format!("attributes.{}", name)));
+
+    csv_record(fields)
+}
+
+/// Encode a product as a CSV row, using its regular price so that the export can be imported again.
+fn csv_product(product: &Product, names: &[String]) -> Result<Bytes, Error> {
+    let mut fields = vec![
+        product.pid.clone(),
+        product.sid.clone(),
+        product.name.clone(),
+        product.description.clone(),
+        product.image.clone(),
+        product.category.clone(),
+        product.regular_price().to_string(),
+        product.stock.to_string(),
+        (product.status == ProductStatus::Draft).to_string(),
+        product.status.as_str().to_string(),
+    ];
+
+    fields.extend(names.iter().map(|name| match product.attributes.get(name) {
+        Some(AttributeValue::Boolean(value)) => value.to_string(),
+        Some(AttributeValue::Number(value)) => value.to_string(),
+        Some(AttributeValue::Text(value)) => value.clone(),
+        None => String::new(),
+    }));
+
+    csv_record(fields)
+}
+
+/// Encode a product as an NDJSON line.
+fn ndjson_product(product: &Product) -> Result<Bytes, Error> {
+    let mut line = serde_json::to_vec(product).map_err(error::ErrorInternalServerError)?;
+    line.push(b'\n');
+
+    Ok(Bytes::from(line))
+}
+
+/// Encode a CSV record.
+fn csv_record(fields: Vec<String>) -> Result<Bytes, Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    writer
+        .write_record(&fields)
+        .map_err(error::ErrorInternalServerError)?;
+
+    writer
+        .into_inner()
+        .map(Bytes::from)
+        .map_err(|error| error::ErrorInternalServerError(error.to_string()))
+}
+
+/// The settings of a Google Merchant Center product feed.
+struct Feed {
+    /// The storefront URL, used to build absolute product and image links.
+    store_url: String,
+
+    /// The ISO 4217 currency code of the prices.
+    currency: String,
+}
+
+impl Feed {
+    /// Read the feed settings from the `STORE_URL` and `FEED_CURRENCY` environment variables.
+    fn from_env() -> Self {
+        Feed {
+            store_url: env::var("STORE_URL")
+                .unwrap_or_else(|_| "http://localhost:5173".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            currency: env::var("FEED_CURRENCY").unwrap_or_else(|_| "USD".to_string()),
+        }
+    }
+
+    /// The opening of the feed document.
+    fn header(&self) -> Bytes {
+        Bytes::from(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <rss version=\"2.0\" xmlns:g=\"http://base.google.com/ns/1.0\">\n\
+             <channel>\n\
+             <title>Products</title>\n\
+             <link>{}</link>\n\
+             <description>Product catalog</description>\n",
+            escape(&self.store_url)
+        ))
+    }
+
+    /// The closing of the feed document.
+    fn footer() -> Bytes {
+        Bytes::from_static(b"</channel>\n</rss>\n")
+    }
+
+    /// Encode a product as a feed item.
+    fn item(&self, product: &Product) -> Bytes {
+        let mut item = String::from("<item>\n");
+        let mut field = |name: &str, value: &str| {
+            item.push_str(&format!("<g:{0}>{1}</g:{0}>\n", name, escape(value)));
+        };
+
+        field("id", &product.pid);
+        field("title", &product.name);
+        field("description", &product.description);
+        field(
+            "link",
+            &format!(
+                "{}/detailed-view.html?product={}",
+                self.store_url, product.pid
+            ),
+        );
+        field("image_link", &self.absolute(&product.image));
+
+        for image in product
+            .images
+            .iter()
+            .filter(|image| image.url != product.image)
+            .take(MAX_ADDITIONAL_IMAGES)
+        {
+            field("additional_image_link", &self.absolute(&image.url));
+        }
+
+        let available = product.status == ProductStatus::Active && product.stock > 0;
+        field(
+            "availability",
+            if available {
+                "in_stock"
+            } else {
+                "out_of_stock"
+            },
+        );
+        field("condition", "new");
+        field("product_type", &product.category);
+        field("price", &self.price(product.regular_price()));
+
+        if let Some(sale) = &product.sale {
+            field("sale_price", &self.price(sale.price));
+            field(
+                "sale_price_effective_date",
+                &format!("{}/{}", timestamp(sale.start), timestamp(sale.end)),
+            );
+        }
+
+        if let Some(AttributeValue::Text(brand)) = product.attributes.get("brand") {
+            field("brand", brand);
+        }
+
+        item.push_str("</item>\n");
+
+        Bytes::from(item)
+    }
+
+    /// Format a price with the feed currency.
+    fn price(&self, price: f32) -> String {
+        format!("{:.2} {}", price, self.currency)
+    }
+
+    /// Resolve a URL relative to the storefront, such as an uploaded image.
+    fn absolute(&self, url: &str) -> String {
+        if url.starts_with('/') {
+            format!("{}{}", self.store_url, url)
+        } else {
+            url.to_string()
+        }
+    }
+}
+
+/// Format a time in milliseconds since the Unix epoch as an ISO 8601 timestamp.
+fn timestamp(millis: i64) -> String {
+    DateTime::from_millis(millis)
+        .try_to_rfc3339_string()
+        .unwrap_or_default()
+}
+
+/// Escape text for use in XML content.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::Sale;
+    use crate::testing::test;
+
+    use mongodb::bson::Bson;
+    use std::collections::BTreeMap;
+
+    fn query(format: ExportFormat, status: Option<ProductStatus>) -> ExportQuery {
+        ExportQuery {
+            format,
+            sid: Some("S1".to_string()),
+            category: None,
+            status,
+        }
+    }
+
+    fn feed() -> Feed {
+        Feed {
+            store_url: "https://shop.test".to_string(),
+            currency: "EUR".to_string(),
+        }
+    }
+
+    #[test]
+    fn xml_special_characters_are_escaped() {
+        assert_eq!(
+            escape(r#"Tom & Jerry's <"best">"#),
+            "Tom &amp; Jerry&apos;s &lt;&quot;best&quot;&gt;"
+        );
+        assert_eq!(escape("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        let record = csv_record(vec![
+            "plain".to_string(),
+            "a, b".to_string(),
+            "say \"hi\"".to_string(),
+            "two\nlines".to_string(),
+            String::new(),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            &record[..],
+            b"plain,\"a, b\",\"say \"\"hi\"\"\",\"two\nlines\",\n"
+        );
+    }
+
+    #[test]
+    fn csv_rows_use_the_regular_price_and_attribute_columns() {
+        let product = Product {
+            pid: "P1".to_string(),
+            price: 8.0,
+            compare_at: Some(10.0),
+            attributes: BTreeMap::from([
+                ("cotton".to_string(), AttributeValue::Boolean(true)),
+                ("weight".to_string(), AttributeValue::Number(1.5)),
+            ]),
+            ..Product::default()
+        };
+        let names = ["color", "cotton", "weight"].map(String::from);
+
+        let row = csv_product(&product, &names).unwrap();
+
+        assert_eq!(&row[..], b"P1,,,,,,10,0,false,active,,true,1.5\n");
+    }
+
+    #[test]
+    fn feed_items_escape_text_and_list_the_sale() {
+        let product = Product {
+            pid: "P1".to_string(),
+            name: "Salt & Pepper".to_string(),
+            image: "/uploads/a.png".to_string(),
+            price: 8.0,
+            compare_at: Some(10.0),
+            stock: 3,
+            sale: Some(Sale {
+                price: 8.0,
+                start: 0,
+                end: 1000,
+            }),
+            ..Product::default()
+        };
+
+        let item = feed().item(&product);
+        let item = std::str::from_utf8(&item).unwrap();
+
+        assert!(item.contains("<g:title>Salt &amp; Pepper</g:title>"));
+        assert!(item.contains("<g:image_link>https://shop.test/uploads/a.png</g:image_link>"));
+        assert!(item.contains("<g:availability>in_stock</g:availability>"));
+        assert!(item.contains("<g:price>10.00 EUR</g:price>"));
+        assert!(item.contains("<g:sale_price>8.00 EUR</g:sale_price>"));
+        assert!(item.contains(
+            "<g:sale_price_effective_date>1970-01-01T00:00:00Z/1970-01-01T00:00:01Z</g:sale_price_effective_date>"
+        ));
+    }
+
+    #[test]
+    fn merchant_feed_defaults_to_active_products() {
+        let filter = export_filter(&query(ExportFormat::Merchant, None));
+
+        assert_eq!(filter.get("sid"), Some(&Bson::from("S1")));
+        assert_eq!(filter.get("status"), Some(&ProductStatus::Active.filter()));
+
+        let filter = export_filter(&query(ExportFormat::Merchant, Some(ProductStatus::Draft)));
+
+        assert_eq!(filter.get("status"), Some(&Bson::from("draft")));
+    }
+
+    #[test]
+    fn backup_exports_include_every_status_by_default() {
+        for format in [ExportFormat::Csv, ExportFormat::Ndjson] {
+            assert!(!export_filter(&query(format, None)).contains_key("status"));
+        }
+    }
+}
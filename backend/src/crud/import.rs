@@ -0,0 +1,478 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::{attributes, audit, product, webhooks};
+use crate::routes::audit::Actor;
+use crate::routes::import::{ImportFormat, ImportReport, ImportRow, RowReport, RowStatus};
+use crate::structures::{AttributeSchema, AttributeValue, Product, ProductStatus, WebhookEvent};
+
+use csv::{ReaderBuilder, StringRecord, Trim};
+use log::error;
+use mongodb::bson::doc;
+use mongodb::Client;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// The maximum number of products sent to the search index at once.
+const INDEX_BATCH_SIZE: usize = 1000;
+
+/// Parse an import file into rows.
+///
+/// # Parameters
+///
+/// - `bytes`: The import file.
+/// - `format`: The import file format.
+///
+/// # Returns
+///
+/// - Returns the rows of the file paired with their line numbers, each containing either the parsed row or the reason it could not be parsed.
+pub fn parse_rows(bytes: &[u8], format: ImportFormat) -> Vec<(u64, Result<ImportRow, String>)> {
+    match format {
+        ImportFormat::Csv => parse_csv(bytes),
+        ImportFormat::Ndjson => parse_ndjson(bytes),
+    }
+}
+
+/// Import products in bulk.
+///
+/// This function validates every row, then creates the products that do not exist yet and updates the ones that do, matched by product ID. Rows are imported independently, so a rejected row does not affect the others. The search index is refreshed in batches once all rows are stored, and an indexing error is logged and reported without failing the import.
+///
+/// # Parameters
+///
+/// - `rows`: The parsed rows paired with their line numbers.
+/// - `dry_run`: Whether to only validate the rows without writing anything.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns the report of every row, along with whether the stored products were indexed.
+pub async fn import_products(
+    rows: Vec<(u64, Result<ImportRow, String>)>,
+    dry_run: bool,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> ImportReport {
+    let mut report = ImportReport {
+        dry_run,
+        indexed: true,
+        ..ImportReport::default()
+    };
+    let mut schemas: HashMap<String, Option<AttributeSchema>> = HashMap::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut imported: Vec<Product> = Vec::new();
+
+    for (line, row) in rows {
+        let pid = row.as_ref().map(|row| row.pid.clone()).unwrap_or_default();
+
+        let outcome = match row {
+            Ok(row) => import_row(row, dry_run, &mut seen, &mut schemas, &actor, &client).await,
+            Err(reason) => Err(reason),
+        };
+
+        let (status, error) = match outcome {
+            Ok((status, product)) => {
+                imported.extend(product);
+                (status, None)
+            }
+            Err(reason) => (RowStatus::Failed, Some(reason)),
+        };
+
+        match status {
+            RowStatus::Created => report.created += 1,
+            RowStatus::Updated => report.updated += 1,
+            RowStatus::Failed => report.failed += 1,
+        }
+
+        report.rows.push(RowReport {
+            line,
+            pid,
+            status,
+            error,
+        });
+    }
+
+    // The rows are already stored, so an indexing error must not hide their report.
+    for batch in imported.chunks(INDEX_BATCH_SIZE) {
+        if let Err(error) = ms_client
+            .index("products")
+            .add_or_replace(batch, Some("pid"))
+            .await
+        {
+            error!(
+                "Failed to index {} imported products: {}",
+                batch.len(),
+                error
+            );
+            report.indexed = false;
+        }
+    }
+
+    report
+}
+
+/// Validate a row and create or update its product.
+///
+/// # Returns
+///
+/// - Returns the outcome of the row along with the stored product, which is `None` in a dry run, or the reason the row was rejected.
+async fn import_row(
+    mut row: ImportRow,
+    dry_run: bool,
+    seen: &mut HashSet<String>,
+    schemas: &mut HashMap<String, Option<AttributeSchema>>,
+    actor: &Actor,
+    client: &Client,
+) -> Result<(RowStatus, Option<Product>), String> {
+    for (name, value) in [
+        ("pid", &row.pid),
+        ("sid", &row.sid),
+        ("name", &row.name),
+        ("category", &row.category),
+    ] {
+        if value.is_empty() {
+            return Err(format!("Missing `{}`", name));
+        }
+    }
+
+    if !row.price.is_finite() || row.price < 0.0 {
+        return Err("Price must not be negative".to_string());
+    }
+
+    if !seen.insert(row.pid.clone()) {
+        return Err("Duplicate product ID in the file".to_string());
+    }
+
+    if !schemas.contains_key(&row.category) {
+        let schema = attributes::retrieve_schema(row.category.clone(), client.clone())
+            .await
+            .map_err(|error| error.to_string())?;
+        schemas.insert(row.category.clone(), schema);
+    }
+
+    if let Some(Some(schema)) = schemas.get(&row.category) {
+        schema.coerce(&mut row.attributes);
+        schema.validate(&row.attributes)?;
+    }
+
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let existing = collection
+        .find_one(doc! { "pid": row.pid.clone() }, None)
+        .await
+        .map_err(|error| error.to_string())?;
+
+    match existing {
+        Some(product) if product.sid != row.sid => {
+            Err("Product belongs to another seller".to_string())
+        }
+        Some(mut product) => {
+            apply_stock_and_draft(&mut product, row.stock, row.draft)?;
+
+            if dry_run {
+                return Ok((RowStatus::Updated, None));
+            }
+
+            if product.images.is_empty() {
+                product.image = row.image;
+            } else {
+                product.set_primary_image(Some(row.image.as_str()));
+            }
+
+            product.name = row.name;
+            product.description = row.description;
+            product.category = row.category;
+            product.attributes = row.attributes;
+            product.set_regular_price(row.price);
+
+            let product = product::store_product(product, actor, client)
+                .await
+                .map_err(|error| error.to_string())?;
+
+            Ok((RowStatus::Updated, Some(product)))
+        }
+        None if dry_run => Ok((RowStatus::Created, None)),
+        None => {
+            let mut product = Product {
+                pid: row.pid,
+                sid: row.sid,
+                name: row.name,
+                description: row.description,
+                image: row.image,
+                category: row.category,
+                price: row.price,
+                stock: row.stock.unwrap_or_default(),
+                attributes: row.attributes,
+                status: if row.draft.unwrap_or_default() {
+                    ProductStatus::Draft
+                } else {
+                    ProductStatus::Active
+                },
+                ..Product::default()
+            };
+
+            product.aggregate();
+            product.touch();
+
+            collection
+                .insert_one(product.clone(), None)
+                .await
+                .map_err(|error| error.to_string())?;
+            audit::record(actor, None, Some(&product), client)
+                .await
+                .map_err(|error| error.to_string())?;
+            webhooks::emit(&[WebhookEvent::ProductCreated], &product, None, client).await;
+
+            Ok((RowStatus::Created, Some(product)))
+        }
+    }
+}
+
+/// Apply the stock and draft columns of a row to an existing product.
+///
+/// The stock of a product with variants is the total of its variants, so a row can only repeat it. A draft is published by a row that is not a draft, while listed products cannot become drafts again.
+///
+/// # Returns
+///
+/// - Returns the reason the row was rejected if it changes the stock of a product with variants or makes a listed product a draft.
+fn apply_stock_and_draft(
+    product: &mut Product,
+    stock: Option<u32>,
+    draft: Option<bool>,
+) -> Result<(), String> {
+    match stock {
+        Some(stock) if product.variants.is_empty() => product.stock = stock,
+        Some(stock) if stock != product.stock => {
+            return Err("Stock of a product with variants must be set on its variants".to_string())
+        }
+        _ => {}
+    }
+
+    match (draft, product.status) {
+        (Some(false), ProductStatus::Draft) => product.status = ProductStatus::Active,
+        (Some(true), ProductStatus::Draft) | (Some(false), _) | (None, _) => {}
+        (Some(true), _) => return Err("A listed product cannot be made a draft again".to_string()),
+    }
+
+    Ok(())
+}
+
+/// Parse a CSV import file with a header row.
+fn parse_csv(bytes: &[u8]) -> Vec<(u64, Result<ImportRow, String>)> {
+    let mut reader = ReaderBuilder::new().trim(Trim::All).from_reader(bytes);
+
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(error) => return vec![(1, Err(error.to_string()))],
+    };
+
+    reader
+        .records()
+        .map(|record| match record {
+            Ok(record) => (
+                record.position().map_or(0, |position| position.line()),
+                csv_row(&headers, &record),
+            ),
+            Err(error) => (
+                error.position().map_or(0, |position| position.line()),
+                Err(error.to_string()),
+            ),
+        })
+        .collect()
+}
+
+/// Read a row from a CSV record.
+///
+/// Attributes are read from the `attributes.<name>` columns as text, empty cells are left out.
+fn csv_row(headers: &StringRecord, record: &StringRecord) -> Result<ImportRow, String> {
+    let field = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .and_then(|index| record.get(index))
+            .unwrap_or("")
+    };
+
+    let attributes: BTreeMap<String, AttributeValue> = headers
+        .iter()
+        .zip(record.iter())
+        .filter(|(_, value)| !value.is_empty())
+        .filter_map(|(header, value)| {
+            let name = header.strip_prefix("attributes.")?;
+            Some((name.to_string(), AttributeValue::Text(value.to_string())))
+        })
+        .collect();
+
+    Ok(ImportRow {
+        pid: field("pid").to_string(),
+        sid: field("sid").to_string(),
+        name: field("name").to_string(),
+        description: field("description").to_string(),
+        image: field("image").to_string(),
+        category: field("category").to_string(),
+        price: field("price")
+            .parse()
+            .map_err(|_| "Invalid `price`".to_string())?,
+        stock: match field("stock") {
+            "" => None,
+            stock => Some(stock.parse().map_err(|_| "Invalid `stock`".to_string())?),
+        },
+        draft: match field("draft") {
+            "" => None,
+            draft => Some(draft.parse().map_err(|_| "Invalid `draft`".to_string())?),
+        },
+        attributes,
+    })
+}
+
+/// Parse an NDJSON import file, skipping blank lines.
+fn parse_ndjson(bytes: &[u8]) -> Vec<(u64, Result<ImportRow, String>)> {
+    bytes
+        .split(|byte| *byte == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.trim_ascii().is_empty())
+        .map(|(index, line)| {
+            (
+                index as u64 + 1,
+                serde_json::from_slice(line).map_err(|error| error.to_string()),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{product, test, variant};
+
+    #[test]
+    fn csv_rows_are_read_by_header() {
+        let rows = parse_csv(
+            b"name,pid,sid,category,price,stock,attributes.color,attributes.size\n\
+              Shirt,P1,S1,clothes,9.5,,red,\n",
+        );
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].0, 2);
+
+        let row = rows[0].1.as_ref().unwrap();
+        assert_eq!(row.pid, "P1");
+        assert_eq!(row.name, "Shirt");
+        assert_eq!(row.price, 9.5);
+        assert_eq!(row.stock, None);
+        assert_eq!(row.draft, None);
+        assert_eq!(
+            row.attributes.get("color"),
+            Some(&AttributeValue::Text("red".to_string()))
+        );
+        assert!(!row.attributes.contains_key("size"));
+    }
+
+    #[test]
+    fn csv_quoted_fields_keep_commas_quotes_and_newlines() {
+        let rows = parse_csv(
+            b"pid,sid,name,description,category,price\n\
+              P1,S1,\"Shirt, blue\",\"Says \"\"hi\"\"\nand more\",clothes,1\n\
+              P2,S1,Hat,,clothes,2\n",
+        );
+
+        assert_eq!(rows.len(), 2);
+
+        let row = rows[0].1.as_ref().unwrap();
+        assert_eq!(row.name, "Shirt, blue");
+        assert_eq!(row.description, "Says \"hi\"\nand more");
+
+        assert_eq!(rows[1].0, 4);
+        assert_eq!(rows[1].1.as_ref().unwrap().name, "Hat");
+    }
+
+    #[test]
+    fn csv_invalid_numbers_are_reported() {
+        let rows = parse_csv(
+            b"pid,sid,name,category,price,stock,draft\n\
+              P1,S1,Shirt,clothes,cheap,1,false\n\
+              P2,S1,Hat,clothes,1,-1,false\n\
+              P3,S1,Cap,clothes,1,1,maybe\n",
+        );
+
+        let errors: Vec<Option<String>> = rows.into_iter().map(|(_, row)| row.err()).collect();
+        assert_eq!(
+            errors,
+            [
+                Some("Invalid `price`".to_string()),
+                Some("Invalid `stock`".to_string()),
+                Some("Invalid `draft`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn ndjson_rows_skip_blank_lines_and_keep_line_numbers() {
+        let rows = parse_ndjson(
+            b"{\"pid\":\"P1\",\"sid\":\"S1\",\"name\":\"Shirt\",\"category\":\"clothes\",\"price\":2,\"attributes\":{\"cotton\":true}}\n\
+              \n  \n\
+              {\"pid\":\"P2\"}\n",
+        );
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, 1);
+        assert_eq!(
+            rows[0].1.as_ref().unwrap().attributes.get("cotton"),
+            Some(&AttributeValue::Boolean(true))
+        );
+        assert_eq!(rows[1].0, 4);
+        assert!(rows[1].1.is_err());
+    }
+
+    #[test]
+    fn stock_is_kept_when_missing_and_set_on_products_without_variants() {
+        let mut listed = product("P1", 1.0);
+
+        assert_eq!(apply_stock_and_draft(&mut listed, None, None), Ok(()));
+        assert_eq!(listed.stock, 10);
+        assert_eq!(apply_stock_and_draft(&mut listed, Some(3), None), Ok(()));
+        assert_eq!(listed.stock, 3);
+    }
+
+    #[test]
+    fn stock_of_products_with_variants_can_only_be_repeated() {
+        let mut listed = Product {
+            variants: vec![variant("S", None, 4), variant("M", None, 6)],
+            ..product("P1", 1.0)
+        };
+        listed.aggregate();
+
+        assert_eq!(apply_stock_and_draft(&mut listed, Some(10), None), Ok(()));
+        assert!(apply_stock_and_draft(&mut listed, Some(3), None).is_err());
+        assert_eq!(listed.stock, 10);
+    }
+
+    #[test]
+    fn drafts_are_published_but_listed_products_stay_listed() {
+        let mut draft = Product {
+            status: ProductStatus::Draft,
+            ..product("P1", 1.0)
+        };
+
+        assert_eq!(apply_stock_and_draft(&mut draft, None, Some(true)), Ok(()));
+        assert_eq!(draft.status, ProductStatus::Draft);
+        assert_eq!(apply_stock_and_draft(&mut draft, None, Some(false)), Ok(()));
+        assert_eq!(draft.status, ProductStatus::Active);
+
+        let mut archived = Product {
+            status: ProductStatus::Archived,
+            ..product("P2", 1.0)
+        };
+
+        assert_eq!(
+            apply_stock_and_draft(&mut archived, None, Some(false)),
+            Ok(())
+        );
+        assert_eq!(archived.status, ProductStatus::Archived);
+        assert!(apply_stock_and_draft(&mut archived, None, Some(true)).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::product;
+use crate::routes::audit::Actor;
+use crate::structures::{Product, Sale, ScheduledPrice};
+
+use actix_web::{error, Error};
+use futures::TryStreamExt;
+use log::error;
+use mongodb::bson::{doc, Bson, DateTime};
+use mongodb::Client;
+
+/// Set or end the sale of a product.
+///
+/// This function replaces the sale of the product with the given ID. A sale whose window is already open takes effect immediately, otherwise the scheduler opens and closes it when its window starts and ends. Removing a running sale restores the regular price.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID to set the sale for.
+/// - `sale`: The new sale, or `None` to end the current one.
+/// - `if_match`: The versions the product must be at, or `None` to modify any version.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the sale is successfully set, `false` if the product does not exist, a bad request `Error` if the sale is invalid or not below the regular price, a precondition failed `Error` if the product is not at one of the given versions, and an `Error` in case of a database or indexing error.
+pub async fn update_sale(
+    pid: String,
+    sale: Option<Sale>,
+    if_match: Option<Vec<String>>,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    if let Some(sale) = &sale {
+        if sale.price < 0.0 || sale.end <= sale.start {
+            return Err(error::ErrorBadRequest(
+                "Sale price must not be negative and the sale must end after it starts",
+            ));
+        }
+    }
+
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let filter = doc! { "pid": pid.clone() };
+
+    let mut product = match collection.find_one(filter, None).await {
+        Ok(Some(product)) => product,
+        _ => return Ok(false),
+    };
+
+    product::check_version(&product, &if_match)?;
+
+    if let Some(sale) = &sale {
+        if sale.price >= product.regular_price() {
+            return Err(error::ErrorBadRequest(
+                "Sale price must be below the regular price",
+            ));
+        }
+    }
+
+    product.sale = sale;
+    product::replace_product(product, &actor, &client, &ms_client).await?;
+
+    Ok(true)
+}
+
+/// Schedule a change of the regular price of a product.
+///
+/// A price change at the same time as an existing one replaces it. Price changes that are already due take effect immediately.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID to schedule the price change for.
+/// - `scheduled`: The new regular price and the time it takes effect.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the price change is successfully scheduled, `false` if the product does not exist, a bad request `Error` if the price is negative, and an `Error` in case of a database or indexing error.
+pub async fn schedule_price(
+    pid: String,
+    scheduled: ScheduledPrice,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    if scheduled.price < 0.0 {
+        return Err(error::ErrorBadRequest("Price must not be negative"));
+    }
+
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let filter = doc! { "pid": pid.clone() };
+
+    let mut product = match collection.find_one(filter, None).await {
+        Ok(Some(product)) => product,
+        _ => return Ok(false),
+    };
+
+    product
+        .scheduled_prices
+        .retain(|existing| existing.at != scheduled.at);
+    product.scheduled_prices.push(scheduled);
+    product::replace_product(product, &actor, &client, &ms_client).await?;
+
+    Ok(true)
+}
+
+/// Cancel a scheduled price change of a product.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID the price change is scheduled for.
+/// - `at`: The time the price change was scheduled to take effect.
+/// - `if_match`: The versions the product must be at, or `None` to modify any version.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the price change is successfully cancelled, `false` if the product or the price change does not exist, a precondition failed `Error` if the product is not at one of the given versions, and an `Error` in case of a database or indexing error.
+pub async fn unschedule_price(
+    pid: String,
+    at: i64,
+    if_match: Option<Vec<String>>,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let filter = doc! { "pid": pid.clone() };
+
+    let mut product = match collection.find_one(filter, None).await {
+        Ok(Some(product)) => product,
+        _ => return Ok(false),
+    };
+
+    product::check_version(&product, &if_match)?;
+
+    let count = product.scheduled_prices.len();
+    product
+        .scheduled_prices
+        .retain(|scheduled| scheduled.at != at);
+
+    if product.scheduled_prices.len() == count {
+        return Ok(false);
+    }
+
+    product::replace_product(product, &actor, &client, &ms_client).await?;
+
+    Ok(true)
+}
+
+/// Apply the sales and scheduled price changes that are due.
+///
+/// This function finds every product whose sale window opened or closed, or that has a price change due, and stores it with its new effective price in the database and the search index.
+///
+/// # Parameters
+///
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the number of repriced products, or an `Error` in case of a database query error.
+pub async fn apply_due_prices(
+    client: &Client,
+    ms_client: &meilisearch_sdk::Client,
+) -> Result<usize, Error> {
+    let now = DateTime::now().timestamp_millis();
+    let filter = doc! {
+        "$or": [
+            { "sale.start": { "$lte": now }, "compare_at": Bson::Null },
+            { "sale.end": { "$lte": now } },
+            { "scheduled_prices.at": { "$lte": now } },
+        ]
+    };
+
+    let products: Vec<Product> = client
+        .database("ecommerce_db")
+        .collection::<Product>("products")
+        .find(filter, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    let actor = Actor::system("pricing scheduler");
+    let mut count = 0;
+
+    for product in products {
+        let pid = product.pid.clone();
+
+        // A product modified concurrently is picked up again on the next run.
+        match product::replace_product(product, &actor, client, ms_client).await {
+            Ok(_) => count += 1,
+            Err(error) => error!("Failed to reprice product {}: {}", pid, error),
+        }
+    }
+
+    Ok(count)
+}
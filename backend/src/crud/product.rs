@@ -3,25 +3,40 @@
 //! This software may be modified and distributed under the terms of the MIT license.
 //! See the LICENSE file for details.
 
-use crate::routes::product::{Data, PageQuery, ProductView, SearchQuery, UpdateQuery};
-use crate::structures::Product;
-
-use actix_web::Error;
-use futures::StreamExt;
-use meilisearch_sdk::SearchResults;
-use mongodb::bson::doc;
-use mongodb::options::{FindOptions, UpdateOptions};
+use crate::crud::{attributes, audit, webhooks};
+use crate::routes::audit::Actor;
+use crate::routes::product::{
+    Data, PageQuery, ProductPage, ProductView, SearchQuery, SortOrder, UpdateQuery,
+    UpdateVariantQuery,
+};
+use crate::structures::{AttributeValue, Image, Product, ProductStatus, Variant};
+
+use actix_web::{error, Error};
+use futures::TryStreamExt;
+use meilisearch_sdk::{SearchResults, Selectors};
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::options::FindOptions;
 use mongodb::Client;
+use std::collections::{BTreeMap, HashMap};
+
+/// The number of product views per page if no limit is given.
+const DEFAULT_PAGE_SIZE: i64 = 18;
+
+/// The maximum number of product views per page.
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// Product fields incremented by the analytics jobs without bumping the product version, and never written back from a product read earlier.
+const COUNTER_FIELDS: [&str; 2] = ["clicks", "popularity"];
 
 /// Retrieve detailed data for a specific product.
 ///
 /// This function queries the MongoDB database to retrieve detailed product data based on its ID.
-
+///
 /// # Parameters
 ///
 /// - `pid`: The product ID to retrieve data for.
 /// - `client`: MongoDB client instance used for database access.
-
+///
 /// # Returns
 ///
 /// - Returns a `Result` containing an `Option<Data>`, where `Some(data)` represents success with the detailed product data, and `None` indicates that the data was not found. An `Error` is returned in case of a database query error.
@@ -31,19 +46,35 @@ pub async fn retrieve_data(pid: String, client: Client) -> Result<Option<Data>,
         .collection::<Product>("products");
     let filter = doc! { "pid": pid };
 
-    if let Ok(Some(product)) = collection.find_one(filter, None).await {
+    if let Ok(Some(mut product)) = collection.find_one(filter, None).await {
+        product.aggregate();
+
         let data = Data {
             pid: product.pid.clone(),
             sid: product.sid.clone(),
             name: product.name.clone(),
             description: product.description.clone(),
             image: product.image.clone(),
+            images: product.images.clone(),
             category: product.category.clone(),
             price: product.price,
             stock: product.stock,
             sales: product.sales,
             rating: product.rating,
-            clicks: product.clicks
+            clicks: product.clicks,
+            options: product.options.clone(),
+            variants: product.variants.clone(),
+            min_price: product.min_price,
+            max_price: product.max_price,
+            compare_at: product.compare_at,
+            sale: product.sale.clone(),
+            scheduled_prices: product.scheduled_prices.clone(),
+            attributes: product.attributes.clone(),
+            low_stock_threshold: product.low_stock_threshold,
+            status: product.status,
+            created_at: product.created_at,
+            updated_at: product.updated_at,
+            version: product.version,
         };
 
         Ok(Some(data))
@@ -52,109 +83,388 @@ pub async fn retrieve_data(pid: String, client: Client) -> Result<Option<Data>,
     }
 }
 
-/// Retrieve products based on a search query and category filter.
+/// Retrieve products based on a search query, a category filter and attribute filters.
+///
+/// This function searches for products in the MeiliSearch index based on a search query, a category filter and filters on filterable attributes.
 ///
-/// This function searches for products in the MeiliSearch index based on a search query and a category filter.
-
 /// # Parameters
 ///
-/// - `query`: Query parameters containing the search query and category filter.
+/// - `query`: Query parameters containing the search query, the category filter and the attribute filters.
 /// - `client`: MeiliSearch client instance used for searching.
-
+///
 /// # Returns
 ///
-/// - Returns a `Result` containing a `Vec<Product>` if the search is successful. If there are no products found, an empty `Vec` is returned. If an error occurs during the search, an `Error` is returned.
+/// - Returns a `Result` containing a `Vec<Product>` if the search is successful. If there are no products found, an empty `Vec` is returned. If an error occurs during the search, such as filtering on an attribute that is not filterable, an `Error` is returned.
 pub async fn retrieve_products(
     query: SearchQuery,
     client: meilisearch_sdk::Client,
 ) -> Result<Vec<Product>, Error> {
-    let search: SearchResults<Product>;
+    let filter = search_filter(&query);
+    let index = client.index("products");
+    let mut search = index.search();
 
-    if !query.category.is_empty() {
-        search = client
-            .index("products")
-            .search()
-            .with_query(query.search.as_str())
-            .with_filter(format!("category = \"{}\"", query.category.as_str()).as_str())
-            .execute()
-            .await
-            .unwrap();
-    } else {
-        search = client
-            .index("products")
-            .search()
-            .with_query(query.search.as_str())
-            .execute()
-            .await
-            .unwrap();
-    }
+    search
+        .with_query(query.search.as_str())
+        .with_filter(filter.as_str());
 
-    let products: Vec<Product> = search.hits.into_iter().map(|hit| hit.result).collect();
+    let results: SearchResults<Product> = search
+        .execute()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let products: Vec<Product> = results.hits.into_iter().map(|hit| hit.result).collect();
 
     Ok(products)
 }
 
-/// Retrieve paginated product views.
+/// Retrieve the facet distribution of filterable attributes for a search.
+///
+/// This function counts, for each requested attribute, how many matching products have each attribute value.
+///
+/// # Parameters
+///
+/// - `query`: Query parameters containing the search query, the filters and the comma separated attribute names to facet on.
+/// - `client`: MeiliSearch client instance used for searching.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the value counts keyed by attribute name and value. An `Error` is returned if an error occurs during the search, such as faceting on an attribute that is not filterable.
+pub async fn retrieve_facets(
+    query: SearchQuery,
+    client: meilisearch_sdk::Client,
+) -> Result<HashMap<String, HashMap<String, usize>>, Error> {
+    let filter = search_filter(&query);
+    let facets: Vec<String> = query
+        .facets
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| format!("attributes.{}", name))
+        .collect();
+    let facets: Vec<&str> = facets.iter().map(String::as_str).collect();
+    let index = client.index("products");
+    let mut search = index.search();
+
+    search
+        .with_query(query.search.as_str())
+        .with_filter(filter.as_str())
+        .with_limit(0)
+        .with_facets(Selectors::Some(&facets));
+
+    let results: SearchResults<Product> = search
+        .execute()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(results
+        .facet_distribution
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, values)| (name.trim_start_matches("attributes.").to_string(), values))
+        .collect())
+}
+
+/// Build the MeiliSearch filter expression of a search query.
 ///
-/// This function retrieves product views for a specific page based on pagination parameters.
+/// Attribute filters are given as comma separated `name:value` pairs and are combined with the category filter. Only active products are searched.
+fn search_filter(query: &SearchQuery) -> String {
+    let mut filters: Vec<String> = vec![format!(
+        "status = {}",
+        filter_value(ProductStatus::Active.as_str())
+    )];
+
+    if !query.category.is_empty() {
+        filters.push(format!("category = {}", filter_value(&query.category)));
+    }
+
+    for pair in query.attributes.split(',') {
+        if let Some((name, value)) = pair.split_once(':') {
+            let name = name.trim();
 
+            if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                filters.push(format!(
+                    "attributes.{} = {}",
+                    name,
+                    filter_value(value.trim())
+                ));
+            }
+        }
+    }
+
+    filters.join(" AND ")
+}
+
+/// Quote a value for use in a MeiliSearch filter expression.
+pub fn filter_value(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Retrieve paginated product views by page number.
+///
+/// This function retrieves active product views for a specific page, sorted by the requested field and then by product ID so that pages are stable. It is kept for backward compatibility, `retrieve_product_page` should be preferred as it does not skip over the previous pages.
+///
 /// # Parameters
 ///
-/// - `query`: Query parameters containing the page number.
+/// - `query`: Query parameters containing the page number, the optional page size, and the optional sort and filters.
 /// - `client`: MongoDB client instance used for database access.
-
+///
 /// # Returns
 ///
-/// - Returns a `Result` containing an `Option<Vec<ProductView>>` if the retrieval is successful. If no product views are found, `None` is returned. An `Error` is returned in case of a database query error.
+/// - Returns a `Result` containing an `Option<Vec<ProductView>>` if the retrieval is successful. If no product views are found, `None` is returned. A bad request `Error` is returned if the page number, the page size or the filters are invalid, and an `Error` in case of a database query error.
 pub async fn retrieve_paginated_products(
     query: PageQuery,
     client: Client,
 ) -> Result<Option<Vec<ProductView>>, Error> {
-    let collection = client
+    let limit = page_limit(&query)?;
+    let page = match query.page {
+        Some(page) if page > 0 => page as u64,
+        _ => return Err(error::ErrorBadRequest("Page must start at 1")),
+    };
+
+    let options = FindOptions::builder()
+        .sort(view_sort(&query))
+        .skip((page - 1) * limit as u64)
+        .limit(limit)
+        .build();
+
+    let products = find_product_views(view_filter(&query)?, options, &client).await?;
+
+    Ok(Some(products))
+}
+
+/// Retrieve a page of product views after a cursor.
+///
+/// This function retrieves active product views sorted by the requested field and then by product ID, starting right after the product the cursor points at, so that pages stay stable while products are added or removed. The cursor holds the sort value and the product ID of the last product of the previous page.
+///
+/// # Parameters
+///
+/// - `query`: Query parameters containing the optional cursor, page size, sort and filters.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the `ProductPage` with the product views, the cursor of the next page and the total number of matching products. A bad request `Error` is returned if the page size, the cursor or the filters are invalid, and an `Error` in case of a database query error.
+pub async fn retrieve_product_page(query: PageQuery, client: Client) -> Result<ProductPage, Error> {
+    let limit = page_limit(&query)?;
+    let mut filter = view_filter(&query)?;
+    let total = client
         .database("ecommerce_db")
-        .collection::<Product>("products");
-    let limit = 18;
-    let skip = (query.page - 1) * limit;
+        .collection::<Product>("products")
+        .count_documents(filter.clone(), None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    if let Some(cursor) = &query.cursor {
+        match query.sort {
+            None => {
+                filter.insert("pid", doc! { "$gt": cursor });
+            }
+            Some(sort) => {
+                let (value, pid) = cursor
+                    .split_once(':')
+                    .and_then(|(value, pid)| Some((value.parse::<f64>().ok()?, pid)))
+                    .ok_or_else(|| error::ErrorBadRequest("Invalid cursor"))?;
+                let operator = match query.order.unwrap_or(sort.default_order()) {
+                    SortOrder::Asc => "$gt",
+                    SortOrder::Desc => "$lt",
+                };
+
+                filter.insert(
+                    "$or",
+                    vec![
+                        doc! { sort.field(): { operator: value } },
+                        doc! { sort.field(): value, "pid": { operator: pid } },
+                    ],
+                );
+            }
+        }
+    }
+
+    // Read one extra product to find out whether there is a next page.
     let options = FindOptions::builder()
-        .skip(skip as u64)
-        .limit(limit as i64)
+        .sort(view_sort(&query))
+        .limit(limit + 1)
         .build();
-    let mut cursor = collection.find(None, options).await.unwrap();
-    let mut products_view: Vec<ProductView> = Vec::new();
 
-    while let Some(Ok(product)) = cursor.next().await {
-        let product_view = ProductView {
-            pid: product.pid.clone(),
-            sid: product.sid.clone(),
-            name: product.name.clone(),
-            image: product.image.clone(),
-            price: product.price,
-            stock: product.stock,
-            sales: product.sales,
-            rating: product.rating,
-            clicks: product.clicks
-        };
+    let mut products = find_products(filter, options, &client).await?;
+    let next_cursor = if products.len() > limit as usize {
+        products.truncate(limit as usize);
+        // The cursor holds the stored value, as an aggregated price may differ from the one the database sorted on.
+        products.last().map(|product| match query.sort {
+            None => product.pid.clone(),
+            Some(sort) => format!("{}:{}", sort.value(product), product.pid),
+        })
+    } else {
+        None
+    };
 
-        products_view.push(product_view);
+    Ok(ProductPage {
+        products: products.into_iter().map(aggregated_view).collect(),
+        next_cursor,
+        total,
+    })
+}
+
+/// Validate the page size of a pagination query, defaulting to `DEFAULT_PAGE_SIZE`.
+fn page_limit(query: &PageQuery) -> Result<i64, Error> {
+    match query.limit {
+        None => Ok(DEFAULT_PAGE_SIZE),
+        Some(limit) if limit > 0 && limit as i64 <= MAX_PAGE_SIZE => Ok(limit as i64),
+        Some(_) => Err(error::ErrorBadRequest(format!(
+            "Limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        ))),
+    }
+}
+
+/// Build the database filter of a catalog view query, selecting the active products matching its filters.
+fn view_filter(query: &PageQuery) -> Result<Document, Error> {
+    let mut filter = doc! { "status": ProductStatus::Active.filter() };
+
+    if let Some(category) = &query.category {
+        filter.insert("category", category.clone());
     }
 
-    Ok(Some(products_view))
+    if let Some(sid) = &query.sid {
+        filter.insert("sid", sid.clone());
+    }
+
+    let mut price = Document::new();
+
+    if let Some(min_price) = query.min_price {
+        if !min_price.is_finite() || min_price < 0.0 {
+            return Err(error::ErrorBadRequest("Minimum price must not be negative"));
+        }
+
+        price.insert("$gte", min_price);
+    }
+
+    if let Some(max_price) = query.max_price {
+        if !max_price.is_finite() || max_price < query.min_price.unwrap_or(0.0) {
+            return Err(error::ErrorBadRequest(
+                "Maximum price must not be below the minimum price",
+            ));
+        }
+
+        price.insert("$lte", max_price);
+    }
+
+    if !price.is_empty() {
+        filter.insert("price", price);
+    }
+
+    if query.in_stock {
+        filter.insert("stock", doc! { "$gt": 0 });
+    }
+
+    Ok(filter)
+}
+
+/// Build the database sort of a catalog view query, using the product ID in the same direction as a tie breaker so that a single index serves both directions.
+fn view_sort(query: &PageQuery) -> Document {
+    match query.sort {
+        None => doc! { "pid": 1 },
+        Some(sort) => {
+            let direction = match query.order.unwrap_or(sort.default_order()) {
+                SortOrder::Asc => 1,
+                SortOrder::Desc => -1,
+            };
+
+            doc! { sort.field(): direction, "pid": direction }
+        }
+    }
+}
+
+/// Retrieve the product views of the products matching a filter.
+pub async fn find_product_views(
+    filter: Document,
+    options: FindOptions,
+    client: &Client,
+) -> Result<Vec<ProductView>, Error> {
+    let products = find_products(filter, options, client).await?;
+
+    Ok(products.into_iter().map(aggregated_view).collect())
+}
+
+/// Retrieve the products matching a filter as stored.
+async fn find_products(
+    filter: Document,
+    options: FindOptions,
+    client: &Client,
+) -> Result<Vec<Product>, Error> {
+    client
+        .database("ecommerce_db")
+        .collection::<Product>("products")
+        .find(filter, options)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)
+}
+
+/// Build the product view of a stored product, applying its variants and current pricing.
+fn aggregated_view(mut product: Product) -> ProductView {
+    product.aggregate();
+    product_view(&product)
+}
+
+/// Retrieve the product views of the active products among the given product IDs, in the order of the IDs.
+pub async fn active_product_views(
+    pids: &[String],
+    client: &Client,
+) -> Result<Vec<ProductView>, Error> {
+    if pids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let filter = doc! { "pid": { "$in": pids }, "status": ProductStatus::Active.filter() };
+    let mut products = find_product_views(filter, FindOptions::default(), client).await?;
+
+    products.sort_by_key(|product| pids.iter().position(|pid| *pid == product.pid));
+
+    Ok(products)
+}
+
+/// Build the product view of an aggregated product.
+pub fn product_view(product: &Product) -> ProductView {
+    ProductView {
+        pid: product.pid.clone(),
+        sid: product.sid.clone(),
+        name: product.name.clone(),
+        image: product.image.clone(),
+        price: product.price,
+        stock: product.stock,
+        sales: product.sales,
+        rating: product.rating,
+        clicks: product.clicks,
+        min_price: product.min_price,
+        max_price: product.max_price,
+        compare_at: product.compare_at,
+        status: product.status,
+        created_at: product.created_at,
+    }
 }
 
 /// Updates a product in the MongoDB collection.
 ///
+/// If versions are given, the product is only updated if its current version is one of them. The stock level of a product with variants is aggregated from the variants, so the `stock` field of the query is ignored for such products. Likewise, the image of a product with a gallery follows the primary gallery image, so the `image` field of the query only selects the primary image among the gallery for such products.
+///
 /// # Arguments
 ///
 /// * `pid` - The product ID (pid) of the product to be updated.
 /// * `query` - The update query containing the fields to be updated.
+/// * `if_match` - The versions the product must be at, or `None` to update any version.
+/// * `actor` - The actor performing the modification, recorded in the audit log.
 /// * `client` - The MongoDB client.
 ///
 /// # Returns
 ///
-/// A `Result` indicating whether the update was successful.
+/// A `Result` indicating whether the update was successful, or a precondition failed `Error` if the product is not at one of the given versions.
 pub async fn update_product(
     pid: String,
     query: UpdateQuery,
+    if_match: Option<Vec<String>>,
+    actor: Actor,
     client: Client,
     ms_client: meilisearch_sdk::Client,
 ) -> Result<bool, Error> {
@@ -162,47 +472,515 @@ pub async fn update_product(
         .database("ecommerce_db")
         .collection::<Product>("products");
     let filter = doc! { "pid": pid.clone() };
-    let update = doc! {
-        "$set": {
-            "image": query.image.clone(),
-            "price": query.price,
-            "stock": query.stock,
-            "sales": query.sales,
-            "rating": query.rating,
+
+    if let Ok(Some(mut product)) = collection.find_one(filter, None).await {
+        check_version(&product, &if_match)?;
+
+        if product.images.is_empty() {
+            product.image = query.image.clone();
+        } else {
+            product.set_primary_image(Some(query.image.as_str()));
+        }
+
+        if product.variants.is_empty() {
+            product.stock = query.stock;
         }
-    };
 
-    let options = UpdateOptions::builder().upsert(false).build();
-
-    if let Ok(Some(product)) = collection.find_one(filter.clone(), None).await {
-        collection
-            .update_one(filter, update, options)
-            .await
-            .expect("Failed to update product");
-
-        ms_client
-            .index("products")
-            .add_or_replace(
-                &[Product {
-                    pid: pid.clone(),
-                    sid: product.sid.clone(),
-                    name: product.name.clone(),
-                    description: product.description.clone(),
-                    image: product.image.clone(),
-                    category: product.category.clone(),
-                    price: query.price,
-                    stock: query.stock,
-                    sales: query.sales,
-                    rating: query.rating,
-                    clicks: product.clicks,
-                }],
-                None,
-            )
-            .await
-            .unwrap();
+        product.set_regular_price(query.price);
+        product.sales = query.sales;
+        product.rating = query.rating;
+
+        replace_product(product, &actor, &client, &ms_client).await?;
 
         Ok(true)
     } else {
         Ok(false)
     }
 }
+
+/// Add a variant to a product.
+///
+/// This function appends a new variant to the product with the given ID. The first variant defines the option axes of the product, every following variant must provide a value for exactly those axes.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID to add the variant to.
+/// - `variant`: The variant to be added.
+/// - `if_match`: The versions the product must be at, or `None` to modify any version.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the variant is successfully added, `false` if the product does not exist, the `sku` is already taken or the option axes do not match, a bad request `Error` if another variant has the same option values, a precondition failed `Error` if the product is not at one of the given versions, and an `Error` in case of a database or indexing error.
+pub async fn add_variant(
+    pid: String,
+    variant: Variant,
+    if_match: Option<Vec<String>>,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let filter = doc! { "pid": pid.clone() };
+
+    let mut product = match collection.find_one(filter, None).await {
+        Ok(Some(product)) => product,
+        _ => return Ok(false),
+    };
+
+    check_version(&product, &if_match)?;
+
+    if variant.sku.is_empty()
+        || variant.options.is_empty()
+        || product
+            .variants
+            .iter()
+            .any(|other| other.sku == variant.sku)
+    {
+        return Ok(false);
+    }
+
+    let axes: Vec<String> = variant.options.keys().cloned().collect();
+
+    if product.options.is_empty() {
+        product.options = axes;
+    } else if product.options.len() != axes.len()
+        || !product
+            .options
+            .iter()
+            .all(|axis| variant.options.contains_key(axis))
+    {
+        return Ok(false);
+    }
+
+    if product
+        .variants
+        .iter()
+        .any(|other| other.options == variant.options)
+    {
+        return Err(error::ErrorBadRequest(
+            "A variant with the same options already exists",
+        ));
+    }
+
+    product.variants.push(variant);
+    replace_product(product, &actor, &client, &ms_client).await?;
+
+    Ok(true)
+}
+
+/// Update a variant of a product.
+///
+/// This function updates the price, stock level and image of the variant with the given `sku`. Fields that are not provided in the query are left unchanged.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID the variant belongs to.
+/// - `sku`: The stock keeping unit of the variant to be updated.
+/// - `query`: The update query containing the fields to be updated.
+/// - `if_match`: The versions the product must be at, or `None` to modify any version.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the variant is successfully updated, `false` if the product or the variant does not exist, a precondition failed `Error` if the product is not at one of the given versions, and an `Error` in case of a database or indexing error.
+pub async fn update_variant(
+    pid: String,
+    sku: String,
+    query: UpdateVariantQuery,
+    if_match: Option<Vec<String>>,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let filter = doc! { "pid": pid.clone() };
+
+    let mut product = match collection.find_one(filter, None).await {
+        Ok(Some(product)) => product,
+        _ => return Ok(false),
+    };
+
+    check_version(&product, &if_match)?;
+
+    match product
+        .variants
+        .iter_mut()
+        .find(|variant| variant.sku == sku)
+    {
+        Some(variant) => {
+            if query.price.is_some() {
+                variant.price = query.price;
+            }
+
+            if let Some(stock) = query.stock {
+                variant.stock = stock;
+            }
+
+            if let Some(image) = query.image {
+                variant.image = image;
+            }
+        }
+        None => return Ok(false),
+    }
+
+    replace_product(product, &actor, &client, &ms_client).await?;
+
+    Ok(true)
+}
+
+/// Remove a variant from a product.
+///
+/// This function removes the variant with the given `sku`. Removing the last variant also clears the option axes of the product.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID the variant belongs to.
+/// - `sku`: The stock keeping unit of the variant to be removed.
+/// - `if_match`: The versions the product must be at, or `None` to modify any version.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the variant is successfully removed, `false` if the product or the variant does not exist, a precondition failed `Error` if the product is not at one of the given versions, and an `Error` in case of a database or indexing error.
+pub async fn remove_variant(
+    pid: String,
+    sku: String,
+    if_match: Option<Vec<String>>,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let filter = doc! { "pid": pid.clone() };
+
+    let mut product = match collection.find_one(filter, None).await {
+        Ok(Some(product)) => product,
+        _ => return Ok(false),
+    };
+
+    check_version(&product, &if_match)?;
+
+    let count = product.variants.len();
+    product.variants.retain(|variant| variant.sku != sku);
+
+    if product.variants.len() == count {
+        return Ok(false);
+    }
+
+    if product.variants.is_empty() {
+        product.options.clear();
+    }
+
+    replace_product(product, &actor, &client, &ms_client).await?;
+
+    Ok(true)
+}
+
+/// Add an image to a product gallery.
+///
+/// This function appends a new image to the gallery of the product with the given ID. The image becomes the primary image if it is flagged as such or if it is the first image of the gallery.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID to add the image to.
+/// - `image`: The image to be added.
+/// - `if_match`: The versions the product must be at, or `None` to modify any version.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the image is successfully added, `false` if the product does not exist or the gallery already contains the URL, a precondition failed `Error` if the product is not at one of the given versions, and an `Error` in case of a database or indexing error.
+pub async fn add_image(
+    pid: String,
+    image: Image,
+    if_match: Option<Vec<String>>,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let filter = doc! { "pid": pid.clone() };
+
+    let mut product = match collection.find_one(filter, None).await {
+        Ok(Some(product)) => product,
+        _ => return Ok(false),
+    };
+
+    check_version(&product, &if_match)?;
+
+    if image.url.is_empty() || product.images.iter().any(|other| other.url == image.url) {
+        return Ok(false);
+    }
+
+    let url = image.url.clone();
+    let primary = image.primary;
+
+    product.images.push(image);
+
+    if primary {
+        product.set_primary_image(Some(url.as_str()));
+    }
+
+    replace_product(product, &actor, &client, &ms_client).await?;
+
+    Ok(true)
+}
+
+/// Reorder a product gallery.
+///
+/// This function rearranges the gallery of the product with the given ID to follow the given order of image URLs.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID whose gallery is reordered.
+/// - `order`: The image URLs in their new order, which must contain every gallery image exactly once.
+/// - `if_match`: The versions the product must be at, or `None` to modify any version.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the gallery is successfully reordered, `false` if the product does not exist or the order does not match the gallery, a precondition failed `Error` if the product is not at one of the given versions, and an `Error` in case of a database or indexing error.
+pub async fn order_images(
+    pid: String,
+    order: Vec<String>,
+    if_match: Option<Vec<String>>,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let filter = doc! { "pid": pid.clone() };
+
+    let mut product = match collection.find_one(filter, None).await {
+        Ok(Some(product)) => product,
+        _ => return Ok(false),
+    };
+
+    check_version(&product, &if_match)?;
+
+    if order.len() != product.images.len() {
+        return Ok(false);
+    }
+
+    let mut images: Vec<Image> = Vec::with_capacity(order.len());
+
+    for url in order.iter() {
+        match product.images.iter().position(|image| &image.url == url) {
+            Some(index) => images.push(product.images.swap_remove(index)),
+            None => return Ok(false),
+        }
+    }
+
+    product.images = images;
+    replace_product(product, &actor, &client, &ms_client).await?;
+
+    Ok(true)
+}
+
+/// Remove an image from a product gallery.
+///
+/// This function removes the image with the given URL from the gallery. If the primary image is removed, the first remaining image becomes the primary image.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID whose gallery contains the image.
+/// - `url`: The URL of the image to be removed.
+/// - `if_match`: The versions the product must be at, or `None` to modify any version.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the image is successfully removed, `false` if the product or the image does not exist, a precondition failed `Error` if the product is not at one of the given versions, and an `Error` in case of a database or indexing error.
+pub async fn remove_image(
+    pid: String,
+    url: String,
+    if_match: Option<Vec<String>>,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let filter = doc! { "pid": pid.clone() };
+
+    let mut product = match collection.find_one(filter, None).await {
+        Ok(Some(product)) => product,
+        _ => return Ok(false),
+    };
+
+    check_version(&product, &if_match)?;
+
+    let count = product.images.len();
+    product.images.retain(|image| image.url != url);
+
+    if product.images.len() == count {
+        return Ok(false);
+    }
+
+    if product.images.is_empty() {
+        product.image.clear();
+    }
+
+    replace_product(product, &actor, &client, &ms_client).await?;
+
+    Ok(true)
+}
+
+/// Replace the attributes of a product.
+///
+/// This function validates the attributes against the schema of the product category before storing them.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID whose attributes are replaced.
+/// - `attributes`: The new product attributes.
+/// - `if_match`: The versions the product must be at, or `None` to modify any version.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the attributes are successfully replaced, `false` if the product does not exist, a bad request `Error` if the attributes do not match the schema, a precondition failed `Error` if the product is not at one of the given versions, and an `Error` in case of a database or indexing error.
+pub async fn update_attributes(
+    pid: String,
+    attributes: BTreeMap<String, AttributeValue>,
+    if_match: Option<Vec<String>>,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+    let filter = doc! { "pid": pid.clone() };
+
+    let mut product = match collection.find_one(filter, None).await {
+        Ok(Some(product)) => product,
+        _ => return Ok(false),
+    };
+
+    check_version(&product, &if_match)?;
+    attributes::validate_attributes(&product.category, &attributes, &client).await?;
+
+    product.attributes = attributes;
+    replace_product(product, &actor, &client, &ms_client).await?;
+
+    Ok(true)
+}
+
+/// Check that a product is at one of the given versions.
+///
+/// # Returns
+///
+/// - Returns `Ok(())` if no versions are given or the product is at one of them, or a precondition failed `Error` otherwise.
+pub fn check_version(product: &Product, if_match: &Option<Vec<String>>) -> Result<(), Error> {
+    match if_match {
+        Some(versions) if !versions.contains(&product.version.to_string()) => Err(
+            error::ErrorPreconditionFailed("Product version does not match"),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Aggregate a product, store it, refresh its search document and record the modification in the audit log.
+///
+/// The product is only stored if the stored version is still the one it was read at, so concurrent modifications are detected instead of silently overwritten.
+///
+/// # Returns
+///
+/// - Returns the stored product, a precondition failed `Error` if the product was modified concurrently, or an `Error` in case of a database or indexing error.
+pub async fn replace_product(
+    product: Product,
+    actor: &Actor,
+    client: &Client,
+    ms_client: &meilisearch_sdk::Client,
+) -> Result<Product, Error> {
+    let product = store_product(product, actor, client).await?;
+
+    ms_client
+        .index("products")
+        .add_or_replace(std::slice::from_ref(&product), Some("pid"))
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(product)
+}
+
+/// Aggregate a product, store it and record the modification in the audit log, without refreshing its search document.
+///
+/// This is used by imports, which refresh the search documents of all imported products in batches. Every modification queues the matching webhook events. The counters maintained by the analytics jobs are left out of the write, so clicks and popularity updated since the product was read are kept.
+///
+/// # Returns
+///
+/// - Returns the stored product, a precondition failed `Error` if the product was modified concurrently, or an `Error` in case of a database error.
+pub async fn store_product(
+    mut product: Product,
+    actor: &Actor,
+    client: &Client,
+) -> Result<Product, Error> {
+    let (filter, update) = versioned_write(&mut product)?;
+
+    let before = client
+        .database("ecommerce_db")
+        .collection::<Product>("products")
+        .find_one_and_update(filter, update, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorPreconditionFailed("Product was modified concurrently"))?;
+
+    product.clicks = before.clicks;
+    product.popularity = before.popularity;
+
+    audit::record(actor, Some(&before), Some(&product), client).await?;
+    webhooks::emit_changes(&before, &product, client).await;
+
+    Ok(product)
+}
+
+/// Aggregate a product and bump its version, returning the filter matching the version it was read at and the update writing it.
+///
+/// The counters maintained by the analytics jobs are left out of the update.
+pub fn versioned_write(product: &mut Product) -> Result<(Document, Document), Error> {
+    // Documents stored before versioning was introduced have no version field.
+    let version = match product.version {
+        0 => doc! { "$in": [0, Bson::Null] },
+        version => doc! { "$eq": version },
+    };
+    let filter = doc! { "pid": product.pid.clone(), "version": version };
+
+    product.aggregate();
+    product.touch();
+
+    let mut fields = bson::to_document(&*product).map_err(error::ErrorInternalServerError)?;
+
+    for field in COUNTER_FIELDS {
+        fields.remove(field);
+    }
+
+    Ok((filter, doc! { "$set": fields }))
+}
@@ -3,13 +3,17 @@
 //! This software may be modified and distributed under the terms of the MIT license.
 //! See the LICENSE file for details.
 
+use crate::crud::{attributes, audit, product, webhooks};
+use crate::database;
+use crate::routes::audit::Actor;
 use crate::routes::profile::ProductQuery;
-use crate::structures::Product;
+use crate::structures::{AttributeValue, Product, ProductStatus, WebhookEvent};
 
-use actix_web::Error;
+use actix_web::{error, Error};
 use futures::StreamExt;
 use mongodb::bson::doc;
 use mongodb::Client;
+use std::collections::BTreeMap;
 
 /// Retrieve products sold by the specified seller id.
 ///
@@ -47,19 +51,23 @@ pub async fn retrieve_seller_products(sid: String, client: Client) -> (Vec<Produ
 
 /// List a product in the database.
 ///
-/// This function attempts to add a new product to the database. If the product with the same `pid` already exists, it returns `false`. If the addition is successful, it returns `true`.
-
+/// This function attempts to add a new product to the database. If the product with the same `pid` already exists, it returns `false`. If the addition is successful, it returns `true` and emits the `product.created` webhook event. The product attributes are validated against the schema of the product category.
+///
 /// # Parameters
 ///
 /// - `query`: Product information to be added.
+/// - `attributes`: The product attributes keyed by attribute name.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
 /// - `client`: MongoDB client instance used for database access.
 /// - `ms_client`: MeiliSearch client instance used for indexing.
-
+///
 /// # Returns
 ///
-/// - Returns `true` if the product is successfully listed, `false` if the product with the same `pid` already exists, and an `Error` in case of a database or indexing error.
+/// - Returns `true` if the product is successfully listed, `false` if the product with the same `pid` already exists, a bad request `Error` if the attributes do not match the schema, and an `Error` in case of a database or indexing error.
 pub async fn list_product(
     query: ProductQuery,
+    attributes: BTreeMap<String, AttributeValue>,
+    actor: Actor,
     client: Client,
     ms_client: meilisearch_sdk::Client,
 ) -> Result<bool, Error> {
@@ -71,7 +79,9 @@ pub async fn list_product(
     if let Ok(Some(_)) = collection.find_one(filter, None).await {
         Ok(false)
     } else {
-        let product = Product {
+        attributes::validate_attributes(&query.category, &attributes, &client).await?;
+
+        let mut product = Product {
             pid: query.pid.clone(),
             sid: query.sid.clone(),
             name: query.name.clone(),
@@ -80,18 +90,34 @@ pub async fn list_product(
             category: query.category.clone(),
             price: query.price,
             stock: query.stock,
+            attributes,
+            status: if query.draft {
+                ProductStatus::Draft
+            } else {
+                ProductStatus::Active
+            },
             ..Product::default()
         };
 
-        let _ = collection.insert_one(product.clone(), None).await;
-        let mut product_doc: Vec<Product> = Vec::new();
+        product.aggregate();
+        product.touch();
+
+        // The unique index on the product ID rejects a product listed concurrently with the same ID.
+        match collection.insert_one(product.clone(), None).await {
+            Ok(_) => {}
+            Err(error) if database::is_duplicate_key(&error) => return Ok(false),
+            Err(error) => return Err(error::ErrorInternalServerError(error)),
+        }
 
-        product_doc.push(product.clone());
+        let product_doc: Vec<Product> = vec![product.clone()];
         ms_client
             .index("products")
             .add_documents(&product_doc, Some("pid"))
             .await
-            .unwrap();
+            .map_err(error::ErrorInternalServerError)?;
+
+        audit::record(&actor, None, Some(&product), &client).await?;
+        webhooks::emit(&[WebhookEvent::ProductCreated], &product, None, &client).await;
 
         Ok(true)
     }
@@ -99,19 +125,113 @@ pub async fn list_product(
 
 /// Delist a product from the database.
 ///
-/// This function attempts to remove a product from the database. If the product with the given `pid` exists and is successfully delisted, it returns `true`. If the product does not exist, it returns `false`.
-
+/// This function attempts to archive a product. Archived products are hidden from the catalog and search results but keep their sales history and can be restored by the seller. If the product with the given `pid` exists and is successfully delisted, it returns `true`. If the product does not exist, it returns `false`.
+///
 /// # Parameters
 ///
 /// - `pid`: The `pid` of the product to be delisted.
+/// - `if_match`: The versions the product must be at, or `None` to delist any version.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
 /// - `client`: MongoDB client instance used for database access.
 /// - `ms_client`: MeiliSearch client instance used for indexing.
-
+///
 /// # Returns
 ///
-/// - Returns `true` if the product is successfully delisted, `false` if the product with the given `pid` does not exist, and an `Error` in case of a database or indexing error.
+/// - Returns `true` if the product is successfully delisted, `false` if the product with the given `pid` does not exist, a precondition failed `Error` if the product is not at one of the given versions, and an `Error` in case of a database or indexing error.
 pub async fn delist_product(
     pid: String,
+    if_match: Option<Vec<String>>,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    change_status(
+        pid,
+        &[
+            ProductStatus::Draft,
+            ProductStatus::Active,
+            ProductStatus::OutOfStock,
+        ],
+        ProductStatus::Archived,
+        if_match,
+        actor,
+        client,
+        ms_client,
+    )
+    .await
+}
+
+/// Restore a delisted product.
+///
+/// This function attempts to list an archived product again. If the product with the given `pid` is archived and is successfully restored, it returns `true`. If the product does not exist or is not archived, it returns `false`.
+///
+/// # Parameters
+///
+/// - `pid`: The `pid` of the product to be restored.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the product is successfully restored, `false` if the product with the given `pid` does not exist or is not archived, and an `Error` in case of a database or indexing error.
+pub async fn restore_product(
+    pid: String,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    change_status(
+        pid,
+        &[ProductStatus::Archived],
+        ProductStatus::Active,
+        None,
+        actor,
+        client,
+        ms_client,
+    )
+    .await
+}
+
+/// Publish a draft product.
+///
+/// This function attempts to list a draft product for the first time. If the product with the given `pid` is a draft and is successfully published, it returns `true`. If the product does not exist or is not a draft, it returns `false`.
+///
+/// # Parameters
+///
+/// - `pid`: The `pid` of the product to be published.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the product is successfully published, `false` if the product with the given `pid` does not exist or is not a draft, and an `Error` in case of a database or indexing error.
+pub async fn publish_product(
+    pid: String,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    change_status(
+        pid,
+        &[ProductStatus::Draft],
+        ProductStatus::Active,
+        None,
+        actor,
+        client,
+        ms_client,
+    )
+    .await
+}
+
+/// Move a product to a new lifecycle status if it currently has one of the given statuses.
+async fn change_status(
+    pid: String,
+    from: &[ProductStatus],
+    to: ProductStatus,
+    if_match: Option<Vec<String>>,
+    actor: Actor,
     client: Client,
     ms_client: meilisearch_sdk::Client,
 ) -> Result<bool, Error> {
@@ -120,15 +240,15 @@ pub async fn delist_product(
         .collection::<Product>("products");
     let filter = doc! { "pid": pid.clone() };
 
-    if let Ok(Some(_)) = collection.find_one_and_delete(filter, None).await {
-        ms_client
-            .index("products")
-            .delete_document(pid.clone())
-            .await
-            .unwrap();
+    match collection.find_one(filter, None).await {
+        Ok(Some(mut product)) if from.contains(&product.status) => {
+            product::check_version(&product, &if_match)?;
 
-        Ok(true)
-    } else {
-        Ok(false)
+            product.status = to;
+            product::replace_product(product, &actor, &client, &ms_client).await?;
+
+            Ok(true)
+        }
+        _ => Ok(false),
     }
 }
@@ -0,0 +1,155 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::product::active_product_views;
+use crate::routes::product::ProductView;
+use crate::structures::{Granularity, RecentViews};
+
+use actix_web::{error, Error};
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::UpdateOptions;
+use mongodb::Client;
+use std::env;
+
+/// The default number of recently viewed products kept per shopper.
+const DEFAULT_RECENT_LIMIT: i64 = 20;
+
+/// The default number of days recently viewed products are kept.
+const DEFAULT_RETENTION: i64 = 30;
+
+/// The settings of the recently viewed products.
+struct RecentSettings {
+    /// The number of products kept per shopper.
+    limit: i64,
+
+    /// The number of milliseconds a viewed product is kept.
+    retention: i64,
+}
+
+impl RecentSettings {
+    /// Read the settings from the `RECENT_VIEWS_LIMIT` and `RECENT_VIEWS_RETENTION` (in days) environment variables.
+    fn from_env() -> Self {
+        let retention = env::var("RECENT_VIEWS_RETENTION")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|days| *days > 0)
+            .unwrap_or(DEFAULT_RETENTION);
+
+        RecentSettings {
+            limit: env::var("RECENT_VIEWS_LIMIT")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .filter(|limit| *limit > 0)
+                .unwrap_or(DEFAULT_RECENT_LIMIT),
+            retention: retention * Granularity::Day.millis(),
+        }
+    }
+}
+
+/// Record that a shopper viewed a product.
+///
+/// This function moves the product to the front of the recently viewed products of the shopper in a single update, dropping the products viewed before the retention period and the oldest products beyond the limit. The whole history expires once no product was viewed for the retention period.
+///
+/// # Parameters
+///
+/// - `viewer`: The user ID of the shopper, or the anonymous ID of their browsing session.
+/// - `pid`: The viewed product ID.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns `Ok(())` if the view is recorded, or an `Error` in case of a database error.
+pub async fn record_view(viewer: &str, pid: &str, client: &Client) -> Result<(), Error> {
+    let settings = RecentSettings::from_env();
+    let now = DateTime::now().timestamp_millis();
+    let earlier = doc! {
+        "$filter": {
+            "input": { "$ifNull": ["$products", []] },
+            "cond": {
+                "$and": [
+                    { "$ne": ["$$this.pid", pid] },
+                    { "$gte": ["$$this.viewed_at", now - settings.retention] },
+                ]
+            },
+        }
+    };
+    let update = vec![doc! {
+        "$set": {
+            "products": {
+                "$slice": [
+                    { "$concatArrays": [[{ "pid": pid, "viewed_at": now }], earlier] },
+                    settings.limit,
+                ]
+            },
+            "expires_at": DateTime::from_millis(now + settings.retention),
+        }
+    }];
+    let options = UpdateOptions::builder().upsert(true).build();
+
+    client
+        .database("ecommerce_db")
+        .collection::<RecentViews>("recent_views")
+        .update_one(doc! { "viewer": viewer }, update, options)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(())
+}
+
+/// Retrieve the products recently viewed by a shopper.
+///
+/// # Parameters
+///
+/// - `viewer`: The user ID of the shopper, or the anonymous ID of their browsing session.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the product views of the active products viewed within the retention period, most recently viewed first, or an `Error` in case of a database query error.
+pub async fn retrieve_recent(viewer: String, client: Client) -> Result<Vec<ProductView>, Error> {
+    let settings = RecentSettings::from_env();
+    let since = DateTime::now().timestamp_millis() - settings.retention;
+
+    let history = client
+        .database("ecommerce_db")
+        .collection::<RecentViews>("recent_views")
+        .find_one(doc! { "viewer": viewer }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let pids: Vec<String> = history
+        .map(|history| {
+            history
+                .products
+                .into_iter()
+                .filter(|product| product.viewed_at >= since)
+                .take(settings.limit as usize)
+                .map(|product| product.pid)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    active_product_views(&pids, &client).await
+}
+
+/// Delete the recently viewed products of a shopper.
+///
+/// # Parameters
+///
+/// - `viewer`: The user ID of the shopper, or the anonymous ID of their browsing session.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns `true` if the history was deleted, `false` if the shopper had none, and an `Error` in case of a database error.
+pub async fn clear_recent(viewer: String, client: Client) -> Result<bool, Error> {
+    let result = client
+        .database("ecommerce_db")
+        .collection::<RecentViews>("recent_views")
+        .delete_one(doc! { "viewer": viewer }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(result.deleted_count > 0)
+}
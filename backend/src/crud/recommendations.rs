@@ -0,0 +1,255 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::product::{active_product_views, filter_value};
+use crate::routes::product::ProductView;
+use crate::routes::recommendations::{Recommendations, RecommendationsQuery};
+use crate::structures::{BoughtTogether, EventKind, Granularity, Product, ProductStatus};
+
+use actix_web::{error, Error};
+use futures::TryStreamExt;
+use meilisearch_sdk::{SearchResults, Selectors};
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::AggregateOptions;
+use mongodb::Client;
+use serde::Deserialize;
+
+/// The number of products of each recommendation list if no limit is given.
+const DEFAULT_RECOMMENDATIONS: u32 = 8;
+
+/// The maximum number of products of each recommendation list.
+const MAX_RECOMMENDATIONS: u32 = 24;
+
+/// The factor by which the price of a related product may differ from the price of the product in the first search pass.
+const PRICE_BAND: f32 = 2.0;
+
+/// The number of products stored as bought together with each product.
+const MAX_BOUGHT_TOGETHER: i64 = MAX_RECOMMENDATIONS as i64;
+
+/// The minimum number of sessions two products must be purchased in together to be suggested.
+const MIN_CO_PURCHASES: i64 = 2;
+
+/// The maximum number of distinct products purchased in a session counted as a basket, larger sessions being bulk orders.
+const MAX_BASKET: i64 = 50;
+
+/// A search hit, of which only the product ID is retrieved.
+#[derive(Deserialize)]
+struct SearchHit {
+    pid: String,
+}
+
+/// Retrieve the recommendations of a specific product.
+///
+/// This function searches for related products among the active products of the same category whose name or description shares terms with the product name, first within a price band around the product price and then at any price if too few products were found. It also retrieves the active products most often bought together with the product, as computed by `compute_bought_together`.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID to retrieve recommendations for.
+/// - `query`: Query parameters containing the optional number of products of each list.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for searching.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing an `Option<Recommendations>`, where `None` indicates that the product was not found. A bad request `Error` is returned if the limit is invalid, and an `Error` in case of a database or search error.
+pub async fn retrieve_recommendations(
+    pid: String,
+    query: RecommendationsQuery,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<Option<Recommendations>, Error> {
+    let limit = query.limit.unwrap_or(DEFAULT_RECOMMENDATIONS);
+
+    if limit == 0 || limit > MAX_RECOMMENDATIONS {
+        return Err(error::ErrorBadRequest(format!(
+            "Limit must be between 1 and {}",
+            MAX_RECOMMENDATIONS
+        )));
+    }
+
+    let database = client.database("ecommerce_db");
+    let mut product = match database
+        .collection::<Product>("products")
+        .find_one(doc! { "pid": pid.clone() }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+    {
+        Some(product) => product,
+        None => return Ok(None),
+    };
+
+    product.aggregate();
+
+    let related = related_products(&product, limit as usize, &client, &ms_client).await?;
+
+    let suggestions = database
+        .collection::<BoughtTogether>("bought_together")
+        .find_one(doc! { "pid": pid }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let pids: Vec<String> = suggestions
+        .map(|suggestions| {
+            suggestions
+                .products
+                .into_iter()
+                .map(|product| product.pid)
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut bought_together = active_product_views(&pids, &client).await?;
+    bought_together.truncate(limit as usize);
+
+    Ok(Some(Recommendations {
+        related,
+        bought_together,
+    }))
+}
+
+/// Search for the products related to a product.
+async fn related_products(
+    product: &Product,
+    limit: usize,
+    client: &Client,
+    ms_client: &meilisearch_sdk::Client,
+) -> Result<Vec<ProductView>, Error> {
+    let filter = format!(
+        "status = {} AND category = {} AND pid != {}",
+        filter_value(ProductStatus::Active.as_str()),
+        filter_value(&product.category),
+        filter_value(&product.pid)
+    );
+    let banded = format!(
+        "{} AND price >= {} AND price <= {}",
+        filter,
+        product.price / PRICE_BAND,
+        product.price * PRICE_BAND
+    );
+
+    let mut pids = search_pids(&product.name, &banded, limit, ms_client).await?;
+
+    if pids.len() < limit {
+        for pid in search_pids(&product.name, &filter, limit, ms_client).await? {
+            if pids.len() < limit && !pids.contains(&pid) {
+                pids.push(pid);
+            }
+        }
+    }
+
+    active_product_views(&pids, client).await
+}
+
+/// Search for the IDs of the products matching a query and a filter, most relevant first.
+async fn search_pids(
+    query: &str,
+    filter: &str,
+    limit: usize,
+    ms_client: &meilisearch_sdk::Client,
+) -> Result<Vec<String>, Error> {
+    let index = ms_client.index("products");
+    let mut search = index.search();
+
+    search
+        .with_query(query)
+        .with_filter(filter)
+        .with_limit(limit)
+        .with_attributes_to_retrieve(Selectors::Some(&["pid"]));
+
+    let results: SearchResults<SearchHit> = search
+        .execute()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(results.hits.into_iter().map(|hit| hit.result.pid).collect())
+}
+
+/// Compute the products frequently bought together with each product.
+///
+/// This function groups the purchases of the last `window` days by session, counts the sessions in which each pair of products was purchased together, and stores for every product the products purchased together with it in at least `MIN_CO_PURCHASES` sessions, most often bought together first. Sessions with a single product or with more than `MAX_BASKET` products are ignored, and the suggestions of products no longer bought together with any product are removed.
+///
+/// # Parameters
+///
+/// - `window`: The number of days of purchases counted.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the number of products with suggestions, or an `Error` in case of a database error.
+pub async fn compute_bought_together(window: i64, client: &Client) -> Result<u64, Error> {
+    let database = client.database("ecommerce_db");
+    let now = DateTime::now().timestamp_millis();
+
+    let pipeline = vec![
+        doc! {
+            "$match": {
+                "kind": EventKind::Purchase.as_str(),
+                "timestamp": { "$gte": now - window * Granularity::Day.millis() },
+                "session": { "$type": "string" },
+            }
+        },
+        doc! { "$group": { "_id": "$session", "pids": { "$addToSet": "$pid" } } },
+        doc! {
+            "$match": {
+                "$expr": {
+                    "$and": [
+                        { "$gte": [{ "$size": "$pids" }, 2] },
+                        { "$lte": [{ "$size": "$pids" }, MAX_BASKET] },
+                    ]
+                }
+            }
+        },
+        // Pair every product of a basket with every other product of the same basket.
+        doc! { "$project": { "_id": 0, "pid": "$pids", "other": "$pids" } },
+        doc! { "$unwind": "$pid" },
+        doc! { "$unwind": "$other" },
+        doc! { "$match": { "$expr": { "$ne": ["$pid", "$other"] } } },
+        doc! { "$group": { "_id": { "pid": "$pid", "other": "$other" }, "count": { "$sum": 1 } } },
+        doc! { "$match": { "count": { "$gte": MIN_CO_PURCHASES } } },
+        doc! { "$sort": { "_id.pid": 1, "count": -1, "_id.other": 1 } },
+        doc! {
+            "$group": {
+                "_id": "$_id.pid",
+                "products": { "$push": { "pid": "$_id.other", "count": "$count" } },
+            }
+        },
+        doc! {
+            "$project": {
+                "_id": 0,
+                "pid": "$_id",
+                "products": { "$slice": ["$products", MAX_BOUGHT_TOGETHER] },
+                "computed_at": now,
+            }
+        },
+        doc! {
+            "$merge": {
+                "into": "bought_together",
+                "on": "pid",
+                "whenMatched": "replace",
+                "whenNotMatched": "insert",
+            }
+        },
+    ];
+    let options = AggregateOptions::builder().allow_disk_use(true).build();
+
+    database
+        .collection::<Document>("analytics_events")
+        .aggregate(pipeline, options)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect::<Vec<Document>>()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    let suggestions = database.collection::<BoughtTogether>("bought_together");
+
+    suggestions
+        .delete_many(doc! { "computed_at": { "$lt": now } }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    suggestions
+        .count_documents(None, None)
+        .await
+        .map_err(error::ErrorInternalServerError)
+}
@@ -0,0 +1,393 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::product;
+use crate::routes::audit::Actor;
+use crate::structures::{
+    Product, ProductStatus, RestockSubscription, StockAlert, StockAlertKind, StockLevel, StockState,
+};
+
+use actix_web::{error, Error};
+use futures::TryStreamExt;
+use log::{error, info};
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+
+/// The default stock level at or below which products are low on stock.
+const DEFAULT_LOW_STOCK_THRESHOLD: u32 = 5;
+
+/// The maximum number of stock levels written in a single database command.
+const STOCK_BATCH_SIZE: usize = 500;
+
+/// The number of seconds a stock alert webhook has to answer.
+const WEBHOOK_TIMEOUT: u64 = 10;
+
+/// The stock figures of a listed product.
+#[derive(Deserialize)]
+struct StockProduct {
+    pid: String,
+
+    sid: String,
+
+    name: String,
+
+    stock: u32,
+
+    #[serde(default)]
+    low_stock_threshold: Option<u32>,
+}
+
+/// Set the low stock threshold of a product.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID to set the threshold for.
+/// - `threshold`: The stock level at or below which the product is low on stock, or `None` for the default threshold.
+/// - `actor`: The actor performing the modification, recorded in the audit log.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+///
+/// # Returns
+///
+/// - Returns `true` if the threshold is successfully set, `false` if the product does not exist, and an `Error` in case of a database or indexing error.
+pub async fn update_threshold(
+    pid: String,
+    threshold: Option<u32>,
+    actor: Actor,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) -> Result<bool, Error> {
+    let collection = client
+        .database("ecommerce_db")
+        .collection::<Product>("products");
+
+    let mut product = match collection
+        .find_one(doc! { "pid": pid }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+    {
+        Some(product) => product,
+        None => return Ok(false),
+    };
+
+    product.low_stock_threshold = threshold;
+    product::replace_product(product, &actor, &client, &ms_client).await?;
+
+    Ok(true)
+}
+
+/// Subscribe a shopper to be notified when an out of stock product is back in stock.
+///
+/// Subscribing twice to the same product keeps a single subscription.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID to subscribe to.
+/// - `uid`: The user ID of the shopper.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns `true` if the shopper is subscribed, `false` if the product is not listed, a bad request `Error` if the user ID is empty or the product is in stock, and an `Error` in case of a database error.
+pub async fn subscribe(pid: String, uid: String, client: Client) -> Result<bool, Error> {
+    if uid.is_empty() {
+        return Err(error::ErrorBadRequest("User ID must not be empty"));
+    }
+
+    let database = client.database("ecommerce_db");
+    let product = database
+        .collection::<Product>("products")
+        .find_one(doc! { "pid": pid.clone() }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    match product {
+        Some(product)
+            if matches!(
+                product.status,
+                ProductStatus::Active | ProductStatus::OutOfStock
+            ) =>
+        {
+            if product.stock > 0 {
+                return Err(error::ErrorBadRequest("Product is in stock"));
+            }
+        }
+        _ => return Ok(false),
+    }
+
+    let options = UpdateOptions::builder().upsert(true).build();
+
+    database
+        .collection::<RestockSubscription>("restock_subscriptions")
+        .update_one(
+            doc! { "pid": pid, "uid": uid },
+            doc! { "$setOnInsert": { "created_at": DateTime::now().timestamp_millis() } },
+            options,
+        )
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(true)
+}
+
+/// Unsubscribe a shopper from the back in stock notification of a product.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID to unsubscribe from.
+/// - `uid`: The user ID of the shopper.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns `true` if the subscription was removed, `false` if there was none, and an `Error` in case of a database error.
+pub async fn unsubscribe(pid: String, uid: String, client: Client) -> Result<bool, Error> {
+    let result = client
+        .database("ecommerce_db")
+        .collection::<RestockSubscription>("restock_subscriptions")
+        .delete_one(doc! { "pid": pid, "uid": uid }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(result.deleted_count > 0)
+}
+
+/// Check the stock levels of the listed products and deliver the resulting alerts.
+///
+/// This function compares the stock level of every listed product with the level seen by the previous check, and emits an alert when a product crosses below its low stock threshold, runs out of stock, or is back in stock after running out. Back in stock alerts carry the subscribed shoppers, whose subscriptions are removed once the alert is delivered. The new level of a product is only recorded once its alert is delivered to every URL, so an alert that failed is emitted again by the next check. Products seen for the first time only have their level recorded.
+///
+/// Alerts are posted as JSON to every URL of the comma separated `STOCK_ALERT_WEBHOOKS` environment variable, and only logged if there is none. The default low stock threshold is read from the `LOW_STOCK_THRESHOLD` environment variable.
+///
+/// # Parameters
+///
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the number of alerts, or an `Error` in case of a database error. Failed deliveries are logged and retried by the next check.
+pub async fn check_stock(client: &Client) -> Result<usize, Error> {
+    let database = client.database("ecommerce_db");
+    let now = DateTime::now().timestamp_millis();
+    let default_threshold = env::var("LOW_STOCK_THRESHOLD")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_LOW_STOCK_THRESHOLD);
+
+    let options = FindOptions::builder()
+        .projection(doc! { "pid": 1, "sid": 1, "name": 1, "stock": 1, "low_stock_threshold": 1 })
+        .build();
+    let products: Vec<StockProduct> = database
+        .collection::<StockProduct>("products")
+        .find(
+            doc! {
+                "status": {
+                    "$in": [ProductStatus::Active.as_str(), ProductStatus::OutOfStock.as_str()]
+                }
+            },
+            options,
+        )
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    let states: HashMap<String, StockLevel> = database
+        .collection::<StockState>("stock_levels")
+        .find(None, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .map_ok(|state| (state.pid, state.level))
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    let mut changes: Vec<(String, StockLevel)> = Vec::new();
+    let mut alerts: Vec<(StockAlert, StockLevel)> = Vec::new();
+
+    for product in products {
+        let threshold = product.low_stock_threshold.unwrap_or(default_threshold);
+        let level = StockLevel::of(product.stock, threshold);
+        let previous = states.get(&product.pid).copied();
+
+        if previous == Some(level) {
+            continue;
+        }
+
+        match alert_kind(previous, level) {
+            Some(kind) => alerts.push((
+                StockAlert {
+                    kind,
+                    pid: product.pid,
+                    sid: product.sid,
+                    name: product.name,
+                    stock: product.stock,
+                    threshold,
+                    subscribers: Vec::new(),
+                    timestamp: now,
+                },
+                level,
+            )),
+            None => changes.push((product.pid, level)),
+        }
+    }
+
+    let subscriptions = database.collection::<RestockSubscription>("restock_subscriptions");
+
+    for (alert, _) in alerts
+        .iter_mut()
+        .filter(|(alert, _)| alert.kind == StockAlertKind::Restocked)
+    {
+        alert.subscribers = subscriptions
+            .find(doc! { "pid": alert.pid.clone() }, None)
+            .await
+            .map_err(error::ErrorInternalServerError)?
+            .map_ok(|subscription| subscription.uid)
+            .try_collect()
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+    }
+
+    let urls: Vec<String> = env::var("STOCK_ALERT_WEBHOOKS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect();
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(WEBHOOK_TIMEOUT))
+        .build()
+        .map_err(error::ErrorInternalServerError)?;
+    let count = alerts.len();
+
+    for (alert, level) in alerts {
+        let mut delivered = true;
+
+        if urls.is_empty() {
+            info!("Stock alert {:?} for product {}", alert.kind, alert.pid);
+        }
+
+        for url in &urls {
+            let result = http
+                .post(url)
+                .json(&alert)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+
+            if let Err(error) = result {
+                error!(
+                    "Failed to deliver the stock alert of product {} to {}: {}",
+                    alert.pid, url, error
+                );
+                delivered = false;
+            }
+        }
+
+        // The level of an undelivered alert is not recorded, so the next check emits the alert again and shoppers stay subscribed until they are notified.
+        if !delivered {
+            continue;
+        }
+
+        if !alert.subscribers.is_empty() {
+            subscriptions
+                .delete_many(
+                    doc! { "pid": alert.pid.clone(), "uid": { "$in": &alert.subscribers } },
+                    None,
+                )
+                .await
+                .map_err(error::ErrorInternalServerError)?;
+        }
+
+        changes.push((alert.pid, level));
+    }
+
+    for batch in changes.chunks(STOCK_BATCH_SIZE) {
+        let updates: Vec<Document> = batch
+            .iter()
+            .map(|(pid, level)| {
+                doc! {
+                    "q": { "pid": pid.clone() },
+                    "u": { "pid": pid.clone(), "level": level.as_str(), "since": now },
+                    "upsert": true,
+                }
+            })
+            .collect();
+
+        database
+            .run_command(doc! { "update": "stock_levels", "updates": updates }, None)
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+    }
+
+    Ok(count)
+}
+
+/// The kind of alert emitted when a product moves from its previous stock level to a new one, if any.
+///
+/// Products seen for the first time have no previous level and emit no alert.
+fn alert_kind(previous: Option<StockLevel>, level: StockLevel) -> Option<StockAlertKind> {
+    match (previous?, level) {
+        (previous, level) if previous == level => None,
+        (StockLevel::InStock, StockLevel::Low) => Some(StockAlertKind::Low),
+        (_, StockLevel::Out) => Some(StockAlertKind::Out),
+        (StockLevel::Out, _) => Some(StockAlertKind::Restocked),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test;
+
+    #[test]
+    fn stock_level_follows_the_threshold() {
+        assert_eq!(StockLevel::of(0, 5), StockLevel::Out);
+        assert_eq!(StockLevel::of(1, 5), StockLevel::Low);
+        assert_eq!(StockLevel::of(5, 5), StockLevel::Low);
+        assert_eq!(StockLevel::of(6, 5), StockLevel::InStock);
+        assert_eq!(StockLevel::of(0, 0), StockLevel::Out);
+        assert_eq!(StockLevel::of(1, 0), StockLevel::InStock);
+    }
+
+    #[test]
+    fn first_seen_products_emit_no_alert() {
+        for level in [StockLevel::InStock, StockLevel::Low, StockLevel::Out] {
+            assert_eq!(alert_kind(None, level), None);
+        }
+    }
+
+    #[test]
+    fn unchanged_levels_emit_no_alert() {
+        for level in [StockLevel::InStock, StockLevel::Low, StockLevel::Out] {
+            assert_eq!(alert_kind(Some(level), level), None);
+        }
+    }
+
+    #[test]
+    fn level_transitions_emit_their_alert() {
+        use StockLevel::{InStock, Low, Out};
+
+        let transitions = [
+            (InStock, Low, Some(StockAlertKind::Low)),
+            (InStock, Out, Some(StockAlertKind::Out)),
+            (Low, Out, Some(StockAlertKind::Out)),
+            (Out, Low, Some(StockAlertKind::Restocked)),
+            (Out, InStock, Some(StockAlertKind::Restocked)),
+            (Low, InStock, None),
+        ];
+
+        for (previous, level, kind) in transitions {
+            assert_eq!(alert_kind(Some(previous), level), kind);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::analytics::{CLICK_WEIGHT, PURCHASE_WEIGHT};
+use crate::crud::product::product_view;
+use crate::routes::product::ProductView;
+use crate::routes::trending::ListQuery;
+use crate::structures::{Granularity, Product, ProductStatus, Rollup};
+use crate::trending::{ListCache, ListKind, ProductLists};
+
+use actix_web::{error, Error};
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The number of products of a list if no limit is given.
+const DEFAULT_LIST_SIZE: u32 = 10;
+
+/// The maximum number of products of a list, which is also the number of products ranked per category.
+const MAX_LIST_SIZE: u32 = 50;
+
+/// The number of hours of clicks and purchases counted in the trending score.
+const TRENDING_WINDOW: i64 = 24;
+
+/// The number of days of purchases counted in the best seller score.
+const BESTSELLER_WINDOW: i64 = 7;
+
+/// The recent scores of a product.
+#[derive(Deserialize)]
+struct Scores {
+    #[serde(rename = "_id")]
+    pid: String,
+
+    trending: f64,
+
+    sales: f64,
+}
+
+/// Retrieve a homepage product list.
+///
+/// This function serves the list from the cache, and only computes the lists if the cache is empty or expired, such as before the first background refresh.
+///
+/// # Parameters
+///
+/// - `kind`: The list to retrieve.
+/// - `query`: Query parameters containing the optional category and number of products.
+/// - `cache`: The cache of the product lists.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the ranked product views, or a bad request `Error` if the limit is invalid, and an `Error` in case of a database query error.
+pub async fn retrieve_list(
+    kind: ListKind,
+    query: ListQuery,
+    cache: &ListCache,
+    client: &Client,
+) -> Result<Vec<ProductView>, Error> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_SIZE);
+
+    if limit == 0 || limit > MAX_LIST_SIZE {
+        return Err(error::ErrorBadRequest(format!(
+            "Limit must be between 1 and {}",
+            MAX_LIST_SIZE
+        )));
+    }
+
+    let lists = match cache.get() {
+        Some(lists) => lists,
+        None => refresh_lists(cache, client).await?,
+    };
+
+    Ok(lists
+        .get(kind, &query.category)
+        .iter()
+        .take(limit as usize)
+        .cloned()
+        .collect())
+}
+
+/// Compute the homepage product lists and store them in the cache.
+///
+/// This function ranks the active products by their clicks and purchases over the last `TRENDING_WINDOW` hours for the trending list, and by their purchases over the last `BESTSELLER_WINDOW` days for the best seller list, reading both from the analytics rollups in a single aggregation. The top `MAX_LIST_SIZE` products are kept for every category and for the whole catalog, products with equal scores being ordered by product ID.
+///
+/// # Parameters
+///
+/// - `cache`: The cache of the product lists.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the computed lists, or an `Error` in case of a database query error.
+pub async fn refresh_lists(cache: &ListCache, client: &Client) -> Result<Arc<ProductLists>, Error> {
+    let database = client.database("ecommerce_db");
+    let now = DateTime::now().timestamp_millis();
+    let hour = Granularity::Hour;
+    let day = Granularity::Day;
+    let is_hour = doc! { "$eq": ["$granularity", hour.as_str()] };
+    let is_day = doc! { "$eq": ["$granularity", day.as_str()] };
+    let trending = doc! {
+        "$add": [
+            { "$multiply": [{ "$ifNull": ["$counts.click", 0] }, CLICK_WEIGHT] },
+            { "$multiply": [{ "$ifNull": ["$counts.purchase", 0] }, PURCHASE_WEIGHT] },
+        ]
+    };
+    let sales = doc! { "$toDouble": { "$ifNull": ["$counts.purchase", 0] } };
+
+    let pipeline = vec![
+        doc! {
+            "$match": {
+                "$or": [
+                    {
+                        "granularity": hour.as_str(),
+                        "start": { "$gte": hour.bucket(now) - (TRENDING_WINDOW - 1) * hour.millis() },
+                    },
+                    {
+                        "granularity": day.as_str(),
+                        "start": { "$gte": day.bucket(now) - (BESTSELLER_WINDOW - 1) * day.millis() },
+                    },
+                ]
+            }
+        },
+        doc! {
+            "$group": {
+                "_id": "$pid",
+                "trending": { "$sum": { "$cond": [is_hour, trending, 0.0] } },
+                "sales": { "$sum": { "$cond": [is_day, sales, 0.0] } },
+            }
+        },
+        doc! { "$match": { "$or": [{ "trending": { "$gt": 0 } }, { "sales": { "$gt": 0 } }] } },
+    ];
+
+    let scores: HashMap<String, Scores> = database
+        .collection::<Rollup>("analytics_rollups")
+        .aggregate(pipeline, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .map_err(error::ErrorInternalServerError)
+        .and_then(|group| async move {
+            mongodb::bson::from_document::<Scores>(group)
+                .map(|scores| (scores.pid.clone(), scores))
+                .map_err(error::ErrorInternalServerError)
+        })
+        .try_collect()
+        .await?;
+
+    let pids: Vec<&String> = scores.keys().collect();
+    let mut products: Vec<Product> = database
+        .collection::<Product>("products")
+        .find(doc! { "pid": { "$in": pids } }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    // Products that ran out of stock since their status was stored are not listed.
+    for product in products.iter_mut() {
+        product.aggregate();
+    }
+
+    products.retain(|product| product.status == ProductStatus::Active);
+
+    let lists = Arc::new(ProductLists {
+        trending: rank(&products, |product| scores[&product.pid].trending),
+        bestsellers: rank(&products, |product| scores[&product.pid].sales),
+        computed_at: now,
+    });
+
+    cache.set(lists.clone());
+
+    Ok(lists)
+}
+
+/// Rank the products with a positive score, keeping the top products of every category and of the whole catalog.
+fn rank(
+    products: &[Product],
+    score: impl Fn(&Product) -> f64,
+) -> HashMap<String, Vec<ProductView>> {
+    let mut ranked: Vec<(f64, &Product)> = products
+        .iter()
+        .map(|product| (score(product), product))
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.pid.cmp(&b.1.pid)));
+
+    let mut lists: HashMap<String, Vec<ProductView>> = HashMap::new();
+
+    for (_, product) in ranked {
+        let mut categories = vec![String::new()];
+
+        if !product.category.is_empty() {
+            categories.push(product.category.clone());
+        }
+
+        for category in categories {
+            let list = lists.entry(category).or_default();
+
+            if list.len() < MAX_LIST_SIZE as usize {
+                list.push(product_view(product));
+            }
+        }
+    }
+
+    lists
+}
@@ -0,0 +1,777 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::product::product_view;
+use crate::routes::product::ProductView;
+use crate::routes::webhooks::{DeadLetterQuery, WebhookRequest, WebhookView};
+use crate::structures::{
+    Product, ProductStatus, WebhookDelivery, WebhookEvent, WebhookSubscription,
+};
+
+use actix_web::{error, Error};
+use futures::{StreamExt, TryStreamExt};
+use hmac::{Hmac, Mac};
+use log::error;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions};
+use mongodb::Client;
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+
+/// The maximum number of dead letters per page.
+const PAGE_SIZE: i64 = 20;
+
+/// The default number of delivery attempts before a delivery is dead-lettered.
+const DEFAULT_MAX_ATTEMPTS: u32 = 10;
+
+/// The default number of seconds before the first retry of a failed delivery, doubled on every further retry.
+const DEFAULT_BACKOFF: i64 = 30;
+
+/// The maximum number of milliseconds between two delivery attempts.
+const MAX_BACKOFF: i64 = 6 * 60 * 60 * 1000;
+
+/// The maximum number of deliveries attempted in a single run.
+const DELIVERY_BATCH_SIZE: usize = 100;
+
+/// The number of deliveries attempted concurrently.
+const DELIVERY_CONCURRENCY: usize = 8;
+
+/// The number of milliseconds a claimed delivery is withheld from other runs while it is attempted.
+const DELIVERY_LEASE: i64 = 5 * 60 * 1000;
+
+/// The number of seconds a webhook has to answer.
+const WEBHOOK_TIMEOUT: u64 = 10;
+
+/// The events emitted for a product, with the product after and, for updates, before the events.
+type Emission<'a> = (Vec<WebhookEvent>, &'a Product, Option<&'a Product>);
+
+/// The body of a webhook delivery.
+#[derive(Serialize)]
+struct WebhookPayload {
+    /// The event ID, shared by the deliveries of the event to every subscription.
+    id: String,
+
+    /// The event.
+    event: WebhookEvent,
+
+    /// The time the event was emitted in milliseconds since the Unix epoch.
+    timestamp: i64,
+
+    /// The product after the event.
+    product: ProductView,
+
+    /// The product before the event, for updates.
+    previous: Option<ProductView>,
+}
+
+/// The retry settings of the webhook deliveries.
+struct RetrySettings {
+    /// The number of delivery attempts before a delivery is dead-lettered.
+    max_attempts: u32,
+
+    /// The number of milliseconds before the first retry.
+    backoff: i64,
+}
+
+impl RetrySettings {
+    /// Read the settings from the `WEBHOOK_MAX_ATTEMPTS` and `WEBHOOK_BACKOFF` (in seconds) environment variables.
+    fn from_env() -> Self {
+        let backoff = env::var("WEBHOOK_BACKOFF")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|seconds| *seconds > 0)
+            .unwrap_or(DEFAULT_BACKOFF);
+
+        RetrySettings {
+            max_attempts: env::var("WEBHOOK_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .filter(|attempts| *attempts > 0)
+                .unwrap_or(DEFAULT_MAX_ATTEMPTS),
+            backoff: backoff * 1000,
+        }
+    }
+
+    /// The number of milliseconds to wait before the next attempt after the given number of failed attempts.
+    fn delay(&self, attempts: u32) -> i64 {
+        let factor = 2_i64
+            .checked_pow(attempts.saturating_sub(1))
+            .unwrap_or(i64::MAX);
+
+        self.backoff.saturating_mul(factor).min(MAX_BACKOFF)
+    }
+}
+
+/// Subscribe a module to product lifecycle events.
+///
+/// # Parameters
+///
+/// - `request`: The URL, secret and events of the subscription.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the created subscription without its secret, a bad request `Error` if the URL is not an HTTP URL, the secret is empty or no event is given, and an `Error` in case of a database error.
+pub async fn create_subscription(
+    request: WebhookRequest,
+    client: Client,
+) -> Result<WebhookView, Error> {
+    match reqwest::Url::parse(&request.url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+        _ => return Err(error::ErrorBadRequest("URL must be an HTTP or HTTPS URL")),
+    }
+
+    if request.secret.is_empty() {
+        return Err(error::ErrorBadRequest("Secret must not be empty"));
+    }
+
+    let mut events: Vec<WebhookEvent> = Vec::new();
+
+    for event in request.events {
+        if !events.contains(&event) {
+            events.push(event);
+        }
+    }
+
+    if events.is_empty() {
+        return Err(error::ErrorBadRequest("At least one event must be given"));
+    }
+
+    let subscription = WebhookSubscription {
+        id: ObjectId::new().to_hex(),
+        url: request.url,
+        secret: request.secret,
+        events,
+        created_at: DateTime::now().timestamp_millis(),
+    };
+
+    client
+        .database("ecommerce_db")
+        .collection::<WebhookSubscription>("webhook_subscriptions")
+        .insert_one(subscription.clone(), None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(webhook_view(subscription))
+}
+
+/// Retrieve the webhook subscriptions.
+///
+/// # Parameters
+///
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the subscriptions without their secrets, oldest first, or an `Error` in case of a database query error.
+pub async fn retrieve_subscriptions(client: Client) -> Result<Vec<WebhookView>, Error> {
+    let options = FindOptions::builder()
+        .sort(doc! { "created_at": 1 })
+        .build();
+
+    client
+        .database("ecommerce_db")
+        .collection::<WebhookSubscription>("webhook_subscriptions")
+        .find(None, options)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .map_ok(webhook_view)
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)
+}
+
+/// Delete a webhook subscription along with its pending deliveries.
+///
+/// # Parameters
+///
+/// - `id`: The subscription ID.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns `true` if the subscription was deleted, `false` if it does not exist, and an `Error` in case of a database error.
+pub async fn delete_subscription(id: String, client: Client) -> Result<bool, Error> {
+    let database = client.database("ecommerce_db");
+    let result = database
+        .collection::<WebhookSubscription>("webhook_subscriptions")
+        .delete_one(doc! { "id": id.clone() }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    database
+        .collection::<WebhookDelivery>("webhook_deliveries")
+        .delete_many(doc! { "subscription": id }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(result.deleted_count > 0)
+}
+
+/// Retrieve the deliveries given up after their last attempt failed.
+///
+/// # Parameters
+///
+/// - `query`: Query parameters containing the page number.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the dead letters of the page, most recently given up first, or an `Error` in case of a database query error.
+pub async fn retrieve_dead_letters(
+    query: DeadLetterQuery,
+    client: Client,
+) -> Result<Vec<WebhookDelivery>, Error> {
+    let options = FindOptions::builder()
+        .sort(doc! { "failed_at": -1 })
+        .skip(query.page.saturating_sub(1) as u64 * PAGE_SIZE as u64)
+        .limit(PAGE_SIZE)
+        .build();
+
+    client
+        .database("ecommerce_db")
+        .collection::<WebhookDelivery>("webhook_dead_letters")
+        .find(None, options)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)
+}
+
+/// Queue a dead letter for delivery again, with a fresh number of attempts.
+///
+/// # Parameters
+///
+/// - `id`: The delivery ID.
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns `true` if the delivery is queued, `false` if there is no such dead letter, a bad request `Error` if its subscription was deleted, and an `Error` in case of a database error.
+pub async fn retry_dead_letter(id: String, client: Client) -> Result<bool, Error> {
+    let database = client.database("ecommerce_db");
+    let dead_letters = database.collection::<WebhookDelivery>("webhook_dead_letters");
+
+    let mut delivery = match dead_letters
+        .find_one(doc! { "id": id.clone() }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+    {
+        Some(delivery) => delivery,
+        None => return Ok(false),
+    };
+
+    let subscription = database
+        .collection::<WebhookSubscription>("webhook_subscriptions")
+        .find_one(doc! { "id": delivery.subscription.clone() }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    if subscription.is_none() {
+        return Err(error::ErrorBadRequest("Subscription was deleted"));
+    }
+
+    delivery.attempts = 0;
+    delivery.next_attempt = DateTime::now().timestamp_millis();
+    delivery.failed_at = None;
+
+    database
+        .collection::<WebhookDelivery>("webhook_deliveries")
+        .insert_one(delivery, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    dead_letters
+        .delete_one(doc! { "id": id }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(true)
+}
+
+/// Queue the delivery of product lifecycle events to the subscriptions of each event.
+///
+/// The deliveries are stored before they are attempted, so events emitted while a subscriber is unreachable are delivered once it is back. Events are emitted after the product is stored, so a failure to queue them is logged instead of failing the modification.
+///
+/// # Parameters
+///
+/// - `events`: The emitted events.
+/// - `product`: The product after the events.
+/// - `previous`: The product before the events, for updates.
+/// - `client`: MongoDB client instance used for database access.
+pub async fn emit(
+    events: &[WebhookEvent],
+    product: &Product,
+    previous: Option<&Product>,
+    client: &Client,
+) {
+    if let Err(error) = queue(&[(events.to_vec(), product, previous)], client).await {
+        error!(
+            "Failed to queue the webhook events of product {}: {}",
+            product.pid, error
+        );
+    }
+}
+
+/// Queue the delivery of the events of a product modification.
+///
+/// This function emits `product.delisted` if the product was archived and `product.updated` otherwise, along with `stock.changed` and `price.changed` if the stock level or any price of the product or its variants changed.
+///
+/// # Parameters
+///
+/// - `before`: The product before the modification.
+/// - `after`: The product after the modification.
+/// - `client`: MongoDB client instance used for database access.
+pub async fn emit_changes(before: &Product, after: &Product, client: &Client) {
+    emit(&change_events(before, after), after, Some(before), client).await
+}
+
+/// Queue the delivery of the events of many product modifications at once, as for `emit_changes`.
+///
+/// # Parameters
+///
+/// - `changes`: The products before and after their modification.
+/// - `client`: MongoDB client instance used for database access.
+pub async fn emit_changes_many(changes: &[(Product, Product)], client: &Client) {
+    let emissions: Vec<Emission> = changes
+        .iter()
+        .map(|(before, after)| (change_events(before, after), after, Some(before)))
+        .collect();
+
+    if let Err(error) = queue(&emissions, client).await {
+        error!(
+            "Failed to queue the webhook events of {} products: {}",
+            changes.len(),
+            error
+        );
+    }
+}
+
+/// The events of a product modification.
+fn change_events(before: &Product, after: &Product) -> Vec<WebhookEvent> {
+    let mut events = Vec::new();
+
+    if after.status == ProductStatus::Archived && before.status != ProductStatus::Archived {
+        events.push(WebhookEvent::ProductDelisted);
+    } else {
+        events.push(WebhookEvent::ProductUpdated);
+    }
+
+    let variant_stocks = |product: &Product| -> Vec<(String, u32)> {
+        product
+            .variants
+            .iter()
+            .map(|variant| (variant.sku.clone(), variant.stock))
+            .collect()
+    };
+
+    if before.stock != after.stock || variant_stocks(before) != variant_stocks(after) {
+        events.push(WebhookEvent::StockChanged);
+    }
+
+    let prices = |product: &Product| {
+        (
+            product.price,
+            product.compare_at,
+            product.min_price,
+            product.max_price,
+            product
+                .variants
+                .iter()
+                .map(|variant| (variant.sku.clone(), variant.price))
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    if prices(before) != prices(after) {
+        events.push(WebhookEvent::PriceChanged);
+    }
+
+    events
+}
+
+/// Store the deliveries of the events of one or more products, looking up the subscriptions and inserting the deliveries once.
+async fn queue(emissions: &[Emission<'_>], client: &Client) -> Result<(), Error> {
+    let database = client.database("ecommerce_db");
+    let mut names: Vec<&str> = emissions
+        .iter()
+        .flat_map(|(events, _, _)| events.iter().map(WebhookEvent::as_str))
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    let subscriptions: Vec<WebhookSubscription> = database
+        .collection::<WebhookSubscription>("webhook_subscriptions")
+        .find(doc! { "events": { "$in": names } }, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    if subscriptions.is_empty() {
+        return Ok(());
+    }
+
+    let now = DateTime::now().timestamp_millis();
+    let mut deliveries: Vec<WebhookDelivery> = Vec::new();
+
+    for (events, product, previous) in emissions {
+        for event in events {
+            let payload = WebhookPayload {
+                id: ObjectId::new().to_hex(),
+                event: *event,
+                timestamp: now,
+                product: product_view(product),
+                previous: previous.map(product_view),
+            };
+            let payload =
+                serde_json::to_string(&payload).map_err(error::ErrorInternalServerError)?;
+
+            for subscription in subscriptions
+                .iter()
+                .filter(|subscription| subscription.events.contains(event))
+            {
+                deliveries.push(WebhookDelivery {
+                    id: ObjectId::new().to_hex(),
+                    subscription: subscription.id.clone(),
+                    pid: product.pid.clone(),
+                    url: subscription.url.clone(),
+                    event: *event,
+                    payload: payload.clone(),
+                    attempts: 0,
+                    next_attempt: now,
+                    last_error: None,
+                    created_at: now,
+                    failed_at: None,
+                });
+            }
+        }
+    }
+
+    if deliveries.is_empty() {
+        return Ok(());
+    }
+
+    database
+        .collection::<WebhookDelivery>("webhook_deliveries")
+        .insert_many(deliveries, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(())
+}
+
+/// Attempt the webhook deliveries that are due.
+///
+/// This function claims the due deliveries, posts them to their subscriptions, and removes the successful ones. A failed delivery is retried after a delay read from the `WEBHOOK_BACKOFF` environment variable in seconds, doubled after every further failure, and moved to the dead letters after `WEBHOOK_MAX_ATTEMPTS` attempts. Deliveries of deleted subscriptions are dropped.
+///
+/// Every delivery is signed with the secret of its subscription: the `X-Webhook-Signature` header holds `sha256=` followed by the hexadecimal HMAC-SHA256 of the `X-Webhook-Timestamp` header, a dot and the body.
+///
+/// # Parameters
+///
+/// - `client`: MongoDB client instance used for database access.
+///
+/// # Returns
+///
+/// - Returns a `Result` containing the number of successful deliveries, or an `Error` in case of a database error.
+pub async fn deliver_webhooks(client: &Client) -> Result<usize, Error> {
+    let settings = RetrySettings::from_env();
+    let database = client.database("ecommerce_db");
+    let deliveries = database.collection::<WebhookDelivery>("webhook_deliveries");
+    let now = DateTime::now().timestamp_millis();
+
+    // Claimed deliveries are pushed back by the lease, so a concurrent run does not attempt them twice.
+    let mut claimed: Vec<WebhookDelivery> = Vec::new();
+    let options = FindOneAndUpdateOptions::builder()
+        .sort(doc! { "next_attempt": 1 })
+        .build();
+
+    while claimed.len() < DELIVERY_BATCH_SIZE {
+        let delivery = deliveries
+            .find_one_and_update(
+                doc! { "next_attempt": { "$lte": now } },
+                doc! { "$set": { "next_attempt": now + DELIVERY_LEASE } },
+                options.clone(),
+            )
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+
+        match delivery {
+            Some(delivery) => claimed.push(delivery),
+            None => break,
+        }
+    }
+
+    if claimed.is_empty() {
+        return Ok(0);
+    }
+
+    let subscriptions: HashMap<String, WebhookSubscription> = database
+        .collection::<WebhookSubscription>("webhook_subscriptions")
+        .find(None, None)
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .map_ok(|subscription| (subscription.id.clone(), subscription))
+        .try_collect()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(WEBHOOK_TIMEOUT))
+        .build()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let outcomes: Vec<(WebhookDelivery, Option<Result<(), String>>)> =
+        futures::stream::iter(claimed)
+            .map(|delivery| {
+                let subscription = subscriptions.get(&delivery.subscription);
+                let http = &http;
+
+                async move {
+                    let outcome = match subscription {
+                        Some(subscription) => Some(attempt(http, subscription, &delivery).await),
+                        None => None,
+                    };
+
+                    (delivery, outcome)
+                }
+            })
+            .buffer_unordered(DELIVERY_CONCURRENCY)
+            .collect()
+            .await;
+
+    let dead_letters = database.collection::<WebhookDelivery>("webhook_dead_letters");
+    let mut delivered = 0;
+
+    for (mut delivery, outcome) in outcomes {
+        let error = match outcome {
+            Some(Err(error)) => error,
+            outcome => {
+                if outcome.is_some() {
+                    delivered += 1;
+                }
+
+                deliveries
+                    .delete_one(doc! { "id": delivery.id }, None)
+                    .await
+                    .map_err(error::ErrorInternalServerError)?;
+                continue;
+            }
+        };
+
+        error!(
+            "Failed to deliver the {} webhook {} to {}: {}",
+            delivery.event.as_str(),
+            delivery.id,
+            delivery.url,
+            error
+        );
+
+        let now = DateTime::now().timestamp_millis();
+        delivery.attempts += 1;
+        delivery.last_error = Some(error);
+
+        if delivery.attempts >= settings.max_attempts {
+            delivery.failed_at = Some(now);
+
+            dead_letters
+                .insert_one(delivery.clone(), None)
+                .await
+                .map_err(error::ErrorInternalServerError)?;
+            deliveries
+                .delete_one(doc! { "id": delivery.id }, None)
+                .await
+                .map_err(error::ErrorInternalServerError)?;
+        } else {
+            deliveries
+                .update_one(
+                    doc! { "id": delivery.id },
+                    doc! {
+                        "$set": {
+                            "attempts": delivery.attempts,
+                            "next_attempt": now + settings.delay(delivery.attempts),
+                            "last_error": delivery.last_error,
+                        }
+                    },
+                    None,
+                )
+                .await
+                .map_err(error::ErrorInternalServerError)?;
+        }
+    }
+
+    Ok(delivered)
+}
+
+/// Post a signed delivery to the URL of its subscription.
+///
+/// # Returns
+///
+/// - Returns `Ok(())` if the subscriber answered with a success status, or the reason of the failure otherwise.
+async fn attempt(
+    http: &reqwest::Client,
+    subscription: &WebhookSubscription,
+    delivery: &WebhookDelivery,
+) -> Result<(), String> {
+    let timestamp = DateTime::now().timestamp_millis().to_string();
+    let signature = sign(&subscription.secret, &timestamp, &delivery.payload)?;
+
+    http.post(&subscription.url)
+        .header("Content-Type", "application/json")
+        .header("X-Webhook-Id", &delivery.id)
+        .header("X-Webhook-Event", delivery.event.as_str())
+        .header("X-Webhook-Timestamp", timestamp)
+        .header("X-Webhook-Signature", signature)
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map(|_| ())
+        .map_err(|error| error.to_string())
+}
+
+/// Sign a delivery with the secret of its subscription, as an HMAC-SHA256 of the timestamp and the payload joined by a dot.
+fn sign(secret: &str, timestamp: &str, payload: &str) -> Result<String, String> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|error| error.to_string())?;
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(payload.as_bytes());
+
+    Ok(format!(
+        "sha256={}",
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
+
+/// Build the view of a subscription, leaving out its secret.
+fn webhook_view(subscription: WebhookSubscription) -> WebhookView {
+    WebhookView {
+        id: subscription.id,
+        url: subscription.url,
+        events: subscription.events,
+        created_at: subscription.created_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{test, variant};
+
+    fn settings() -> RetrySettings {
+        RetrySettings {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            backoff: 1000,
+        }
+    }
+
+    fn product() -> Product {
+        Product {
+            variants: vec![variant("S", None, 10)],
+            ..crate::testing::product("P1", 10.0)
+        }
+    }
+
+    #[test]
+    fn delay_doubles_after_each_failure() {
+        let settings = settings();
+
+        assert_eq!(settings.delay(0), 1000);
+        assert_eq!(settings.delay(1), 1000);
+        assert_eq!(settings.delay(2), 2000);
+        assert_eq!(settings.delay(5), 16_000);
+    }
+
+    #[test]
+    fn delay_is_capped_without_overflowing() {
+        let settings = settings();
+
+        assert_eq!(settings.delay(20), MAX_BACKOFF);
+        assert_eq!(settings.delay(64), MAX_BACKOFF);
+        assert_eq!(settings.delay(65), MAX_BACKOFF);
+        assert_eq!(settings.delay(u32::MAX), MAX_BACKOFF);
+
+        let settings = RetrySettings {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            backoff: i64::MAX,
+        };
+
+        assert_eq!(settings.delay(3), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn signature_is_an_hmac_of_the_timestamp_and_payload() {
+        assert_eq!(
+            sign("secret", "1700000000000", r#"{"event":"product.created"}"#),
+            Ok(
+                "sha256=833a136ee1c76c193008584df1033ea111fbe607ae53885267905d0583754312"
+                    .to_string()
+            )
+        );
+        assert_ne!(
+            sign("secret", "1700000000001", r#"{"event":"product.created"}"#),
+            sign("secret", "1700000000000", r#"{"event":"product.created"}"#)
+        );
+    }
+
+    #[test]
+    fn unchanged_product_is_only_updated() {
+        assert_eq!(
+            change_events(&product(), &product()),
+            [WebhookEvent::ProductUpdated]
+        );
+    }
+
+    #[test]
+    fn archiving_delists_the_product() {
+        let after = Product {
+            status: ProductStatus::Archived,
+            ..product()
+        };
+
+        assert_eq!(
+            change_events(&product(), &after),
+            [WebhookEvent::ProductDelisted]
+        );
+        assert_eq!(
+            change_events(&after, &after),
+            [WebhookEvent::ProductUpdated]
+        );
+    }
+
+    #[test]
+    fn variant_changes_are_stock_and_price_changes() {
+        let mut after = product();
+        after.variants[0].stock = 4;
+        after.variants[0].price = Some(12.0);
+
+        assert_eq!(
+            change_events(&product(), &after),
+            [
+                WebhookEvent::ProductUpdated,
+                WebhookEvent::StockChanged,
+                WebhookEvent::PriceChanged,
+            ]
+        );
+    }
+
+    #[test]
+    fn sale_is_a_price_change() {
+        let after = Product {
+            price: 8.0,
+            compare_at: Some(10.0),
+            ..product()
+        };
+
+        assert_eq!(
+            change_events(&product(), &after),
+            [WebhookEvent::ProductUpdated, WebhookEvent::PriceChanged]
+        );
+    }
+}
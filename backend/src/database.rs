@@ -3,10 +3,13 @@
 //! This software may be modified and distributed under the terms of the MIT license.
 //! See the LICENSE file for details.
 
+use crate::crud;
+use crate::ranking;
 use crate::structures::Product;
 
 use futures::TryStreamExt;
 use log::{error, info};
+use mongodb::error::{ErrorKind, WriteError, WriteFailure};
 use mongodb::{bson::doc, options::ClientOptions, Client};
 use std::io::{self, Error};
 
@@ -23,7 +26,7 @@ pub async fn establish_connection() -> Result<Client, Error> {
         Ok(options) => options,
         Err(error) => {
             error!("Failed to parse client options: {}", error);
-            return Err(io::Error::new(io::ErrorKind::Other, "Database error"));
+            return Err(io::Error::other("Database error"));
         }
     };
 
@@ -32,7 +35,7 @@ pub async fn establish_connection() -> Result<Client, Error> {
         Ok(client) => client,
         Err(error) => {
             error!("Failed to create MongoDB client: {}", error);
-            return Err(io::Error::new(io::ErrorKind::Other, "Database error"));
+            return Err(io::Error::other("Database error"));
         }
     };
 
@@ -49,11 +52,31 @@ pub async fn establish_connection() -> Result<Client, Error> {
         }
         Err(error) => {
             error!("Failed to ping the database: {}", error);
-            return Err(io::Error::new(io::ErrorKind::Other, "Database error"));
+            Err(io::Error::other("Database error"))
         }
     }
 }
 
+/// Whether a database error was caused by a duplicate key in a unique index.
+pub fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(WriteError { code: 11000, .. }))
+    )
+}
+
+/// Create a MeiliSearch client.
+///
+/// # Returns
+///
+/// Returns the MeiliSearch `Client` connected to the search server.
+pub fn establish_search_connection() -> meilisearch_sdk::Client {
+    meilisearch_sdk::Client::new(
+        "http://0.0.0.0:7700",
+        Some("KbKaO3ANX62fyv6gu8UtJlgPwEjKKmaH-s-d9KSiDzE"),
+    )
+}
+
 /// Index products in MeiliSearch.
 ///
 /// This function indexes products in MeiliSearch, a search engine. It fetches products from the MongoDB database, adds them to MeiliSearch, and configures search settings.
@@ -67,13 +90,10 @@ pub async fn establish_connection() -> Result<Client, Error> {
 /// Returns a `Result` containing the MeiliSearch `Client` if indexing is successful, or an `Error` if an error occurs during indexing.
 pub async fn index_products(client: &Client) -> Result<meilisearch_sdk::Client, Error> {
     // Create a MeiliSearch client with the provided server URL and API key.
-    let ms_client = meilisearch_sdk::Client::new(
-        "http://0.0.0.0:7700",
-        Some("KbKaO3ANX62fyv6gu8UtJlgPwEjKKmaH-s-d9KSiDzE"),
-    );
+    let ms_client = establish_search_connection();
 
     // Fetch products from the MongoDB database.
-    let products_doc = fetch_products(client).await.unwrap();
+    let products_doc = fetch_products(client).await?;
 
     info!("Indexing {} product(s)", products_doc.len());
 
@@ -85,16 +105,20 @@ pub async fn index_products(client: &Client) -> Result<meilisearch_sdk::Client,
         .await
         .unwrap();
 
-    let ranking_rules = ["typo", "words", "proximity", "attribute"];
+    let ranking_rules = ranking::ranking_rules();
+    info!("Ranking search results by {}", ranking_rules.join(", "));
     ms_client
         .index("products")
         .set_ranking_rules(&ranking_rules)
         .await
         .unwrap();
 
+    let filterable_attributes = crud::attributes::filterable_attributes(client)
+        .await
+        .unwrap();
     ms_client
         .index("products")
-        .set_filterable_attributes(&["category"])
+        .set_filterable_attributes(&filterable_attributes)
         .await
         .unwrap();
 
@@ -111,7 +135,7 @@ pub async fn index_products(client: &Client) -> Result<meilisearch_sdk::Client,
 
         if task_status.is_failure() {
             error!("Indexing task failed");
-            return Err(io::Error::new(io::ErrorKind::Other, "Indexing error"));
+            return Err(io::Error::other("Indexing error"));
         } else if task_status.is_success() {
             info!("Successfully indexed {} product(s)", products_doc.len());
             break;
@@ -143,12 +167,22 @@ async fn fetch_products(client: &Client) -> Result<Vec<Product>, Error> {
         Ok(cursor) => cursor,
         Err(error) => {
             error!("Error while fetching products: {}", error);
-            return Err(io::Error::new(io::ErrorKind::Other, "Database error"));
+            return Err(io::Error::other("Database error"));
         }
     };
 
     // Convert the fetched products to a vector.
-    let products: Vec<Product> = cursor.try_collect().await.unwrap_or_else(|_| vec![]);
+    let mut products: Vec<Product> = match cursor.try_collect().await {
+        Ok(products) => products,
+        Err(error) => {
+            error!("Error while reading products: {}", error);
+            return Err(io::Error::other("Database error"));
+        }
+    };
+
+    for product in products.iter_mut() {
+        product.aggregate();
+    }
 
     Ok(products)
 }
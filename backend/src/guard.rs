@@ -0,0 +1,333 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::routes::analytics::{Rejections, Visitor};
+use crate::structures::EventKind;
+use crate::sync::lock;
+
+use mongodb::bson::DateTime;
+use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// The default number of seconds during which repeated events of a visitor on a product are ignored.
+const DEFAULT_DEDUPE_WINDOW: i64 = 1800;
+
+/// The default number of analytics events an IP address can send per minute.
+const DEFAULT_RATE_LIMIT: u32 = 60;
+
+/// The length of a rate limit window in milliseconds.
+const RATE_WINDOW: i64 = 60_000;
+
+/// The number of tracked visitors above which expired entries are pruned.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Case-insensitive fragments of the user agents of bots, crawlers and scripts.
+const BOT_AGENTS: [&str; 16] = [
+    "bot",
+    "crawl",
+    "spider",
+    "slurp",
+    "scrapy",
+    "curl",
+    "wget",
+    "python",
+    "java/",
+    "go-http-client",
+    "okhttp",
+    "libwww",
+    "httpclient",
+    "headless",
+    "phantomjs",
+    "facebookexternalhit",
+];
+
+/// An enum representing the reason an analytics event was rejected.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rejection {
+    /// The IP address sent too many events.
+    RateLimited,
+
+    /// The user agent belongs to a bot or is missing.
+    Bot,
+
+    /// The visitor already sent the same event for the product recently.
+    Duplicate,
+}
+
+/// A guard protecting the analytics events from bots, repeated events and floods.
+///
+/// Every rejected event is counted, so the volume of filtered traffic can be monitored.
+pub struct EventGuard {
+    /// The number of milliseconds during which repeated events of a visitor on a product are ignored.
+    dedupe_window: i64,
+
+    /// The number of events an IP address can send per minute.
+    rate_limit: u32,
+
+    /// The time of the last recorded event, keyed by visitor fingerprint, product ID and event kind.
+    seen: Mutex<HashMap<(String, String, EventKind), i64>>,
+
+    /// The start of the current rate limit window and the number of events in it, keyed by IP address.
+    rates: Mutex<HashMap<String, (i64, u32)>>,
+
+    /// The number of events rejected because of the rate limit.
+    rate_limited: AtomicU64,
+
+    /// The number of events rejected because they were sent by bots.
+    bot: AtomicU64,
+
+    /// The number of events rejected as duplicates.
+    duplicate: AtomicU64,
+
+    /// The time the counts started in milliseconds since the Unix epoch.
+    since: i64,
+}
+
+impl EventGuard {
+    /// Create a guard with the settings read from the `EVENT_DEDUPE_WINDOW` (in seconds) and `EVENT_RATE_LIMIT` (per minute) environment variables.
+    pub fn from_env() -> Self {
+        let dedupe_window = env::var("EVENT_DEDUPE_WINDOW")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|seconds| *seconds >= 0)
+            .unwrap_or(DEFAULT_DEDUPE_WINDOW);
+        let rate_limit = env::var("EVENT_RATE_LIMIT")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|limit| *limit > 0)
+            .unwrap_or(DEFAULT_RATE_LIMIT);
+
+        EventGuard::new(dedupe_window, rate_limit)
+    }
+
+    /// Create a guard ignoring repeated events within the given number of seconds and limiting the events of an IP address per minute.
+    fn new(dedupe_window: i64, rate_limit: u32) -> Self {
+        EventGuard {
+            dedupe_window: dedupe_window.saturating_mul(1000),
+            rate_limit,
+            seen: Mutex::new(HashMap::new()),
+            rates: Mutex::new(HashMap::new()),
+            rate_limited: AtomicU64::new(0),
+            bot: AtomicU64::new(0),
+            duplicate: AtomicU64::new(0),
+            since: DateTime::now().timestamp_millis(),
+        }
+    }
+
+    /// Admit an event from a visitor, checking the rate limit of its IP address and its user agent.
+    pub fn admit(&self, visitor: &Visitor) -> Result<(), Rejection> {
+        let now = DateTime::now().timestamp_millis();
+        let mut rates = lock(&self.rates);
+
+        if rates.len() > PRUNE_THRESHOLD {
+            rates.retain(|_, (start, _)| now - *start < RATE_WINDOW);
+        }
+
+        let (start, count) = rates.entry(visitor.ip.clone()).or_insert((now, 0));
+
+        if now - *start >= RATE_WINDOW {
+            *start = now;
+            *count = 0;
+        }
+
+        *count += 1;
+
+        if *count > self.rate_limit {
+            return Err(self.reject(Rejection::RateLimited));
+        }
+
+        drop(rates);
+
+        let is_bot = match &visitor.user_agent {
+            Some(user_agent) => {
+                let user_agent = user_agent.to_lowercase();
+                BOT_AGENTS
+                    .iter()
+                    .any(|fragment| user_agent.contains(fragment))
+            }
+            None => true,
+        };
+
+        if is_bot {
+            return Err(self.reject(Rejection::Bot));
+        }
+
+        Ok(())
+    }
+
+    /// Check that a visitor did not send the same event for a product within the deduplication window, and remember the event if not.
+    ///
+    /// Views, clicks and wishlist additions are deduplicated, while additions to a cart and purchases may be repeated. Visitors are told apart by their IP address and user agent rather than by the session they send, which a client can change at will.
+    pub fn first_seen(
+        &self,
+        visitor: &Visitor,
+        pid: &str,
+        kind: EventKind,
+    ) -> Result<(), Rejection> {
+        if matches!(kind, EventKind::AddToCart | EventKind::Purchase) {
+            return Ok(());
+        }
+
+        let now = DateTime::now().timestamp_millis();
+        let mut seen = lock(&self.seen);
+
+        if seen.len() > PRUNE_THRESHOLD {
+            seen.retain(|_, time| now - *time < self.dedupe_window);
+        }
+
+        let fingerprint = format!(
+            "{} {}",
+            visitor.ip,
+            visitor.user_agent.as_deref().unwrap_or_default()
+        );
+        let key = (fingerprint, pid.to_string(), kind);
+
+        match seen.get(&key) {
+            Some(time) if now - *time < self.dedupe_window => {
+                Err(self.reject(Rejection::Duplicate))
+            }
+            _ => {
+                seen.insert(key, now);
+                Ok(())
+            }
+        }
+    }
+
+    /// The number of events rejected for each reason since the server started.
+    pub fn rejections(&self) -> Rejections {
+        Rejections {
+            rate_limited: self.rate_limited.load(Ordering::Relaxed),
+            bot: self.bot.load(Ordering::Relaxed),
+            duplicate: self.duplicate.load(Ordering::Relaxed),
+            since: self.since,
+        }
+    }
+
+    /// Count a rejected event.
+    fn reject(&self, rejection: Rejection) -> Rejection {
+        let counter = match rejection {
+            Rejection::RateLimited => &self.rate_limited,
+            Rejection::Bot => &self.bot,
+            Rejection::Duplicate => &self.duplicate,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        rejection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test;
+
+    const BROWSER: &str = "Mozilla/5.0 (X11; Linux x86_64) Firefox/120.0";
+
+    fn visitor(ip: &str, user_agent: Option<&str>) -> Visitor {
+        Visitor {
+            ip: ip.to_string(),
+            user_agent: user_agent.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn browsers_are_admitted() {
+        let guard = EventGuard::new(DEFAULT_DEDUPE_WINDOW, DEFAULT_RATE_LIMIT);
+
+        assert_eq!(guard.admit(&visitor("10.0.0.1", Some(BROWSER))), Ok(()));
+    }
+
+    #[test]
+    fn bots_and_missing_user_agents_are_rejected() {
+        let guard = EventGuard::new(DEFAULT_DEDUPE_WINDOW, DEFAULT_RATE_LIMIT);
+
+        for user_agent in [
+            Some("Googlebot/2.1 (+http://www.google.com/bot.html)"),
+            Some("curl/8.4.0"),
+            Some("Mozilla/5.0 HeadlessChrome/120.0"),
+            None,
+        ] {
+            assert_eq!(
+                guard.admit(&visitor("10.0.0.1", user_agent)),
+                Err(Rejection::Bot)
+            );
+        }
+
+        assert_eq!(guard.rejections().bot, 4);
+    }
+
+    #[test]
+    fn rate_limit_applies_per_ip_address() {
+        let guard = EventGuard::new(DEFAULT_DEDUPE_WINDOW, 3);
+        let first = visitor("10.0.0.1", Some(BROWSER));
+
+        for _ in 0..3 {
+            assert_eq!(guard.admit(&first), Ok(()));
+        }
+
+        assert_eq!(guard.admit(&first), Err(Rejection::RateLimited));
+        assert_eq!(guard.admit(&visitor("10.0.0.2", Some(BROWSER))), Ok(()));
+        assert_eq!(guard.rejections().rate_limited, 1);
+    }
+
+    #[test]
+    fn repeated_views_are_rejected_whatever_the_session() {
+        let guard = EventGuard::new(DEFAULT_DEDUPE_WINDOW, DEFAULT_RATE_LIMIT);
+        let first = visitor("10.0.0.1", Some(BROWSER));
+
+        assert_eq!(guard.first_seen(&first, "P1", EventKind::View), Ok(()));
+        assert_eq!(
+            guard.first_seen(&first, "P1", EventKind::View),
+            Err(Rejection::Duplicate)
+        );
+        assert_eq!(guard.first_seen(&first, "P1", EventKind::Click), Ok(()));
+        assert_eq!(guard.first_seen(&first, "P2", EventKind::View), Ok(()));
+        assert_eq!(
+            guard.first_seen(
+                &visitor("10.0.0.1", Some("Safari/17.0")),
+                "P1",
+                EventKind::View
+            ),
+            Ok(())
+        );
+        assert_eq!(guard.rejections().duplicate, 1);
+    }
+
+    #[test]
+    fn purchases_and_cart_additions_may_repeat() {
+        let guard = EventGuard::new(DEFAULT_DEDUPE_WINDOW, DEFAULT_RATE_LIMIT);
+        let first = visitor("10.0.0.1", Some(BROWSER));
+
+        for kind in [EventKind::AddToCart, EventKind::Purchase] {
+            assert_eq!(guard.first_seen(&first, "P1", kind), Ok(()));
+            assert_eq!(guard.first_seen(&first, "P1", kind), Ok(()));
+        }
+    }
+
+    #[test]
+    fn zero_window_disables_deduplication() {
+        let guard = EventGuard::new(0, DEFAULT_RATE_LIMIT);
+        let first = visitor("10.0.0.1", Some(BROWSER));
+
+        assert_eq!(guard.first_seen(&first, "P1", EventKind::View), Ok(()));
+        assert_eq!(guard.first_seen(&first, "P1", EventKind::View), Ok(()));
+    }
+
+    #[test]
+    fn huge_dedupe_windows_saturate() {
+        let guard = EventGuard::new(i64::MAX, DEFAULT_RATE_LIMIT);
+        let first = visitor("10.0.0.1", Some(BROWSER));
+
+        assert_eq!(guard.dedupe_window, i64::MAX);
+        assert_eq!(guard.first_seen(&first, "P1", EventKind::View), Ok(()));
+        assert_eq!(
+            guard.first_seen(&first, "P1", EventKind::View),
+            Err(Rejection::Duplicate)
+        );
+    }
+}
@@ -0,0 +1,469 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use futures::TryStreamExt;
+use log::{error, info, warn};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::IndexOptions;
+use mongodb::{Client, IndexModel};
+use std::fmt;
+use std::io::{self, Error};
+use std::time::Duration;
+
+/// Represents the definition of a database index.
+pub struct IndexDefinition {
+    /// The name of the collection the index belongs to.
+    pub collection: &'static str,
+
+    /// The name of the index.
+    pub name: &'static str,
+
+    /// The indexed fields and their directions, in order.
+    pub keys: Document,
+
+    /// Whether the index rejects documents with duplicate keys.
+    pub unique: bool,
+
+    /// The time after the date of the first key at which documents are deleted, for TTL indexes.
+    pub expire_after: Option<Duration>,
+}
+
+impl IndexDefinition {
+    /// Build the index model used to create the index.
+    fn model(&self) -> IndexModel {
+        IndexModel::builder()
+            .keys(self.keys.clone())
+            .options(
+                IndexOptions::builder()
+                    .name(self.name.to_string())
+                    .unique(self.unique)
+                    .expire_after(self.expire_after)
+                    .build(),
+            )
+            .build()
+    }
+
+    /// Whether an existing index matches the definition.
+    fn matches(&self, index: &IndexModel) -> bool {
+        let unique = index
+            .options
+            .as_ref()
+            .and_then(|options| options.unique)
+            .unwrap_or(false);
+        let expire_after = index
+            .options
+            .as_ref()
+            .and_then(|options| options.expire_after);
+
+        // Index keys are ordered, and directions may be stored as any numeric type.
+        let keys = |keys: &Document| -> Vec<(String, Option<f64>)> {
+            keys.iter()
+                .map(|(key, direction)| (key.clone(), number(direction)))
+                .collect()
+        };
+
+        unique == self.unique
+            && expire_after == self.expire_after
+            && keys(&index.keys) == keys(&self.keys)
+    }
+}
+
+/// An enum representing a difference between the defined and the existing indexes.
+pub enum Drift {
+    /// A defined index does not exist.
+    Missing {
+        collection: &'static str,
+        name: &'static str,
+    },
+
+    /// An index exists with the name of a definition but different keys or options.
+    Changed {
+        collection: &'static str,
+        name: &'static str,
+    },
+
+    /// An index exists without a definition.
+    Unexpected {
+        collection: &'static str,
+        name: String,
+    },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::Missing { collection, name } => {
+                write!(f, "{}.{}: missing", collection, name)
+            }
+            Drift::Changed { collection, name } => {
+                write!(f, "{}.{}: differs from its definition", collection, name)
+            }
+            Drift::Unexpected { collection, name } => {
+                write!(f, "{}.{}: not defined", collection, name)
+            }
+        }
+    }
+}
+
+/// The definitions of the database indexes.
+///
+/// Indexes are identified by name, so changing the keys or options of a definition recreates the index with the same name on the next start. Renaming a definition leaves the old index behind, reported as not defined.
+pub fn definitions() -> Vec<IndexDefinition> {
+    let index = |collection, name, keys, unique| IndexDefinition {
+        collection,
+        name,
+        keys,
+        unique,
+        expire_after: None,
+    };
+
+    vec![
+        // Product lookups, and the guarantee that product IDs are unique.
+        index("products", "pid", doc! { "pid": 1 }, true),
+        // Seller products and catalog filters.
+        index("products", "sid_pid", doc! { "sid": 1, "pid": 1 }, false),
+        index(
+            "products",
+            "category_pid",
+            doc! { "category": 1, "pid": 1 },
+            false,
+        ),
+        // Catalog sorts, restricted to active products.
+        index(
+            "products",
+            "status_pid",
+            doc! { "status": 1, "pid": 1 },
+            false,
+        ),
+        index(
+            "products",
+            "status_price_pid",
+            doc! { "status": 1, "price": 1, "pid": 1 },
+            false,
+        ),
+        index(
+            "products",
+            "status_rating_pid",
+            doc! { "status": 1, "rating": 1, "pid": 1 },
+            false,
+        ),
+        index(
+            "products",
+            "status_sales_pid",
+            doc! { "status": 1, "sales": 1, "pid": 1 },
+            false,
+        ),
+        index(
+            "products",
+            "status_clicks_pid",
+            doc! { "status": 1, "clicks": 1, "pid": 1 },
+            false,
+        ),
+        index(
+            "products",
+            "status_created_at_pid",
+            doc! { "status": 1, "created_at": 1, "pid": 1 },
+            false,
+        ),
+        // Attribute schemas are looked up by category, one per category.
+        index(
+            "attribute_schemas",
+            "category",
+            doc! { "category": 1 },
+            true,
+        ),
+        // Product history and the audit log, newest first.
+        index(
+            "audit_log",
+            "pid_timestamp",
+            doc! { "pid": 1, "timestamp": -1 },
+            false,
+        ),
+        index(
+            "audit_log",
+            "actor_timestamp",
+            doc! { "actor": 1, "timestamp": -1 },
+            false,
+        ),
+        index("audit_log", "timestamp", doc! { "timestamp": -1 }, false),
+        // Analytics events of a product or seller over time.
+        index(
+            "analytics_events",
+            "pid_timestamp",
+            doc! { "pid": 1, "timestamp": -1 },
+            false,
+        ),
+        index(
+            "analytics_events",
+            "sid_timestamp",
+            doc! { "sid": 1, "timestamp": -1 },
+            false,
+        ),
+        // Recent analytics events of a kind, such as the purchases counted in recommendations.
+        index(
+            "analytics_events",
+            "kind_timestamp",
+            doc! { "kind": 1, "timestamp": -1 },
+            false,
+        ),
+        // Analytics rollups, one per product and bucket, read per product, per seller or for all products.
+        index(
+            "analytics_rollups",
+            "pid_granularity_start",
+            doc! { "pid": 1, "granularity": 1, "start": 1 },
+            true,
+        ),
+        index(
+            "analytics_rollups",
+            "granularity_start",
+            doc! { "granularity": 1, "start": 1 },
+            false,
+        ),
+        index(
+            "analytics_rollups",
+            "sid_granularity_start",
+            doc! { "sid": 1, "granularity": 1, "start": 1 },
+            false,
+        ),
+        // Bought together suggestions, one document per product, and the suggestions of a purged product.
+        index("bought_together", "pid", doc! { "pid": 1 }, true),
+        index(
+            "bought_together",
+            "products_pid",
+            doc! { "products.pid": 1 },
+            false,
+        ),
+        // Recently viewed products, one document per shopper, deleted once expired.
+        index("recent_views", "viewer", doc! { "viewer": 1 }, true),
+        index(
+            "recent_views",
+            "products_pid",
+            doc! { "products.pid": 1 },
+            false,
+        ),
+        IndexDefinition {
+            expire_after: Some(Duration::ZERO),
+            ..index(
+                "recent_views",
+                "expires_at",
+                doc! { "expires_at": 1 },
+                false,
+            )
+        },
+        // Stock levels seen by the stock checker, one per product.
+        index("stock_levels", "pid", doc! { "pid": 1 }, true),
+        // Back in stock subscriptions, one per product and shopper.
+        index(
+            "restock_subscriptions",
+            "pid_uid",
+            doc! { "pid": 1, "uid": 1 },
+            true,
+        ),
+        // Webhook subscriptions by ID, and the subscriptions of an event.
+        index("webhook_subscriptions", "id", doc! { "id": 1 }, true),
+        index(
+            "webhook_subscriptions",
+            "events",
+            doc! { "events": 1 },
+            false,
+        ),
+        // Pending webhook deliveries by ID, by due time, and by subscription or product when either is deleted.
+        index("webhook_deliveries", "id", doc! { "id": 1 }, true),
+        index(
+            "webhook_deliveries",
+            "next_attempt",
+            doc! { "next_attempt": 1 },
+            false,
+        ),
+        index(
+            "webhook_deliveries",
+            "subscription",
+            doc! { "subscription": 1 },
+            false,
+        ),
+        index("webhook_deliveries", "pid", doc! { "pid": 1 }, false),
+        // Webhook dead letters by ID, and most recently given up first.
+        index("webhook_dead_letters", "id", doc! { "id": 1 }, true),
+        index(
+            "webhook_dead_letters",
+            "failed_at",
+            doc! { "failed_at": -1 },
+            false,
+        ),
+        // Applied migrations, one record per version.
+        index("migrations", "version", doc! { "version": 1 }, true),
+    ]
+}
+
+/// Compare the existing indexes with their definitions.
+///
+/// This function lists the indexes of every collection with index definitions and reports the defined indexes that are missing or differ, and the existing indexes that are not defined. The default index on `_id` is ignored.
+///
+/// # Parameters
+///
+/// - `client`: A reference to the MongoDB `Client` used to list the indexes.
+///
+/// # Returns
+///
+/// Returns a `Result` containing the differences, which is empty if the indexes match their definitions, or an `Error` if the indexes cannot be listed.
+pub async fn check_indexes(client: &Client) -> Result<Vec<Drift>, Error> {
+    let definitions = definitions();
+    let mut collections: Vec<&'static str> = definitions
+        .iter()
+        .map(|definition| definition.collection)
+        .collect();
+    collections.sort();
+    collections.dedup();
+
+    let mut drifts: Vec<Drift> = Vec::new();
+
+    for collection in collections {
+        let existing = existing_indexes(collection, client).await?;
+        let defined: Vec<&IndexDefinition> = definitions
+            .iter()
+            .filter(|definition| definition.collection == collection)
+            .collect();
+
+        for definition in &defined {
+            match existing
+                .iter()
+                .find(|index| index_name(index) == Some(definition.name))
+            {
+                None => drifts.push(Drift::Missing {
+                    collection,
+                    name: definition.name,
+                }),
+                Some(index) if !definition.matches(index) => drifts.push(Drift::Changed {
+                    collection,
+                    name: definition.name,
+                }),
+                Some(_) => {}
+            }
+        }
+
+        for index in &existing {
+            let name = index_name(index).unwrap_or_default();
+
+            if name != "_id_" && !defined.iter().any(|definition| definition.name == name) {
+                drifts.push(Drift::Unexpected {
+                    collection,
+                    name: name.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(drifts)
+}
+
+/// Create the missing indexes and recreate the changed ones.
+///
+/// This function brings the indexes in line with their definitions and can be run any number of times. Indexes without a definition are reported but never dropped, as they may have been created by hand.
+///
+/// # Parameters
+///
+/// - `client`: A reference to the MongoDB `Client` used to manage the indexes.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the indexes match their definitions, or an `Error` if an index cannot be created, such as a unique index over duplicate values.
+pub async fn ensure_indexes(client: &Client) -> Result<(), Error> {
+    let database = client.database("ecommerce_db");
+    let definitions = definitions();
+
+    for drift in check_indexes(client).await? {
+        let (collection, name) = match drift {
+            Drift::Missing { collection, name } => (collection, name),
+            Drift::Changed { collection, name } => {
+                info!("Dropping index {}.{} to recreate it", collection, name);
+
+                if let Err(error) = database
+                    .collection::<Document>(collection)
+                    .drop_index(name, None)
+                    .await
+                {
+                    error!("Failed to drop index {}.{}: {}", collection, name, error);
+                    return Err(io::Error::other("Database error"));
+                }
+
+                (collection, name)
+            }
+            Drift::Unexpected { collection, name } => {
+                warn!("Index {}.{} has no definition", collection, name);
+                continue;
+            }
+        };
+
+        let definition = definitions
+            .iter()
+            .find(|definition| definition.collection == collection && definition.name == name)
+            .expect("drift of a defined index");
+
+        info!("Creating index {}.{}", collection, name);
+
+        if let Err(error) = database
+            .collection::<Document>(collection)
+            .create_index(definition.model(), None)
+            .await
+        {
+            error!("Failed to create index {}.{}: {}", collection, name, error);
+            return Err(io::Error::other("Database error"));
+        }
+    }
+
+    Ok(())
+}
+
+/// List the indexes of a collection, which has none if it does not exist yet.
+async fn existing_indexes(collection: &str, client: &Client) -> Result<Vec<IndexModel>, Error> {
+    let database = client.database("ecommerce_db");
+    let missing = database
+        .list_collection_names(doc! { "name": collection })
+        .await
+        .map_err(|error| {
+            error!("Failed to list collections: {}", error);
+            io::Error::other("Database error")
+        })?
+        .is_empty();
+
+    if missing {
+        return Ok(Vec::new());
+    }
+
+    let indexes = database
+        .collection::<Document>(collection)
+        .list_indexes(None)
+        .await
+        .map_err(|error| {
+            error!("Failed to list indexes of {}: {}", collection, error);
+            io::Error::other("Database error")
+        })?
+        .try_collect()
+        .await
+        .map_err(|error| {
+            error!("Failed to list indexes of {}: {}", collection, error);
+            io::Error::other("Database error")
+        })?;
+
+    Ok(indexes)
+}
+
+/// The name of an existing index.
+fn index_name(index: &IndexModel) -> Option<&str> {
+    index
+        .options
+        .as_ref()
+        .and_then(|options| options.name.as_deref())
+}
+
+/// Read a numeric index direction.
+fn number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(value) => Some(*value as f64),
+        Bson::Int64(value) => Some(*value as f64),
+        Bson::Double(value) => Some(*value),
+        _ => None,
+    }
+}
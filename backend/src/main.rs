@@ -9,13 +9,26 @@ extern crate log;
 extern crate mongodb;
 
 use actix_cors::Cors;
-use actix_web::{middleware, web::{self, route}, App, HttpRequest, HttpResponse, HttpServer};
-use std::{env, fs, io};
+use actix_web::{middleware, web, App, HttpRequest, HttpResponse, HttpServer};
+use clap::Parser;
+use std::{env, fs, io, sync::Arc};
 
+mod cli;
+mod clicks;
 mod crud;
 mod database;
+mod guard;
+mod indexes;
+mod migrations;
+mod ranking;
 mod routes;
+mod scheduler;
+mod storage;
 mod structures;
+mod sync;
+#[cfg(test)]
+mod testing;
+mod trending;
 
 async fn not_found_handler(request: HttpRequest) -> HttpResponse {
     let _ = request.path().to_string();
@@ -29,6 +42,8 @@ async fn not_found_handler(request: HttpRequest) -> HttpResponse {
 
 #[actix_rt::main]
 async fn main() -> io::Result<()> {
+    let cli = cli::Cli::parse();
+
     // Set up logging.
     env::set_var("RUST_LOG", "error,info,actix_web=debug,actix_server=info");
     env_logger::init();
@@ -36,19 +51,64 @@ async fn main() -> io::Result<()> {
     // Establish a connection to the database.
     let client = database::establish_connection().await.unwrap();
 
+    // Run the maintenance command instead of the server if one is given.
+    if let Some(command) = cli.command {
+        return cli::run(command, client).await;
+    }
+
+    // Refuse to serve a database whose documents are not migrated to this release.
+    migrations::check(&client).await?;
+
+    // Create or update the database indexes.
+    indexes::ensure_indexes(&client).await.unwrap();
+
     // Index products from the database.
     let products_index = database::index_products(&client).await.unwrap();
 
-    // Create shared data objects to share the client and product index across services.
-    let shared_client = web::Data::new(client);
-    let shared_products_index = web::Data::new(products_index);
+    // Apply sales and scheduled price changes in the background.
+    scheduler::spawn_pricing(client.clone(), products_index.clone());
+
+    // Recompute the popularity of products in the background.
+    scheduler::spawn_popularity(client.clone(), products_index.clone());
+
+    // Recompute the products frequently bought together in the background.
+    scheduler::spawn_recommendations(client.clone());
+
+    // Rank the trending and best selling products in the background.
+    let lists = Arc::new(trending::ListCache::from_env());
+    scheduler::spawn_lists(lists.clone(), client.clone());
+
+    // Check stock levels and deliver stock alerts in the background.
+    scheduler::spawn_stock_alerts(client.clone());
+
+    // Deliver product lifecycle events to the webhook subscriptions in the background.
+    scheduler::spawn_webhooks(client.clone());
+
+    // Buffer clicks in memory and flush them in the background.
+    let clicks = Arc::new(clicks::ClickBuffer::default());
+    scheduler::spawn_click_flush(clicks.clone(), client.clone(), products_index.clone());
+
+    // Set up the local storage for uploaded images.
+    let uploads_path = env::var("UPLOADS_PATH").unwrap_or_else(|_| "uploads".to_string());
+    let uploads = storage::LocalStorage::new(uploads_path, "/uploads")?;
+    let uploads_root = uploads.root().to_path_buf();
+
+    // Create shared data objects to share the client, product index, click buffer, event guard, product lists and storage across services.
+    let shared_client = web::Data::new(client.clone());
+    let shared_products_index = web::Data::new(products_index.clone());
+    let shared_clicks = web::Data::from(clicks.clone());
+    let shared_guard = web::Data::new(guard::EventGuard::from_env());
+    let shared_lists = web::Data::from(lists);
+    let shared_storage: web::Data<dyn storage::Storage> =
+        web::Data::from(Arc::new(uploads) as Arc<dyn storage::Storage>);
 
     // Configure the HTTP server.
     HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allowed_methods(vec!["GET", "POST", "PATCH", "DELETE"])
-            .allowed_headers(vec!["Content-Type"])
+            .allowed_headers(vec!["Content-Type", "If-Match", "X-Actor-Id"])
+            .expose_headers(vec!["ETag"])
             .max_age(3600);
 
         App::new()
@@ -56,20 +116,70 @@ async fn main() -> io::Result<()> {
             .wrap(cors)
             .app_data(shared_client.clone())
             .app_data(shared_products_index.clone())
+            .app_data(shared_clicks.clone())
+            .app_data(shared_guard.clone())
+            .app_data(shared_lists.clone())
+            .app_data(shared_storage.clone())
+            .service(routes::admin::purge_product)
+            .service(routes::webhooks::create_subscription)
+            .service(routes::webhooks::subscriptions)
+            .service(routes::webhooks::dead_letters)
+            .service(routes::webhooks::delete_subscription)
+            .service(routes::webhooks::retry_dead_letter)
+            .service(routes::audit::history)
+            .service(routes::audit::entries)
             .service(routes::analytics::data)
             .service(routes::analytics::increment)
+            .service(routes::analytics::record)
+            .service(routes::analytics::seller)
+            .service(routes::analytics::rejections)
+            .service(routes::attributes::data)
+            .service(routes::attributes::save_schema)
+            .service(routes::export::export_products)
             .service(routes::product::data)
             .service(routes::product::search)
+            .service(routes::product::facets)
             .service(routes::product::page)
+            .service(routes::trending::trending)
+            .service(routes::trending::bestsellers)
             .service(routes::product::update)
+            .service(routes::product::add_variant)
+            .service(routes::product::update_variant)
+            .service(routes::product::remove_variant)
+            .service(routes::product::add_image)
+            .service(routes::product::upload_image)
+            .service(routes::product::order_images)
+            .service(routes::product::remove_image)
+            .service(routes::product::update_attributes)
+            .service(routes::recommendations::related)
+            .service(routes::stock::set_threshold)
+            .service(routes::stock::subscribe)
+            .service(routes::stock::unsubscribe)
+            .service(routes::pricing::set_sale)
+            .service(routes::pricing::end_sale)
+            .service(routes::pricing::schedule_price)
+            .service(routes::pricing::unschedule_price)
             .service(routes::profile::seller_products)
             .service(routes::profile::add_product)
             .service(routes::profile::remove_product)
+            .service(routes::profile::restore_product)
+            .service(routes::profile::publish_product)
+            .service(routes::recent::recent_products)
+            .service(routes::recent::clear_recent)
+            .service(routes::import::import_products)
+            .service(routes::bulk::update_products)
+            .service(routes::bulk::apply_operation)
             .service(routes::frontend::route_cart)
             .service(routes::frontend::route_wishlist)
+            .service(actix_files::Files::new("/uploads", uploads_root.clone()))
             .default_service(web::to(not_found_handler))
     })
     .bind("0.0.0.0:8080")?
     .run()
-    .await
+    .await?;
+
+    // Flush the clicks buffered since the last flush before exiting.
+    scheduler::flush_clicks(&clicks, &client, &products_index).await;
+
+    Ok(())
 }
@@ -0,0 +1,338 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use futures::future::LocalBoxFuture;
+use futures::{FutureExt, TryStreamExt};
+use log::{error, info};
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Client, Database};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Error};
+
+/// A migration step, run against the application database.
+type Step = fn(Database) -> LocalBoxFuture<'static, mongodb::error::Result<()>>;
+
+/// Represents a versioned change of the stored documents.
+struct Migration {
+    /// The version of the migration, starting at 1 and increasing by one.
+    version: u32,
+
+    /// The name of the migration.
+    name: &'static str,
+
+    /// The step applying the migration.
+    up: Step,
+
+    /// The step reverting the migration, or `None` if the changes cannot be undone.
+    down: Option<Step>,
+}
+
+/// Represents an applied migration, as recorded in the `migrations` collection.
+#[derive(Serialize, Deserialize)]
+struct AppliedMigration {
+    /// The version of the migration.
+    version: u32,
+
+    /// The name of the migration.
+    name: String,
+
+    /// The time the migration was applied in milliseconds since the Unix epoch.
+    applied_at: i64,
+}
+
+/// Represents the state of a migration.
+pub struct MigrationStatus {
+    /// The version of the migration.
+    pub version: u32,
+
+    /// The name of the migration.
+    pub name: String,
+
+    /// The time the migration was applied in milliseconds since the Unix epoch, or `None` if it is pending.
+    pub applied_at: Option<i64>,
+}
+
+/// The migrations in version order. New migrations are appended with the next version and never edited once released.
+fn migrations() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            name: "backfill_product_fields",
+            up: backfill_product_fields,
+            // The fields missing before the backfill are not recorded.
+            down: None,
+        },
+        Migration {
+            version: 2,
+            name: "numeric_product_prices",
+            up: numeric_product_prices,
+            // The original number types are not recorded.
+            down: None,
+        },
+    ]
+}
+
+/// Store the defaults of the product fields added after the first release in the documents missing them.
+///
+/// Products stored before statuses were introduced are active, and products stored before timestamps were introduced are dated by their object ID.
+fn backfill_product_fields(
+    database: Database,
+) -> LocalBoxFuture<'static, mongodb::error::Result<()>> {
+    async move {
+        // The update time is set in a second stage, which sees the creation time set by the first one.
+        let pipeline = vec![
+            doc! {
+                "$set": {
+                    "images": { "$ifNull": ["$images", []] },
+                    "options": { "$ifNull": ["$options", []] },
+                    "variants": { "$ifNull": ["$variants", []] },
+                    "min_price": { "$ifNull": ["$min_price", "$price"] },
+                    "max_price": { "$ifNull": ["$max_price", "$price"] },
+                    "scheduled_prices": { "$ifNull": ["$scheduled_prices", []] },
+                    "attributes": { "$ifNull": ["$attributes", {}] },
+                    "status": { "$ifNull": ["$status", "active"] },
+                    "created_at": {
+                        "$ifNull": [
+                            "$created_at",
+                            { "$toLong": { "$convert": { "input": "$_id", "to": "date", "onError": 0 } } },
+                        ]
+                    },
+                    "version": { "$ifNull": ["$version", 0] },
+                }
+            },
+            doc! { "$set": { "updated_at": { "$ifNull": ["$updated_at", "$created_at"] } } },
+        ];
+
+        database
+            .collection::<Document>("products")
+            .update_many(doc! {}, pipeline, None)
+            .await?;
+
+        Ok(())
+    }
+    .boxed_local()
+}
+
+/// Store the prices and ratings of products as doubles, as documents inserted by hand may hold integers or decimals.
+fn numeric_product_prices(
+    database: Database,
+) -> LocalBoxFuture<'static, mongodb::error::Result<()>> {
+    async move {
+        let mut fields = Document::new();
+
+        for field in ["price", "min_price", "max_price", "rating"] {
+            fields.insert(field, doc! { "$toDouble": format!("${}", field) });
+        }
+
+        database
+            .collection::<Document>("products")
+            .update_many(
+                doc! {
+                    "$or": [
+                        { "price": { "$type": ["int", "long", "decimal"] } },
+                        { "min_price": { "$type": ["int", "long", "decimal"] } },
+                        { "max_price": { "$type": ["int", "long", "decimal"] } },
+                        { "rating": { "$type": ["int", "long", "decimal"] } },
+                    ]
+                },
+                vec![doc! { "$set": fields }],
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+    .boxed_local()
+}
+
+/// Retrieve the state of every migration.
+///
+/// # Parameters
+///
+/// - `client`: A reference to the MongoDB `Client` used to read the applied migrations.
+///
+/// # Returns
+///
+/// Returns a `Result` containing the state of every known migration in version order, or an `Error` if the applied migrations cannot be read or include unknown versions.
+pub async fn status(client: &Client) -> Result<Vec<MigrationStatus>, Error> {
+    let applied = applied_migrations(client).await?;
+    unknown_versions(&applied)?;
+
+    Ok(migrations()
+        .iter()
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            name: migration.name.to_string(),
+            applied_at: applied
+                .iter()
+                .find(|applied| applied.version == migration.version)
+                .map(|applied| applied.applied_at),
+        })
+        .collect())
+}
+
+/// Check that every migration is applied.
+///
+/// This function is run before serving requests, so that the server never reads documents in a layout it does not expect.
+///
+/// # Parameters
+///
+/// - `client`: A reference to the MongoDB `Client` used to read the applied migrations.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the database is up to date, or an `Error` if migrations are pending or the database was migrated by a newer release.
+pub async fn check(client: &Client) -> Result<(), Error> {
+    let pending = status(client)
+        .await?
+        .iter()
+        .filter(|status| status.applied_at.is_none())
+        .count();
+
+    if pending > 0 {
+        error!(
+            "The database has {} pending migration(s), run the migrate command first",
+            pending
+        );
+        return Err(io::Error::other("Pending migrations"));
+    }
+
+    Ok(())
+}
+
+/// Migrate the database up or down to a version.
+///
+/// This function applies the pending migrations up to the target version in version order, then reverts the applied migrations above the target version in reverse order. Nothing is migrated if one of the migrations to revert cannot be undone. Each migration is recorded in, or removed from, the `migrations` collection right after its step completes, so an interrupted run resumes where it stopped.
+///
+/// # Parameters
+///
+/// - `target`: The version to migrate to, or `None` for the latest version. Version 0 reverts every migration.
+/// - `client`: A reference to the MongoDB `Client` used to migrate the database.
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the database is at the target version, or an `Error` if the target version is unknown, a migration to revert cannot be undone or a step fails.
+pub async fn migrate(target: Option<u32>, client: &Client) -> Result<(), Error> {
+    let migrations = migrations();
+    let latest = migrations.last().map_or(0, |migration| migration.version);
+    let target = target.unwrap_or(latest);
+
+    if target > latest {
+        return Err(io::Error::other(format!(
+            "Unknown migration version {}, the latest is {}",
+            target, latest
+        )));
+    }
+
+    let applied = applied_migrations(client).await?;
+    unknown_versions(&applied)?;
+
+    let database = client.database("ecommerce_db");
+    let records = database.collection::<AppliedMigration>("migrations");
+    let is_applied = |version: u32| applied.iter().any(|applied| applied.version == version);
+    let reverted: Vec<(&Migration, Step)> = migrations
+        .iter()
+        .rev()
+        .filter(|migration| migration.version > target && is_applied(migration.version))
+        .map(|migration| match migration.down {
+            Some(down) => Ok((migration, down)),
+            None => Err(io::Error::other(format!(
+                "Migration {} {} cannot be reverted",
+                migration.version, migration.name
+            ))),
+        })
+        .collect::<Result<_, _>>()?;
+
+    for migration in migrations
+        .iter()
+        .filter(|migration| migration.version <= target && !is_applied(migration.version))
+    {
+        info!(
+            "Applying migration {} {}",
+            migration.version, migration.name
+        );
+
+        (migration.up)(database.clone())
+            .await
+            .map_err(|error| failed(migration, error))?;
+
+        let record = AppliedMigration {
+            version: migration.version,
+            name: migration.name.to_string(),
+            applied_at: DateTime::now().timestamp_millis(),
+        };
+
+        records
+            .insert_one(record, None)
+            .await
+            .map_err(|error| failed(migration, error))?;
+    }
+
+    for (migration, down) in reverted {
+        info!(
+            "Reverting migration {} {}",
+            migration.version, migration.name
+        );
+
+        down(database.clone())
+            .await
+            .map_err(|error| failed(migration, error))?;
+
+        records
+            .delete_one(doc! { "version": migration.version }, None)
+            .await
+            .map_err(|error| failed(migration, error))?;
+    }
+
+    Ok(())
+}
+
+/// Read the applied migrations in version order.
+async fn applied_migrations(client: &Client) -> Result<Vec<AppliedMigration>, Error> {
+    let options = FindOptions::builder().sort(doc! { "version": 1 }).build();
+
+    client
+        .database("ecommerce_db")
+        .collection::<AppliedMigration>("migrations")
+        .find(None, options)
+        .await
+        .map_err(|error| {
+            error!("Failed to read the applied migrations: {}", error);
+            io::Error::other("Database error")
+        })?
+        .try_collect()
+        .await
+        .map_err(|error| {
+            error!("Failed to read the applied migrations: {}", error);
+            io::Error::other("Database error")
+        })
+}
+
+/// Reject a database with applied migrations this release does not know, which were applied by a newer release.
+fn unknown_versions(applied: &[AppliedMigration]) -> Result<(), Error> {
+    let migrations = migrations();
+
+    match applied.iter().find(|applied| {
+        !migrations
+            .iter()
+            .any(|migration| migration.version == applied.version)
+    }) {
+        Some(applied) => Err(io::Error::other(format!(
+            "The database was migrated to version {} ({}) by a newer release",
+            applied.version, applied.name
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Log a failed migration step.
+fn failed(migration: &Migration, error: mongodb::error::Error) -> Error {
+    error!(
+        "Migration {} {} failed: {}",
+        migration.version, migration.name, error
+    );
+    io::Error::other("Migration error")
+}
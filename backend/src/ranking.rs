@@ -0,0 +1,132 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use log::warn;
+use std::env;
+
+/// The built-in MeiliSearch ranking rules, in order of importance.
+const BASE_RULES: [&str; 4] = ["typo", "words", "proximity", "attribute"];
+
+/// The numeric product fields custom ranking rules can sort by.
+const RANKING_FIELDS: [&str; 5] = ["popularity", "sales", "rating", "clicks", "created_at"];
+
+/// The default half-life of the events counted in the popularity score in days.
+const DEFAULT_HALF_LIFE: f64 = 7.0;
+
+/// The default number of days of events counted in the popularity score.
+const DEFAULT_WINDOW: i64 = 90;
+
+/// An enum representing a ranking profile, the custom ranking rules breaking ties between equally relevant search results.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RankingProfile {
+    /// Text relevance only.
+    Relevance,
+
+    /// The most popular products first.
+    #[default]
+    Popularity,
+
+    /// The best selling products first, then the best rated.
+    BestSellers,
+
+    /// The best rated products first, then the best selling.
+    TopRated,
+}
+
+impl RankingProfile {
+    /// Parse a ranking profile name.
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "relevance" => Some(RankingProfile::Relevance),
+            "popularity" => Some(RankingProfile::Popularity),
+            "best_sellers" => Some(RankingProfile::BestSellers),
+            "top_rated" => Some(RankingProfile::TopRated),
+            _ => None,
+        }
+    }
+
+    /// The custom ranking rules of the profile.
+    fn rules(&self) -> Vec<&'static str> {
+        match self {
+            RankingProfile::Relevance => vec![],
+            RankingProfile::Popularity => vec!["popularity:desc", "rating:desc"],
+            RankingProfile::BestSellers => vec!["sales:desc", "rating:desc"],
+            RankingProfile::TopRated => vec!["rating:desc", "sales:desc"],
+        }
+    }
+}
+
+/// Build the MeiliSearch ranking rules of the products index.
+///
+/// The built-in rules are followed by custom rules, read from the `SEARCH_RANKING_RULES` environment variable as a comma separated list such as `sales:desc,rating:desc`, or else from the profile named by the `SEARCH_RANKING_PROFILE` environment variable (`relevance`, `popularity`, `best_sellers` or `top_rated`, defaulting to `popularity`). Invalid rules and profile names are ignored with a warning.
+///
+/// # Returns
+///
+/// Returns the ranking rules in order of importance.
+pub fn ranking_rules() -> Vec<String> {
+    let custom: Vec<String> = match env::var("SEARCH_RANKING_RULES") {
+        Ok(rules) => rules
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .filter(|rule| {
+                let valid = rule.split_once(':').is_some_and(|(field, order)| {
+                    RANKING_FIELDS.contains(&field) && (order == "asc" || order == "desc")
+                });
+
+                if !valid {
+                    warn!("Ignoring invalid ranking rule `{}`", rule);
+                }
+
+                valid
+            })
+            .map(str::to_string)
+            .collect(),
+        Err(_) => {
+            let profile = match env::var("SEARCH_RANKING_PROFILE") {
+                Ok(name) => RankingProfile::parse(name.trim()).unwrap_or_else(|| {
+                    warn!("Ignoring unknown ranking profile `{}`", name);
+                    RankingProfile::default()
+                }),
+                Err(_) => RankingProfile::default(),
+            };
+
+            profile.rules().into_iter().map(str::to_string).collect()
+        }
+    };
+
+    BASE_RULES
+        .iter()
+        .map(|rule| rule.to_string())
+        .chain(custom)
+        .collect()
+}
+
+/// The settings of the popularity score.
+pub struct PopularitySettings {
+    /// The number of days after which an event counts half as much.
+    pub half_life: f64,
+
+    /// The number of days of events counted.
+    pub window: i64,
+}
+
+impl PopularitySettings {
+    /// Read the popularity settings from the `POPULARITY_HALF_LIFE` and `POPULARITY_WINDOW` environment variables in days.
+    pub fn from_env() -> Self {
+        PopularitySettings {
+            half_life: env::var("POPULARITY_HALF_LIFE")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .filter(|days: &f64| *days > 0.0)
+                .unwrap_or(DEFAULT_HALF_LIFE),
+            window: env::var("POPULARITY_WINDOW")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .filter(|days| *days > 0)
+                .unwrap_or(DEFAULT_WINDOW),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::admin;
+use crate::routes::audit::Actor;
+
+use actix_web::dev::Payload;
+use actix_web::{error, web, Error, FromRequest, HttpRequest, HttpResponse};
+use futures::future::{ready, Ready};
+use mongodb::Client;
+use std::env;
+
+/// Represents the authorization of a request to an admin endpoint.
+///
+/// The request must carry the token of the `ADMIN_TOKEN` environment variable as an `Authorization: Bearer` header. Admin endpoints are disabled while no token is configured.
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let expected = match env::var("ADMIN_TOKEN") {
+            Ok(token) if !token.is_empty() => token,
+            _ => return ready(Err(error::ErrorForbidden("Admin endpoints are disabled"))),
+        };
+        let given = request
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or("");
+
+        if tokens_match(given.as_bytes(), expected.as_bytes()) {
+            ready(Ok(Admin))
+        } else {
+            ready(Err(error::ErrorUnauthorized("Invalid admin token")))
+        }
+    }
+}
+
+/// Compare two tokens in a time that does not depend on where they differ.
+fn tokens_match(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Permanently delete a product.
+///
+/// This function is an Actix web handler for purging a product from the product module. It receives the product ID as a path parameter and delegates the operation to the `admin::purge_product` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `_admin`: The admin authorization of the request.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, or `Ok(HttpResponse::InternalServerError())`.
+#[delete("/api/admin/product/{pid}")]
+pub async fn purge_product(
+    path: web::Path<(String,)>,
+    _admin: Admin,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match admin::purge_product(
+        path.0.clone(),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
@@ -3,11 +3,19 @@
 //! This software may be modified and distributed under the terms of the MIT license.
 //! See the LICENSE file for details.
 
+use crate::clicks::ClickBuffer;
 use crate::crud::analytics;
+use crate::guard::EventGuard;
+use crate::routes::admin::Admin;
+use crate::structures::{EventCounts, EventKind, Granularity};
 
-use actix_web::{web, Error, HttpResponse};
+use actix_web::dev::Payload;
+use actix_web::{web, Error, FromRequest, HttpRequest, HttpResponse};
+use futures::future::{ready, Ready};
 use mongodb::Client;
 use serde::{Deserialize, Serialize};
+use std::env;
+use std::net::IpAddr;
 
 /// Represents the data structure for analytics information.
 #[derive(Default, Deserialize, Serialize)]
@@ -29,61 +37,444 @@ pub struct Data {
 
     /// The product number of clicks.
     pub clicks: u32,
+
+    /// The start of the time range of the series in milliseconds since the Unix epoch.
+    pub from: i64,
+
+    /// The exclusive end of the time range of the series in milliseconds since the Unix epoch.
+    pub to: i64,
+
+    /// The length of the buckets of the series.
+    pub granularity: Granularity,
+
+    /// The number of events of each kind over the time range.
+    pub totals: EventCounts,
+
+    /// The number of events of each kind per bucket, including empty buckets, oldest first.
+    pub series: Vec<Bucket>,
 }
 
-/// Retrieve analytics data for a specific product.
+/// Represents the analytics events of a time bucket.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Bucket {
+    /// The start of the bucket in milliseconds since the Unix epoch.
+    pub start: i64,
+
+    /// The number of events of each kind in the bucket.
+    #[serde(flatten)]
+    pub counts: EventCounts,
+}
+
+/// Represents the query parameters for retrieving analytics data.
+#[derive(Deserialize)]
+pub struct AnalyticsQuery {
+    /// The inclusive start of the time range in milliseconds since the Unix epoch, defaulting to 30 days before its end.
+    pub from: Option<i64>,
+
+    /// The exclusive end of the time range in milliseconds since the Unix epoch, defaulting to now.
+    pub to: Option<i64>,
+
+    /// The length of the buckets, defaulting to one day.
+    #[serde(default)]
+    pub granularity: Granularity,
+}
+
+/// Represents an analytics event sent by the storefront.
+#[derive(Deserialize)]
+pub struct EventRequest {
+    /// The kind of the event.
+    pub kind: EventKind,
+
+    /// The anonymous ID of the browsing session the event occurred in.
+    pub session: Option<String>,
+
+    /// The user ID of the signed-in shopper, if any.
+    pub uid: Option<String>,
+
+    /// The number of units added to a cart or purchased, ignored for other events.
+    pub quantity: Option<u32>,
+}
+
+/// Represents the query parameters identifying the shopper who clicked a product.
+#[derive(Deserialize)]
+pub struct ViewerQuery {
+    /// The user ID of the signed-in shopper, if any.
+    pub uid: Option<String>,
+
+    /// The anonymous ID of the browsing session, if any.
+    pub session: Option<String>,
+}
+
+/// Represents the client sending an analytics event.
 ///
-/// This function is an Actix web handler for fetching analytics data for a product based on its ID.
+/// The IP address is the address of the connection peer, as forwarding headers can be set by any client. Behind a reverse proxy, the `TRUSTED_PROXY_HEADER` environment variable names the header the proxy sets to the client address, such as `X-Forwarded-For`, and its last address is used instead.
+pub struct Visitor {
+    /// The IP address of the client.
+    pub ip: String,
+
+    /// The `User-Agent` header of the request, if any.
+    pub user_agent: Option<String>,
+}
+
+impl FromRequest for Visitor {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let forwarded = env::var("TRUSTED_PROXY_HEADER")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .and_then(|name| forwarded_ip(request, &name));
+        let ip = forwarded
+            .or_else(|| request.peer_addr().map(|address| address.ip()))
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        let user_agent = request
+            .headers()
+            .get("User-Agent")
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_string());
+
+        ready(Ok(Visitor { ip, user_agent }))
+    }
+}
+
+/// The client address set by the trusted proxy in the given header, which is the last one as the proxy appends it to any given by the client.
+fn forwarded_ip(request: &HttpRequest, name: &str) -> Option<IpAddr> {
+    request
+        .headers()
+        .get(name)?
+        .to_str()
+        .ok()?
+        .rsplit(',')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// An enum representing the outcome of an analytics event.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventOutcome {
+    /// The event was recorded.
+    Recorded,
+
+    /// The event was counted as rejected but not recorded, as it was sent by a bot or repeated.
+    Ignored,
+
+    /// The product does not exist.
+    NotFound,
+}
+
+/// Represents the number of analytics events rejected for each reason.
+#[derive(Serialize)]
+pub struct Rejections {
+    /// The number of events rejected because their IP address sent too many events.
+    pub rate_limited: u64,
+
+    /// The number of events rejected because they were sent by bots.
+    pub bot: u64,
+
+    /// The number of events rejected as repeated by the same visitor.
+    pub duplicate: u64,
+
+    /// The time the counts started in milliseconds since the Unix epoch.
+    pub since: i64,
+}
+
+/// Represents the query parameters for retrieving the analytics dashboard of a seller.
+#[derive(Deserialize)]
+pub struct DashboardQuery {
+    /// The inclusive start of the time range in milliseconds since the Unix epoch, defaulting to 30 days before its end.
+    pub from: Option<i64>,
+
+    /// The exclusive end of the time range in milliseconds since the Unix epoch, defaulting to now.
+    pub to: Option<i64>,
+
+    /// The number of top and bottom performing products, defaulting to 5.
+    pub limit: Option<u32>,
+
+    /// The stock level at or below which a product is listed as low on stock, defaulting to 5.
+    pub low_stock: Option<u32>,
+}
+
+/// Represents the sales funnel figures of a product, a category or a seller over a time range.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Performance {
+    /// The number of times the products were displayed.
+    #[serde(default)]
+    pub views: u64,
+
+    /// The number of clicks on the products in listings.
+    #[serde(default)]
+    pub clicks: u64,
+
+    /// The number of times the products were added to a cart.
+    #[serde(default)]
+    pub add_to_cart: u64,
+
+    /// The number of times the products were added to a wishlist.
+    #[serde(default)]
+    pub wishlist: u64,
+
+    /// The number of purchases.
+    #[serde(default)]
+    pub purchases: u64,
+
+    /// The number of units sold.
+    #[serde(default)]
+    pub units: u64,
+
+    /// The revenue of the units sold, at their effective prices when purchased.
+    #[serde(default)]
+    pub revenue: f64,
+}
+
+/// Represents the performance of a product.
+#[derive(Deserialize, Serialize)]
+pub struct ProductPerformance {
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The product name.
+    pub name: String,
+
+    /// The product category.
+    pub category: String,
+
+    /// The product stock level.
+    pub stock: u32,
+
+    /// The product figures over the time range.
+    #[serde(flatten)]
+    pub performance: Performance,
+}
+
+/// Represents the performance of the products of a category.
+#[derive(Deserialize, Serialize)]
+pub struct CategoryPerformance {
+    /// The product category.
+    pub category: String,
+
+    /// The number of products in the category.
+    pub products: u64,
+
+    /// The category figures over the time range.
+    #[serde(flatten)]
+    pub performance: Performance,
+}
+
+/// Represents the analytics dashboard of a seller.
+#[derive(Serialize)]
+pub struct SellerDashboard {
+    /// The seller ID formatted as (S0000...).
+    pub sid: String,
+
+    /// The start of the time range in milliseconds since the Unix epoch.
+    pub from: i64,
 
+    /// The exclusive end of the time range in milliseconds since the Unix epoch.
+    pub to: i64,
+
+    /// The number of products of the seller, excluding archived ones.
+    pub products: u64,
+
+    /// The figures of all products of the seller.
+    pub totals: Performance,
+
+    /// The share of product displays followed by a click.
+    pub click_through_rate: f64,
+
+    /// The share of clicks followed by a purchase.
+    pub conversion_rate: f64,
+
+    /// The products with the highest revenue, best first.
+    pub top_products: Vec<ProductPerformance>,
+
+    /// The products with the lowest revenue, worst first.
+    pub bottom_products: Vec<ProductPerformance>,
+
+    /// The products low on stock, lowest stock first.
+    pub low_stock: Vec<ProductPerformance>,
+
+    /// The figures per category, highest revenue first.
+    pub categories: Vec<CategoryPerformance>,
+}
+
+/// Retrieve analytics data for a specific product.
+///
+/// This function is an Actix web handler for fetching analytics data for a product based on its ID, including the number of events of each kind over a time range, bucketed by hour or day.
+///
 /// # Parameters
 ///
 /// - `path`: Path parameter containing the product ID.
+/// - `query`: Query parameters containing the optional time range and granularity.
 /// - `client`: MongoDB client data.
-
+///
 /// # Returns
 ///
-/// - Returns an `HttpResponse` with analytics data in JSON format if found, `HttpResponse::NoContent()` if not found, or an `HttpResponse::InternalServerError()` in case of an error.
+/// - Returns an `HttpResponse` with analytics data in JSON format if found, `HttpResponse::NoContent()` if not found, `HttpResponse::BadRequest()` if the time range is invalid, or an `HttpResponse::InternalServerError()` in case of an error.
 #[get("/api/analytics/{pid}")]
 pub async fn data(
     path: web::Path<(String,)>,
+    query: web::Query<AnalyticsQuery>,
     client: web::Data<Client>,
 ) -> Result<HttpResponse, Error> {
-    match analytics::retrieve_data(path.0.clone(), client.get_ref().clone()).await {
+    match analytics::retrieve_data(path.0.clone(), query.into_inner(), client.get_ref().clone())
+        .await
+    {
         Ok(Some(data)) => Ok(HttpResponse::Ok()
             .content_type("application/json")
             .json(data)),
         Ok(None) => Ok(HttpResponse::NoContent().finish()),
-        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+        Err(error) => Ok(error.error_response()),
     }
 }
 
-/// Increment the number of clicks for a specific product.
+/// Record an analytics event for a specific product.
+///
+/// This function is an Actix web handler for recording a view, click, add-to-cart, wishlist or purchase event sent by the storefront.
 ///
-/// This function is an Actix web handler for incrementing the number of clicks for a product based on its ID.
-
 /// # Parameters
 ///
 /// - `path`: Path parameter containing the product ID.
+/// - `json`: The event kind, the optional session ID, user ID and quantity.
+/// - `visitor`: The client sending the event.
+/// - `guard`: The event guard data.
+/// - `clicks`: The click buffer data.
 /// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::Accepted())` if the event was ignored as sent by a bot or repeated, `Ok(HttpResponse::NoContent())` if the product does not exist, `Ok(HttpResponse::BadRequest())` if the quantity is zero or above 100, `Ok(HttpResponse::TooManyRequests())` if the client sent too many events, or `Ok(HttpResponse::InternalServerError())`.
+#[post("/api/analytics/{pid}/events")]
+pub async fn record(
+    path: web::Path<(String,)>,
+    json: web::Json<EventRequest>,
+    visitor: Visitor,
+    guard: web::Data<EventGuard>,
+    clicks: web::Data<ClickBuffer>,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match analytics::record_event(
+        path.0.clone(),
+        json.into_inner(),
+        visitor,
+        guard.get_ref(),
+        clicks.get_ref(),
+        client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(EventOutcome::Recorded) => Ok(HttpResponse::Ok().finish()),
+        Ok(EventOutcome::Ignored) => Ok(HttpResponse::Accepted().finish()),
+        Ok(EventOutcome::NotFound) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
 
+/// Increment the number of clicks for a specific product.
+///
+/// This function is an Actix web handler for incrementing the number of clicks for a product based on its ID. It records a click event and is kept for backward compatibility. The product is added to the recently viewed products of the shopper if they are identified.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `query`: Query parameters containing the optional user ID and session ID of the shopper.
+/// - `visitor`: The client sending the click.
+/// - `guard`: The event guard data.
+/// - `clicks`: The click buffer data.
+/// - `client`: MongoDB client data.
+///
 /// # Returns
 ///
-/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, or `Ok(HttpResponse::InternalServerError())`.
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::Accepted())` if the click was ignored, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::TooManyRequests())`, or `Ok(HttpResponse::InternalServerError())`.
 #[post("/api/analytics/{pid}/clicks/increment")]
 pub async fn increment(
     path: web::Path<(String,)>,
+    query: web::Query<ViewerQuery>,
+    visitor: Visitor,
+    guard: web::Data<EventGuard>,
+    clicks: web::Data<ClickBuffer>,
     client: web::Data<Client>,
-    ms_client: web::Data<meilisearch_sdk::Client>,
 ) -> Result<HttpResponse, Error> {
-    match analytics::increment_clicks(
+    let ViewerQuery { uid, session } = query.into_inner();
+    let event = EventRequest {
+        kind: EventKind::Click,
+        session,
+        uid,
+        quantity: None,
+    };
+
+    match analytics::record_event(
         path.0.clone(),
+        event,
+        visitor,
+        guard.get_ref(),
+        clicks.get_ref(),
         client.get_ref().clone(),
-        ms_client.get_ref().clone(),
     )
     .await
     {
-        Ok(true) => Ok(HttpResponse::Ok().finish()),
-        Ok(false) => Ok(HttpResponse::NoContent().finish()),
-        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+        Ok(EventOutcome::Recorded) => Ok(HttpResponse::Ok().finish()),
+        Ok(EventOutcome::Ignored) => Ok(HttpResponse::Accepted().finish()),
+        Ok(EventOutcome::NotFound) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
     }
 }
+
+/// Retrieve the analytics dashboard of a seller.
+///
+/// This function is an Actix web handler for fetching the figures aggregated across all products of a seller over a time range: revenue, units sold, click-through and conversion rates, top and bottom performers, products low on stock and a breakdown per category.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the seller ID.
+/// - `query`: Query parameters containing the optional time range, number of top and bottom performers and low stock level.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the dashboard in JSON format if successful, `HttpResponse::BadRequest()` if the query parameters are invalid, or an `HttpResponse::InternalServerError()` in case of an error.
+#[get("/api/analytics/seller/{sid}")]
+pub async fn seller(
+    path: web::Path<(String,)>,
+    query: web::Query<DashboardQuery>,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match analytics::retrieve_dashboard(
+        path.0.clone(),
+        query.into_inner(),
+        client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(dashboard) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(dashboard)),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Retrieve the number of rejected analytics events.
+///
+/// This function is an Actix web handler for monitoring the analytics events rejected by the event guard since the server started, by reason.
+///
+/// # Parameters
+///
+/// - `_admin`: The admin authorization of the request.
+/// - `guard`: The event guard data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the number of rejected events in JSON format.
+#[get("/api/admin/analytics/rejections")]
+pub async fn rejections(
+    _admin: Admin,
+    guard: web::Data<EventGuard>,
+) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .json(guard.rejections()))
+}
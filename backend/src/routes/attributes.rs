@@ -0,0 +1,75 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::attributes;
+use crate::structures::{AttributeDefinition, AttributeSchema};
+
+use actix_web::{web, Error, HttpResponse};
+use mongodb::Client;
+
+/// Retrieve the attribute schema of a product category.
+///
+/// This function is an Actix web handler for fetching the attribute schema of a product category.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product category.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the schema in JSON format if found, `HttpResponse::NoContent()` if not found, or an `HttpResponse::InternalServerError()` in case of an error.
+#[get("/api/attributes/{category}")]
+pub async fn data(
+    path: web::Path<(String,)>,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match attributes::retrieve_schema(path.0.clone(), client.get_ref().clone()).await {
+        Ok(Some(schema)) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(schema)),
+        Ok(None) => Ok(HttpResponse::NoContent().finish()),
+        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+    }
+}
+
+/// Create or replace the attribute schema of a product category.
+///
+/// This function is an Actix web handler for storing the attribute schema of a product category. It receives the category as a path parameter and the attribute definitions as a JSON body and delegates the operation to the `attributes::save_schema` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product category.
+/// - `json`: The attribute definitions of the category.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::BadRequest())`, or `Ok(HttpResponse::InternalServerError())`.
+#[post("/api/attributes/{category}")]
+pub async fn save_schema(
+    path: web::Path<(String,)>,
+    json: web::Json<Vec<AttributeDefinition>>,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    let schema = AttributeSchema {
+        category: path.0.clone(),
+        attributes: json.into_inner(),
+    };
+
+    match attributes::save_schema(
+        schema,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::BadRequest().finish()),
+        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+    }
+}
@@ -0,0 +1,140 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::audit;
+use crate::routes::admin::Admin;
+
+use actix_web::dev::Payload;
+use actix_web::{web, Error, FromRequest, HttpRequest, HttpResponse};
+use futures::future::{ready, Ready};
+use mongodb::Client;
+use serde::Deserialize;
+
+/// Represents the actor performing a request, as recorded in the audit log.
+///
+/// The actor ID is taken from the `X-Actor-Id` header and defaults to `anonymous`.
+#[derive(Clone)]
+pub struct Actor {
+    /// The actor ID.
+    pub id: String,
+
+    /// The endpoint the request was made to (e.g. PATCH /api/product/{pid}).
+    pub endpoint: String,
+}
+
+impl Actor {
+    /// Create the actor for a modification made by the system itself, such as a background task.
+    pub fn system(endpoint: &str) -> Self {
+        Actor {
+            id: "system".to_string(),
+            endpoint: endpoint.to_string(),
+        }
+    }
+}
+
+impl FromRequest for Actor {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let id = request
+            .headers()
+            .get("X-Actor-Id")
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty())
+            .unwrap_or("anonymous")
+            .to_string();
+        let endpoint = format!(
+            "{} {}",
+            request.method(),
+            request
+                .match_pattern()
+                .unwrap_or_else(|| request.path().to_string())
+        );
+
+        ready(Ok(Actor { id, endpoint }))
+    }
+}
+
+/// Represents the query parameters for paginating a product history.
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    /// The page number for pagination, starting at 1.
+    #[serde(default)]
+    pub page: u32,
+}
+
+/// Represents the query parameters for searching the audit log.
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    /// The actor ID to filter by.
+    pub actor: Option<String>,
+
+    /// The inclusive start of the time range in milliseconds since the Unix epoch.
+    pub from: Option<i64>,
+
+    /// The exclusive end of the time range in milliseconds since the Unix epoch.
+    pub to: Option<i64>,
+
+    /// The page number for pagination, starting at 1.
+    #[serde(default)]
+    pub page: u32,
+}
+
+/// Retrieve the modification history of a product.
+///
+/// This function is an Actix web handler for paging through the audit log entries of a product, newest first.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `query`: Query parameters containing the page number.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the audit entries in JSON format if successful, or an `HttpResponse::InternalServerError()` in case of an error.
+#[get("/api/product/{pid}/history")]
+pub async fn history(
+    path: web::Path<(String,)>,
+    query: web::Query<HistoryQuery>,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match audit::retrieve_history(path.0.clone(), query.into_inner(), client.get_ref().clone())
+        .await
+    {
+        Ok(audit_entries) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(audit_entries)),
+        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+    }
+}
+
+/// Search the audit log.
+///
+/// This function is an Actix web handler for paging through the audit log entries of every product, optionally filtered by actor and time range, newest first.
+///
+/// # Parameters
+///
+/// - `query`: Query parameters containing the filters and the page number.
+/// - `_admin`: The admin authorization of the request.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the audit entries in JSON format if successful, or an `HttpResponse::InternalServerError()` in case of an error.
+#[get("/api/admin/audit")]
+pub async fn entries(
+    query: web::Query<AuditQuery>,
+    _admin: Admin,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match audit::retrieve_entries(query.into_inner(), client.get_ref().clone()).await {
+        Ok(audit_entries) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(audit_entries)),
+        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+    }
+}
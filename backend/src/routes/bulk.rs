@@ -0,0 +1,190 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::bulk;
+use crate::routes::audit::Actor;
+use crate::structures::ProductStatus;
+
+use actix_web::{web, Error, HttpResponse};
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+
+/// Represents a partial update of a product. Missing fields are left unchanged.
+#[derive(Deserialize)]
+pub struct BulkUpdate {
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The product name.
+    pub name: Option<String>,
+
+    /// The product description.
+    pub description: Option<String>,
+
+    /// The product image as a URL.
+    pub image: Option<String>,
+
+    /// The product regular price.
+    pub price: Option<f32>,
+
+    /// The product stock level, ignored for products with variants.
+    pub stock: Option<u32>,
+}
+
+/// Represents the products a bulk operation applies to. Every given criterion must match.
+#[derive(Default, Deserialize)]
+pub struct BulkFilter {
+    /// The seller ID of the products.
+    pub sid: Option<String>,
+
+    /// The category of the products.
+    pub category: Option<String>,
+
+    /// The lifecycle status of the products.
+    pub status: Option<ProductStatus>,
+
+    /// The product IDs of the products.
+    pub pids: Option<Vec<String>>,
+}
+
+/// An enum representing an operation applied to many products at once.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BulkAction {
+    /// Change the regular price by a percentage, e.g. 10 for a 10% increase or -10 for a 10% decrease.
+    AdjustPrice { percent: f32 },
+
+    /// Set the regular price.
+    SetPrice { price: f32 },
+
+    /// Set the stock level of products without variants.
+    SetStock { stock: u32 },
+
+    /// Archive the products, hiding them from the catalog and search results.
+    Delist,
+
+    /// Restore archived products.
+    Restore,
+}
+
+/// Represents an operation applied to every product matching a filter.
+#[derive(Deserialize)]
+pub struct BulkOperation {
+    /// The products the operation applies to.
+    pub filter: BulkFilter,
+
+    /// The operation to apply.
+    pub action: BulkAction,
+}
+
+/// An enum representing the outcome of a bulk operation for a product.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemStatus {
+    /// The product was updated.
+    Updated,
+
+    /// The product was not updated.
+    Failed,
+}
+
+/// Represents the outcome of a bulk operation for a product.
+#[derive(Serialize)]
+pub struct ItemReport {
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The outcome for the product.
+    pub status: ItemStatus,
+
+    /// The reason the product was not updated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Represents the outcome of a bulk operation.
+#[derive(Default, Serialize)]
+pub struct BulkReport {
+    /// The number of updated products.
+    pub updated: usize,
+
+    /// The number of products that were not updated.
+    pub failed: usize,
+
+    /// The outcome for every product.
+    pub items: Vec<ItemReport>,
+}
+
+/// Apply partial updates to many products.
+///
+/// This function is an Actix web handler for updating a list of products at once. It receives the partial updates as a JSON body and delegates the operation to the `bulk::update_products` function.
+///
+/// # Parameters
+///
+/// - `json`: The partial updates, at most one per product.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the report in JSON format if successful, `HttpResponse::BadRequest()` if the list is too long, or an `HttpResponse::InternalServerError()` in case of an error.
+#[post("/api/profile/seller/bulk/update")]
+pub async fn update_products(
+    json: web::Json<Vec<BulkUpdate>>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match bulk::update_products(
+        json.into_inner(),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(report) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(report)),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Apply an operation to every product matching a filter.
+///
+/// This function is an Actix web handler for operations such as adjusting the prices of a seller's category or delisting a list of products. It receives the filter and the operation as a JSON body and delegates the operation to the `bulk::apply_operation` function.
+///
+/// # Parameters
+///
+/// - `json`: The filter and the operation.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the report in JSON format if successful, `HttpResponse::BadRequest()` if the filter is empty or matches too many products, or an `HttpResponse::InternalServerError()` in case of an error.
+#[post("/api/profile/seller/bulk/operation")]
+pub async fn apply_operation(
+    json: web::Json<BulkOperation>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match bulk::apply_operation(
+        json.into_inner(),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(report) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(report)),
+        Err(error) => Ok(error.error_response()),
+    }
+}
@@ -0,0 +1,103 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::export;
+use crate::structures::ProductStatus;
+
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, Error, HttpResponse};
+use mongodb::Client;
+use serde::Deserialize;
+
+/// An enum representing the format of a catalog export.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Comma separated values with a header row, in the same layout as the import files.
+    #[default]
+    Csv,
+
+    /// One product document per line.
+    Ndjson,
+
+    /// A Google Merchant Center product feed in RSS 2.0.
+    Merchant,
+}
+
+impl ExportFormat {
+    /// The content type of the export.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Merchant => "application/rss+xml",
+        }
+    }
+
+    /// The file extension of the export.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Merchant => "xml",
+        }
+    }
+}
+
+/// Represents the query parameters for exporting the catalog.
+#[derive(Clone, Deserialize, clap::Args)]
+pub struct ExportQuery {
+    /// The export format.
+    #[serde(default)]
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    pub format: ExportFormat,
+
+    /// The seller ID to export the products of.
+    #[arg(long)]
+    pub sid: Option<String>,
+
+    /// The product category to export.
+    #[arg(long)]
+    pub category: Option<String>,
+
+    /// The product lifecycle status to export.
+    #[arg(long, value_enum)]
+    pub status: Option<ProductStatus>,
+}
+
+/// Export the catalog.
+///
+/// This function is an Actix web handler for downloading the catalog, optionally filtered by seller, category and status. The products are streamed from the database as they are read, so the whole catalog is never held in memory.
+///
+/// # Parameters
+///
+/// - `query`: Query parameters containing the export format and the optional filters.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns a streamed `HttpResponse` with the catalog in the requested format if successful, or an `HttpResponse::InternalServerError()` in case of an error.
+#[get("/api/product/export")]
+pub async fn export_products(
+    query: web::Query<ExportQuery>,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    let query = query.into_inner();
+    let format = query.format;
+
+    match export::export_products(query, client.get_ref().clone()).await {
+        Ok(stream) => Ok(HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(format!(
+                    "products.{}",
+                    format.extension()
+                ))],
+            })
+            .streaming(stream)),
+        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+    }
+}
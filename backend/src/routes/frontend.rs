@@ -30,7 +30,7 @@ pub async fn route_cart(
 ) -> Result<HttpResponse, Error> {
     let client = reqwest::Client::new();
 
-    client
+    let _ = client
         .post(format!("https://shoppingcart.honeybeeks.net/api/upload/{}", path.0.clone()))
         .json(&json)
         .send()
@@ -46,7 +46,7 @@ pub async fn route_wishlist(
 ) -> Result<HttpResponse, Error> {
     let client = reqwest::Client::new();
 
-    client
+    let _ = client
         .post(format!("https://shoppingcart.honeybeeks.net/api/wishlist/upload/{}", path.0.clone()))
         .json(&json)
         .send()
@@ -0,0 +1,192 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::import;
+use crate::routes::audit::Actor;
+use crate::structures::AttributeValue;
+
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use futures::StreamExt;
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The maximum size of an import file in bytes.
+pub const MAX_IMPORT_SIZE: usize = 50 * 1024 * 1024;
+
+/// An enum representing the format of an import file.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// Comma separated values with a header row. Attributes are given as `attributes.<name>` columns.
+    Csv,
+
+    /// One JSON object per line.
+    Ndjson,
+}
+
+/// Represents the query parameters for importing products.
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    /// The file format, detected from the content type if missing.
+    pub format: Option<ImportFormat>,
+
+    /// Whether to only validate the file without writing anything.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Represents a product to be imported.
+#[derive(Clone, Deserialize)]
+pub struct ImportRow {
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The seller ID formatted as (S0000...).
+    pub sid: String,
+
+    /// The product name.
+    pub name: String,
+
+    /// The product description.
+    #[serde(default)]
+    pub description: String,
+
+    /// The product image as a URL.
+    #[serde(default)]
+    pub image: String,
+
+    /// The product category.
+    pub category: String,
+
+    /// The product regular price.
+    pub price: f32,
+
+    /// The product stock level, or `None` to keep the stock of an existing product.
+    #[serde(default)]
+    pub stock: Option<u32>,
+
+    /// Whether the product is a draft instead of being listed, or `None` to keep the status of an existing product.
+    #[serde(default)]
+    pub draft: Option<bool>,
+
+    /// The product attributes keyed by attribute name.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, AttributeValue>,
+}
+
+/// An enum representing the outcome of importing a row.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RowStatus {
+    /// The row created a new product, or would create one in a dry run.
+    Created,
+
+    /// The row updated an existing product, or would update one in a dry run.
+    Updated,
+
+    /// The row was rejected.
+    Failed,
+}
+
+/// Represents the outcome of importing a row.
+#[derive(Serialize)]
+pub struct RowReport {
+    /// The line number of the row in the import file.
+    pub line: u64,
+
+    /// The product ID of the row, if it could be read.
+    pub pid: String,
+
+    /// The outcome of the row.
+    pub status: RowStatus,
+
+    /// The reason the row was rejected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Represents the outcome of an import.
+#[derive(Default, Serialize)]
+pub struct ImportReport {
+    /// Whether the import only validated the file.
+    pub dry_run: bool,
+
+    /// The number of created products.
+    pub created: usize,
+
+    /// The number of updated products.
+    pub updated: usize,
+
+    /// The number of rejected rows.
+    pub failed: usize,
+
+    /// Whether the stored products were added to the search index. If not, importing the file again indexes them.
+    pub indexed: bool,
+
+    /// The outcome of every row in file order.
+    pub rows: Vec<RowReport>,
+}
+
+/// Import products in bulk.
+///
+/// This function is an Actix web handler for creating or updating many products at once from a CSV or NDJSON request body. Every row is validated and upserted by product ID independently, so invalid rows do not prevent the valid ones from being imported.
+///
+/// # Parameters
+///
+/// - `request`: The HTTP request, used to detect the file format from its content type.
+/// - `query`: Query parameters containing the optional file format and the dry run flag.
+/// - `payload`: The import file.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the import report in JSON format if successful, `HttpResponse::PayloadTooLarge()` if the file exceeds the size limit, or an `HttpResponse::InternalServerError()` in case of an error.
+#[post("/api/profile/seller/import/products")]
+pub async fn import_products(
+    request: HttpRequest,
+    query: web::Query<ImportQuery>,
+    mut payload: web::Payload,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    let format = query.format.unwrap_or_else(|| {
+        match request
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(content_type) if content_type.contains("csv") => ImportFormat::Csv,
+            _ => ImportFormat::Ndjson,
+        }
+    });
+
+    let mut bytes: Vec<u8> = Vec::new();
+
+    while let Some(chunk) = payload.next().await {
+        bytes.extend_from_slice(&chunk?);
+
+        if bytes.len() > MAX_IMPORT_SIZE {
+            return Ok(HttpResponse::PayloadTooLarge().finish());
+        }
+    }
+
+    let report = import::import_products(
+        import::parse_rows(&bytes, format),
+        query.dry_run,
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .json(report))
+}
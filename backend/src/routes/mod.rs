@@ -3,7 +3,19 @@
 //! This software may be modified and distributed under the terms of the MIT license.
 //! See the LICENSE file for details.
 
+pub mod admin;
 pub mod analytics;
+pub mod attributes;
+pub mod audit;
+pub mod bulk;
+pub mod export;
+pub mod import;
+pub mod pricing;
 pub mod product;
 pub mod profile;
+pub mod recent;
+pub mod recommendations;
+pub mod stock;
+pub mod trending;
+pub mod webhooks;
 pub mod frontend;
@@ -0,0 +1,180 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::pricing;
+use crate::routes::audit::Actor;
+use crate::routes::product::if_match;
+use crate::structures::{Sale, ScheduledPrice};
+
+use actix_web::http::header::IfMatch;
+use actix_web::{web, Error, HttpResponse};
+use mongodb::Client;
+use serde::Deserialize;
+
+/// Represents the query parameters for cancelling a scheduled price change.
+#[derive(Deserialize)]
+pub struct ScheduleQuery {
+    /// The time the price change was scheduled to take effect in milliseconds since the Unix epoch.
+    pub at: i64,
+}
+
+/// Set the sale of a product.
+///
+/// This function is an Actix web handler for setting the sale of a product. It receives the product ID as a path parameter and the sale as a JSON body and delegates the operation to the `pricing::update_sale` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `json`: The sale price and window.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::BadRequest())` if the sale is invalid or not below the regular price, `Ok(HttpResponse::PreconditionFailed())`, or `Ok(HttpResponse::InternalServerError())`.
+#[post("/api/product/{pid}/sale")]
+pub async fn set_sale(
+    path: web::Path<(String,)>,
+    json: web::Json<Sale>,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match pricing::update_sale(
+        path.0.clone(),
+        Some(json.into_inner()),
+        if_match(header),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// End the sale of a product.
+///
+/// This function is an Actix web handler for ending the sale of a product, restoring its regular price. It receives the product ID as a path parameter and delegates the operation to the `pricing::update_sale` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::PreconditionFailed())`, or `Ok(HttpResponse::InternalServerError())`.
+#[delete("/api/product/{pid}/sale")]
+pub async fn end_sale(
+    path: web::Path<(String,)>,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match pricing::update_sale(
+        path.0.clone(),
+        None,
+        if_match(header),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Schedule a price change of a product.
+///
+/// This function is an Actix web handler for scheduling a change of the regular price of a product. It receives the product ID as a path parameter and the price change as a JSON body and delegates the operation to the `pricing::schedule_price` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `json`: The new regular price and the time it takes effect.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::BadRequest())` if the price is invalid, or `Ok(HttpResponse::InternalServerError())`.
+#[post("/api/product/{pid}/price/schedule")]
+pub async fn schedule_price(
+    path: web::Path<(String,)>,
+    json: web::Json<ScheduledPrice>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match pricing::schedule_price(
+        path.0.clone(),
+        json.into_inner(),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Cancel a scheduled price change of a product.
+///
+/// This function is an Actix web handler for cancelling a scheduled price change. It receives the product ID as a path parameter and the scheduled time as a query parameter and delegates the operation to the `pricing::unschedule_price` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `query`: Query parameters containing the time the price change was scheduled to take effect.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::PreconditionFailed())`, or `Ok(HttpResponse::InternalServerError())`.
+#[delete("/api/product/{pid}/price/schedule")]
+pub async fn unschedule_price(
+    path: web::Path<(String,)>,
+    query: web::Query<ScheduleQuery>,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match pricing::unschedule_price(
+        path.0.clone(),
+        query.at,
+        if_match(header),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
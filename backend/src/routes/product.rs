@@ -4,10 +4,20 @@
 //! See the LICENSE file for details.
 
 use crate::crud::product;
-
+use crate::routes::audit::Actor;
+use crate::storage::{self, Storage};
+use crate::structures::{
+    AttributeValue, Image, Product, ProductStatus, Sale, ScheduledPrice, Variant,
+};
+
+use actix_multipart::Multipart;
+use actix_web::http::header::{ETag, EntityTag, IfMatch};
 use actix_web::{web, Error, HttpResponse};
+use futures::StreamExt;
 use mongodb::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
 
 /// Represents the data structure for product information.
 #[derive(Default, Deserialize, Serialize)]
@@ -27,10 +37,13 @@ pub struct Data {
     /// The product image as a URL.
     pub image: String,
 
+    /// The product image gallery in display order.
+    pub images: Vec<Image>,
+
     /// The product category.
     pub category: String,
 
-    /// The product price.
+    /// The product effective price.
     pub price: f32,
 
     /// The product stock level.
@@ -44,10 +57,49 @@ pub struct Data {
 
     /// The product number of clicks.
     pub clicks: u32,
+
+    /// The product option axes (e.g. size, color).
+    pub options: Vec<String>,
+
+    /// The product variants.
+    pub variants: Vec<Variant>,
+
+    /// The lowest price across the product variants.
+    pub min_price: f32,
+
+    /// The highest price across the product variants.
+    pub max_price: f32,
+
+    /// The product regular price while a sale is running.
+    pub compare_at: Option<f32>,
+
+    /// The product sale, if one is scheduled or running.
+    pub sale: Option<Sale>,
+
+    /// The product upcoming price changes.
+    pub scheduled_prices: Vec<ScheduledPrice>,
+
+    /// The product specifications keyed by attribute name.
+    pub attributes: BTreeMap<String, AttributeValue>,
+
+    /// The stock level at or below which the product is low on stock, or `None` for the default threshold.
+    pub low_stock_threshold: Option<u32>,
+
+    /// The product lifecycle status.
+    pub status: ProductStatus,
+
+    /// The product creation time in milliseconds since the Unix epoch.
+    pub created_at: i64,
+
+    /// The product last modification time in milliseconds since the Unix epoch.
+    pub updated_at: i64,
+
+    /// The product version, incremented on every modification.
+    pub version: u32,
 }
 
 /// Represents the data structure for a simplified product view.
-#[derive(Default, Deserialize, Serialize)]
+#[derive(Clone, Default, Deserialize, Serialize)]
 pub struct ProductView {
     /// The product ID formatted as (P0000...).
     pub pid: String,
@@ -61,7 +113,7 @@ pub struct ProductView {
     /// The product image as a URL.
     pub image: String,
 
-    /// The product price.
+    /// The product effective price.
     pub price: f32,
 
     /// The product stock level.
@@ -75,6 +127,21 @@ pub struct ProductView {
 
     /// The product number of clicks.
     pub clicks: u32,
+
+    /// The lowest price across the product variants.
+    pub min_price: f32,
+
+    /// The highest price across the product variants.
+    pub max_price: f32,
+
+    /// The product regular price while a sale is running.
+    pub compare_at: Option<f32>,
+
+    /// The product lifecycle status.
+    pub status: ProductStatus,
+
+    /// The product creation time in milliseconds since the Unix epoch.
+    pub created_at: i64,
 }
 
 /// Represents the query parameters for a product search.
@@ -85,13 +152,125 @@ pub struct SearchQuery {
 
     /// The search query.
     pub search: String,
+
+    /// The attribute filters as comma separated `name:value` pairs.
+    #[serde(default)]
+    pub attributes: String,
+
+    /// The comma separated attribute names to facet on.
+    #[serde(default)]
+    pub facets: String,
 }
 
 /// Represents the query parameters for paginating product data.
 #[derive(Deserialize)]
 pub struct PageQuery {
-    /// The page number for pagination.
-    pub page: u32,
+    /// The page number for offset pagination, starting at 1. Kept for backward compatibility, `cursor` should be preferred.
+    pub page: Option<u32>,
+
+    /// The cursor returned with the previous page, or `None` for the first page.
+    pub cursor: Option<String>,
+
+    /// The maximum number of products per page.
+    pub limit: Option<u32>,
+
+    /// The field to sort the products by, or `None` to sort by product ID.
+    pub sort: Option<SortField>,
+
+    /// The sort direction, defaulting to the natural direction of the sort field.
+    pub order: Option<SortOrder>,
+
+    /// The product category to filter by.
+    pub category: Option<String>,
+
+    /// The seller ID to filter by.
+    pub sid: Option<String>,
+
+    /// The lowest effective price to include.
+    pub min_price: Option<f32>,
+
+    /// The highest effective price to include.
+    pub max_price: Option<f32>,
+
+    /// Whether to only include products in stock.
+    #[serde(default)]
+    pub in_stock: bool,
+}
+
+/// An enum representing the field the catalog view is sorted by.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    /// The effective price, cheapest first by default.
+    Price,
+
+    /// The rating, best rated first by default.
+    Rating,
+
+    /// The total number of sales, best sellers first by default.
+    Sales,
+
+    /// The number of clicks, most viewed first by default.
+    Clicks,
+
+    /// The creation time, newest first by default.
+    Created,
+}
+
+impl SortField {
+    /// The name of the stored field.
+    pub fn field(&self) -> &'static str {
+        match self {
+            SortField::Price => "price",
+            SortField::Rating => "rating",
+            SortField::Sales => "sales",
+            SortField::Clicks => "clicks",
+            SortField::Created => "created_at",
+        }
+    }
+
+    /// The sort direction used when none is given.
+    pub fn default_order(&self) -> SortOrder {
+        match self {
+            SortField::Price => SortOrder::Asc,
+            _ => SortOrder::Desc,
+        }
+    }
+
+    /// The value of the field as stored for a product, which the database sorts on.
+    pub fn value(&self, product: &Product) -> f64 {
+        match self {
+            SortField::Price => product.price as f64,
+            SortField::Rating => product.rating as f64,
+            SortField::Sales => product.sales as f64,
+            SortField::Clicks => product.clicks as f64,
+            SortField::Created => product.created_at as f64,
+        }
+    }
+}
+
+/// An enum representing a sort direction.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Smallest values first.
+    Asc,
+
+    /// Largest values first.
+    Desc,
+}
+
+/// Represents a page of product views.
+#[derive(Serialize)]
+pub struct ProductPage {
+    /// The product views of the page.
+    pub products: Vec<ProductView>,
+
+    /// The cursor of the next page, or `None` if this is the last page.
+    pub next_cursor: Option<String>,
+
+    /// The total number of products across all pages.
+    pub total: u64,
 }
 
 /// Represents the query parameters for updating product data.
@@ -100,7 +279,7 @@ pub struct UpdateQuery {
     /// The product image as a URL.
     pub image: String,
 
-    /// The product price.
+    /// The product regular price.
     pub price: f32,
 
     /// The product stock level.
@@ -113,18 +292,50 @@ pub struct UpdateQuery {
     pub rating: f32,
 }
 
+/// Represents the query parameters for updating variant data.
+#[derive(Deserialize)]
+pub struct UpdateVariantQuery {
+    /// The variant price.
+    pub price: Option<f32>,
+
+    /// The variant stock level.
+    pub stock: Option<u32>,
+
+    /// The variant image as a URL.
+    pub image: Option<String>,
+}
+
+/// Represents the query parameters for removing a gallery image.
+#[derive(Deserialize)]
+pub struct ImageQuery {
+    /// The image as a URL.
+    pub url: String,
+}
+
+/// Extract the product versions listed in an `If-Match` header.
+///
+/// # Returns
+///
+/// - Returns the listed versions, or `None` if the header is missing or matches any version.
+pub fn if_match(header: Option<web::Header<IfMatch>>) -> Option<Vec<String>> {
+    match header.map(web::Header::into_inner) {
+        Some(IfMatch::Items(tags)) => Some(tags.iter().map(|tag| tag.tag().to_string()).collect()),
+        _ => None,
+    }
+}
+
 /// Retrieve data for a specific product.
 ///
 /// This function is an Actix web handler for fetching detailed data for a product based on its ID.
-
+///
 /// # Parameters
 ///
 /// - `path`: Path parameter containing the product ID.
 /// - `client`: MongoDB client data.
-
+///
 /// # Returns
 ///
-/// - Returns an `HttpResponse` with the product data in JSON format if found, `HttpResponse::NoContent()` if not found, or an `HttpResponse::InternalServerError()` in case of an error.
+/// - Returns an `HttpResponse` with the product data in JSON format and the product version as its `ETag` if found, `HttpResponse::NoContent()` if not found, or an `HttpResponse::InternalServerError()` in case of an error.
 #[get("/api/product/{pid}/data")]
 pub async fn data(
     path: web::Path<(String,)>,
@@ -133,6 +344,7 @@ pub async fn data(
     match product::retrieve_data(path.0.clone(), client.get_ref().clone()).await {
         Ok(Some(data)) => Ok(HttpResponse::Ok()
             .content_type("application/json")
+            .insert_header(ETag(EntityTag::new_strong(data.version.to_string())))
             .json(data)),
         Ok(None) => Ok(HttpResponse::NoContent().finish()),
         Err(_) => Ok(HttpResponse::InternalServerError().finish()),
@@ -142,12 +354,12 @@ pub async fn data(
 /// Search for products based on a query.
 ///
 /// This function is an Actix web handler for searching for products based on a search query.
-
+///
 /// # Parameters
 ///
 /// - `query`: Query parameters containing the search query.
 /// - `ms_client`: MeiliSearch client data.
-
+///
 /// # Returns
 ///
 /// - Returns an `HttpResponse` with search results in JSON format if successful, or an `HttpResponse::InternalServerError()` in case of an error.
@@ -165,54 +377,100 @@ pub async fn search(
     }
 }
 
-/// Paginate and retrieve product data.
+/// Retrieve the facet distribution of a search.
+///
+/// This function is an Actix web handler for counting the attribute values of the products matching a search query.
 ///
-/// This function is an Actix web handler for paginating and fetching product data for a specific page.
+/// # Parameters
+///
+/// - `query`: Query parameters containing the search query, the filters and the attributes to facet on.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the value counts in JSON format if successful, or an `HttpResponse::InternalServerError()` in case of an error.
+#[get("/api/product/facets")]
+pub async fn facets(
+    query: web::Query<SearchQuery>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match product::retrieve_facets(query.into_inner(), ms_client.get_ref().clone()).await {
+        Ok(facets) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(facets)),
+        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+    }
+}
 
+/// Paginate and retrieve product data.
+///
+/// This function is an Actix web handler for paginating and fetching product data. Requests with a page number are served with offset pagination and answered with a plain list for backward compatibility, all other requests are served with cursor pagination. Both modes accept the same sort and filter parameters.
+///
 /// # Parameters
 ///
-/// - `query`: Query parameters containing the page number for pagination.
+/// - `query`: Query parameters containing the page number or the cursor, the optional page size, and the optional sort and filters.
 /// - `client`: MongoDB client data.
-
+///
 /// # Returns
 ///
-/// - Returns an `HttpResponse` with paginated product data in JSON format if successful, or an `HttpResponse::InternalServerError()` in case of an error.
+/// - Returns an `HttpResponse` with the page of product data in JSON format if successful, an `HttpResponse::BadRequest()` if the pagination, sort or filter parameters are invalid, or an `HttpResponse::InternalServerError()` in case of an error.
 #[get("/api/product/view")]
 pub async fn page(
     query: web::Query<PageQuery>,
     client: web::Data<Client>,
 ) -> Result<HttpResponse, Error> {
-    match product::retrieve_paginated_products(query.into_inner(), client.get_ref().clone()).await {
-        Ok(products) => Ok(HttpResponse::Ok()
+    let query = query.into_inner();
+
+    if query.page.is_some() && query.cursor.is_some() {
+        return Ok(HttpResponse::BadRequest().body("Page and cursor cannot be combined"));
+    }
+
+    if query.page.is_some() {
+        return match product::retrieve_paginated_products(query, client.get_ref().clone()).await {
+            Ok(products) => Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .json(products)),
+            Err(error) => Ok(error.error_response()),
+        };
+    }
+
+    match product::retrieve_product_page(query, client.get_ref().clone()).await {
+        Ok(page) => Ok(HttpResponse::Ok()
             .content_type("application/json")
-            .json(products)),
-        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+            .json(page)),
+        Err(error) => Ok(error.error_response()),
     }
 }
 
 /// Update product information.
 ///
-/// This function is an Actix web handler for updating a product. It receives the product ID as a path parameter and query parameters and delegates the operation to the `profile::update_product` function.
+/// This function is an Actix web handler for updating a product. It receives the product ID as a path parameter and query parameters and delegates the operation to the `profile::update_product` function. If an `If-Match` header is given, the product is only updated if its version matches.
 ///
 /// # Parameters
 ///
 /// - `path`: The product ID (pid) extracted from the URL path.
 /// - `query`: The update query containing the fields to be updated, parsed from the request query parameters.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
 /// - `client`: The Actix web data object containing the MongoDB client connection.
 ///
 /// # Returns
 ///
-/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, or `Ok(HttpResponse::InternalServerError())`.
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::PreconditionFailed())`, or `Ok(HttpResponse::InternalServerError())`.
 #[patch("/api/product/{pid}")]
 pub async fn update(
     path: web::Path<(String,)>,
     query: web::Query<UpdateQuery>,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
     client: web::Data<Client>,
     ms_client: web::Data<meilisearch_sdk::Client>,
 ) -> Result<HttpResponse, Error> {
     match product::update_product(
         path.0.clone(),
         query.into_inner(),
+        if_match(header),
+        actor,
         client.get_ref().clone(),
         ms_client.get_ref().clone(),
     )
@@ -220,6 +478,386 @@ pub async fn update(
     {
         Ok(true) => Ok(HttpResponse::Ok().finish()),
         Ok(false) => Ok(HttpResponse::NoContent().finish()),
-        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Add a variant to a product.
+///
+/// This function is an Actix web handler for adding a variant to a product. It receives the product ID as a path parameter and the variant as a JSON body and delegates the operation to the `product::add_variant` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `json`: The variant to be added.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::Conflict())`, `Ok(HttpResponse::BadRequest())` if another variant has the same option values, `Ok(HttpResponse::PreconditionFailed())`, or `Ok(HttpResponse::InternalServerError())`.
+#[post("/api/product/{pid}/variant")]
+pub async fn add_variant(
+    path: web::Path<(String,)>,
+    json: web::Json<Variant>,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match product::add_variant(
+        path.0.clone(),
+        json.into_inner(),
+        if_match(header),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::Conflict().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Update a variant of a product.
+///
+/// This function is an Actix web handler for updating the price, stock level and image of a variant. It receives the product ID and the variant SKU as path parameters and delegates the operation to the `product::update_variant` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameters containing the product ID and the variant SKU.
+/// - `query`: The update query containing the fields to be updated.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::PreconditionFailed())`, or `Ok(HttpResponse::InternalServerError())`.
+#[patch("/api/product/{pid}/variant/{sku}")]
+pub async fn update_variant(
+    path: web::Path<(String, String)>,
+    query: web::Query<UpdateVariantQuery>,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match product::update_variant(
+        path.0.clone(),
+        path.1.clone(),
+        query.into_inner(),
+        if_match(header),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Remove a variant from a product.
+///
+/// This function is an Actix web handler for removing a variant from a product. It receives the product ID and the variant SKU as path parameters and delegates the operation to the `product::remove_variant` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameters containing the product ID and the variant SKU.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::PreconditionFailed())`, or `Ok(HttpResponse::InternalServerError())`.
+#[delete("/api/product/{pid}/variant/{sku}")]
+pub async fn remove_variant(
+    path: web::Path<(String, String)>,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match product::remove_variant(
+        path.0.clone(),
+        path.1.clone(),
+        if_match(header),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Add an image to a product gallery.
+///
+/// This function is an Actix web handler for adding an image to a product gallery. It receives the product ID as a path parameter and the image as a JSON body and delegates the operation to the `product::add_image` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `json`: The image to be added.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::Conflict())`, `Ok(HttpResponse::PreconditionFailed())`, or `Ok(HttpResponse::InternalServerError())`.
+#[post("/api/product/{pid}/image")]
+pub async fn add_image(
+    path: web::Path<(String,)>,
+    json: web::Json<Image>,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match product::add_image(
+        path.0.clone(),
+        json.into_inner(),
+        if_match(header),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::Conflict().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Upload an image to a product gallery.
+///
+/// This function is an Actix web handler for uploading an image to a product gallery. It receives the product ID as a path parameter and the image as the `image` field of a multipart form, stores it with its thumbnail and WebP variants using the `storage::store_image` function, and adds it to the gallery as the primary image.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `payload`: The multipart form containing the image.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+/// - `storage`: The storage backend data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the added image in JSON format if successful, `HttpResponse::NoContent()` if the product does not exist, `HttpResponse::BadRequest()` if the upload is not a supported image, `HttpResponse::PayloadTooLarge()` if the upload exceeds the size limit, `HttpResponse::PreconditionFailed()` if the product version does not match, or an `HttpResponse::InternalServerError()` in case of an error.
+#[post("/api/product/{pid}/image/upload")]
+pub async fn upload_image(
+    path: web::Path<(String,)>,
+    mut payload: Multipart,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+    storage: web::Data<dyn Storage>,
+) -> Result<HttpResponse, Error> {
+    let pid = path.0.clone();
+    let if_match = if_match(header);
+
+    // The version is checked before the upload is stored, so a stale request leaves no orphaned files.
+    match product::retrieve_data(pid.clone(), client.get_ref().clone()).await {
+        Ok(Some(stored)) => match &if_match {
+            Some(versions) if !versions.contains(&stored.version.to_string()) => {
+                return Ok(HttpResponse::PreconditionFailed().finish())
+            }
+            _ => {}
+        },
+        Ok(None) => return Ok(HttpResponse::NoContent().finish()),
+        Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+    }
+
+    let mut bytes: Vec<u8> = Vec::new();
+
+    while let Some(Ok(mut field)) = payload.next().await {
+        if field.name() != Some("image") {
+            continue;
+        }
+
+        while let Some(chunk) = field.next().await {
+            bytes.extend_from_slice(&chunk?);
+
+            if bytes.len() > storage::MAX_IMAGE_SIZE {
+                return Ok(HttpResponse::PayloadTooLarge().finish());
+            }
+        }
+    }
+
+    let key = pid.clone();
+    let image =
+        match web::block(move || storage::store_image(&key, &bytes, storage.get_ref())).await? {
+            Ok(image) => image,
+            Err(error) if error.kind() == ErrorKind::InvalidData => {
+                return Ok(HttpResponse::BadRequest().finish())
+            }
+            Err(_) => return Ok(HttpResponse::InternalServerError().finish()),
+        };
+
+    match product::add_image(
+        pid,
+        image.clone(),
+        if_match,
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(image)),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Reorder a product gallery.
+///
+/// This function is an Actix web handler for reordering a product gallery. It receives the product ID as a path parameter and the image URLs in their new order as a JSON body and delegates the operation to the `product::order_images` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `json`: The image URLs in their new order.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::Conflict())`, `Ok(HttpResponse::PreconditionFailed())`, or `Ok(HttpResponse::InternalServerError())`.
+#[patch("/api/product/{pid}/image/order")]
+pub async fn order_images(
+    path: web::Path<(String,)>,
+    json: web::Json<Vec<String>>,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match product::order_images(
+        path.0.clone(),
+        json.into_inner(),
+        if_match(header),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::Conflict().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Remove an image from a product gallery.
+///
+/// This function is an Actix web handler for removing an image from a product gallery. It receives the product ID as a path parameter and the image URL as a query parameter and delegates the operation to the `product::remove_image` function. Uploaded images are also removed from the storage.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `query`: Query parameters containing the image URL.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+/// - `storage`: The storage backend data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::PreconditionFailed())`, or `Ok(HttpResponse::InternalServerError())`.
+#[delete("/api/product/{pid}/image")]
+pub async fn remove_image(
+    path: web::Path<(String,)>,
+    query: web::Query<ImageQuery>,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+    storage: web::Data<dyn Storage>,
+) -> Result<HttpResponse, Error> {
+    let url = query.into_inner().url;
+
+    match product::remove_image(
+        path.0.clone(),
+        url.clone(),
+        if_match(header),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => {
+            web::block(move || storage::remove_image(&url, storage.get_ref())).await?;
+
+            Ok(HttpResponse::Ok().finish())
+        }
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Replace the attributes of a product.
+///
+/// This function is an Actix web handler for replacing the attributes of a product. It receives the product ID as a path parameter and the attributes as a JSON body and delegates the operation to the `product::update_attributes` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `json`: The product attributes keyed by attribute name.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::BadRequest())` with the schema violation, `Ok(HttpResponse::PreconditionFailed())`, or `Ok(HttpResponse::InternalServerError())`.
+#[patch("/api/product/{pid}/attributes")]
+pub async fn update_attributes(
+    path: web::Path<(String,)>,
+    json: web::Json<BTreeMap<String, AttributeValue>>,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match product::update_attributes(
+        path.0.clone(),
+        json.into_inner(),
+        if_match(header),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
     }
 }
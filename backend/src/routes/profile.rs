@@ -4,10 +4,15 @@
 //! See the LICENSE file for details.
 
 use crate::crud::profile;
+use crate::routes::audit::Actor;
+use crate::routes::product;
+use crate::structures::AttributeValue;
 
+use actix_web::http::header::IfMatch;
 use actix_web::{web, Error, HttpResponse};
 use mongodb::Client;
 use serde::Deserialize;
+use std::collections::BTreeMap;
 
 /// Struct representing the query parameters for adding a product.
 #[derive(Deserialize)]
@@ -35,17 +40,21 @@ pub struct ProductQuery {
 
     /// The product stock level.
     pub stock: u32,
+
+    /// Whether the product is created as a draft instead of being listed right away.
+    #[serde(default)]
+    pub draft: bool,
 }
 
 /// Retrieve products sold by the specified seller id.
 ///
 /// This function is an Actix web handler for retrieving products sold by a specific seller. It receives the seller id as a path parameter and delegates the operation to the `profile::retrieve_seller_products` function.
-
+///
 /// # Parameters
 ///
 /// - `path`: Path parameters containing the seller id.
 /// - `client`: MongoDB client data.
-
+///
 /// # Returns
 ///
 /// - Returns an `HttpResponse` indicating success or an error. If the operation was successful and the seller has products, it returns `Ok(HttpResponse::Ok().content_type("application/json").json(products))`. If the seller exists but has no products, it returns `Ok(HttpResponse::NoContent().finish())`. If the operation was not successful, it returns `Ok(HttpResponse::InternalServerError().finish())`.
@@ -71,25 +80,31 @@ pub async fn seller_products(
 
 /// Add a product to the seller's profile.
 ///
-/// This function is an Actix web handler for adding a product to a seller's profile. It receives product information as query parameters and delegates the operation to the `profile::list_product` function.
-
+/// This function is an Actix web handler for adding a product to a seller's profile. It receives product information as query parameters and the optional product attributes as a JSON body and delegates the operation to the `profile::list_product` function.
+///
 /// # Parameters
 ///
 /// - `query`: Query parameters containing the product information.
+/// - `json`: The optional product attributes keyed by attribute name.
+/// - `actor`: The actor performing the request.
 /// - `client`: MongoDB client data.
 /// - `ms_client`: MeiliSearch client data.
-
+///
 /// # Returns
 ///
-/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::Conflict())`, or `Ok(HttpResponse::InternalServerError())`.
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::Conflict())`, `Ok(HttpResponse::BadRequest())` with the schema violation, or `Ok(HttpResponse::InternalServerError())`.
 #[post("/api/profile/seller/add/product")]
 pub async fn add_product(
     query: web::Query<ProductQuery>,
+    json: Option<web::Json<BTreeMap<String, AttributeValue>>>,
+    actor: Actor,
     client: web::Data<Client>,
     ms_client: web::Data<meilisearch_sdk::Client>,
 ) -> Result<HttpResponse, Error> {
     match profile::list_product(
         query.into_inner(),
+        json.map(web::Json::into_inner).unwrap_or_default(),
+        actor,
         client.get_ref().clone(),
         ms_client.get_ref().clone(),
     )
@@ -97,31 +112,107 @@ pub async fn add_product(
     {
         Ok(true) => Ok(HttpResponse::Ok().finish()),
         Ok(false) => Ok(HttpResponse::Conflict().finish()),
-        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+        Err(error) => Ok(error.error_response()),
     }
 }
 
 /// Remove a product from the seller's profile.
 ///
-/// This function is an Actix web handler for removing a product from a seller's profile. It receives the product ID as a path parameter and delegates the operation to the `profile::delist_product` function.
-
+/// This function is an Actix web handler for removing a product from a seller's profile. It receives the product ID as a path parameter and delegates the operation to the `profile::delist_product` function, which archives the product so it can be restored later. If an `If-Match` header is given, the product is only removed if its version matches.
+///
 /// # Parameters
 ///
 /// - `path`: Path parameter containing the product ID.
+/// - `header`: The optional `If-Match` header containing the expected product versions.
+/// - `actor`: The actor performing the request.
 /// - `client`: MongoDB client data.
 /// - `ms_client`: MeiliSearch client data.
-
+///
 /// # Returns
 ///
-/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, or `Ok(HttpResponse::InternalServerError())`.
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::PreconditionFailed())`, or `Ok(HttpResponse::InternalServerError())`.
 #[delete("/api/profile/seller/remove/product/{pid}")]
 pub async fn remove_product(
     path: web::Path<(String,)>,
+    header: Option<web::Header<IfMatch>>,
+    actor: Actor,
     client: web::Data<Client>,
     ms_client: web::Data<meilisearch_sdk::Client>,
 ) -> Result<HttpResponse, Error> {
     match profile::delist_product(
         path.0.clone(),
+        product::if_match(header),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Restore a removed product to the seller's profile.
+///
+/// This function is an Actix web handler for restoring an archived product. It receives the product ID as a path parameter and delegates the operation to the `profile::restore_product` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::PreconditionFailed())` if the product was modified concurrently, or `Ok(HttpResponse::InternalServerError())`.
+#[post("/api/profile/seller/restore/product/{pid}")]
+pub async fn restore_product(
+    path: web::Path<(String,)>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match profile::restore_product(
+        path.0.clone(),
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Publish a draft product of the seller's profile.
+///
+/// This function is an Actix web handler for publishing a draft product. It receives the product ID as a path parameter and delegates the operation to the `profile::publish_product` function.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, `Ok(HttpResponse::PreconditionFailed())` if the product was modified concurrently, or `Ok(HttpResponse::InternalServerError())`.
+#[post("/api/profile/seller/publish/product/{pid}")]
+pub async fn publish_product(
+    path: web::Path<(String,)>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match profile::publish_product(
+        path.0.clone(),
+        actor,
         client.get_ref().clone(),
         ms_client.get_ref().clone(),
     )
@@ -129,6 +220,6 @@ pub async fn remove_product(
     {
         Ok(true) => Ok(HttpResponse::Ok().finish()),
         Ok(false) => Ok(HttpResponse::NoContent().finish()),
-        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+        Err(error) => Ok(error.error_response()),
     }
 }
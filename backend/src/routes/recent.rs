@@ -0,0 +1,58 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::recent;
+
+use actix_web::{web, Error, HttpResponse};
+use mongodb::Client;
+
+/// Retrieve the recently viewed products of a shopper.
+///
+/// This function is an Actix web handler for fetching the products a shopper viewed most recently, for a "recently viewed" carousel.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the user ID, or the session ID of an anonymous shopper.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the product views in JSON format, most recently viewed first, or an `HttpResponse::InternalServerError()` in case of an error.
+#[get("/api/profile/{uid}/recent")]
+pub async fn recent_products(
+    path: web::Path<(String,)>,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match recent::retrieve_recent(path.0.clone(), client.get_ref().clone()).await {
+        Ok(products) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(products)),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Delete the recently viewed products of a shopper.
+///
+/// This function is an Actix web handler for erasing the view history of a shopper, such as when they ask for their data to be removed.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the user ID, or the session ID of an anonymous shopper.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())` if the shopper had no history, or `Ok(HttpResponse::InternalServerError())`.
+#[delete("/api/profile/{uid}/recent")]
+pub async fn clear_recent(
+    path: web::Path<(String,)>,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match recent::clear_recent(path.0.clone(), client.get_ref().clone()).await {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
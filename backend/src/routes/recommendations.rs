@@ -0,0 +1,65 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::recommendations;
+use crate::routes::product::ProductView;
+
+use actix_web::{web, Error, HttpResponse};
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+
+/// Represents the data structure for the recommendations of a product.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Recommendations {
+    /// Similar products of the same category, most similar first.
+    pub related: Vec<ProductView>,
+
+    /// Products often purchased in the same sessions, most often bought together first.
+    pub bought_together: Vec<ProductView>,
+}
+
+/// Represents the query parameters for the recommendations of a product.
+#[derive(Deserialize)]
+pub struct RecommendationsQuery {
+    /// The maximum number of products of each list.
+    pub limit: Option<u32>,
+}
+
+/// Retrieve the recommendations of a specific product.
+///
+/// This function is an Actix web handler for fetching the related products and the products frequently bought together with a product.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `query`: Query parameters containing the optional number of products of each list.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the recommendations in JSON format if found, `HttpResponse::NoContent()` if the product does not exist, `HttpResponse::BadRequest()` if the limit is invalid, or an `HttpResponse::InternalServerError()` in case of an error.
+#[get("/api/product/{pid}/related")]
+pub async fn related(
+    path: web::Path<(String,)>,
+    query: web::Query<RecommendationsQuery>,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match recommendations::retrieve_recommendations(
+        path.0.clone(),
+        query.into_inner(),
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(Some(recommendations)) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(recommendations)),
+        Ok(None) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
@@ -0,0 +1,127 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::stock;
+use crate::routes::audit::Actor;
+
+use actix_web::{web, Error, HttpResponse};
+use mongodb::Client;
+use serde::Deserialize;
+
+/// Represents the request body for setting the low stock threshold of a product.
+#[derive(Deserialize)]
+pub struct ThresholdRequest {
+    /// The stock level at or below which the product is low on stock, or `null` for the default threshold.
+    pub threshold: Option<u32>,
+}
+
+/// Represents the request body and query parameters identifying the shopper of a back in stock subscription.
+#[derive(Deserialize)]
+pub struct SubscriptionRequest {
+    /// The user ID of the shopper.
+    pub uid: String,
+}
+
+/// Set the low stock threshold of a product.
+///
+/// This function is an Actix web handler for setting the stock level at or below which a low stock alert is emitted for a product.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `json`: The threshold, or `null` for the default threshold.
+/// - `actor`: The actor performing the request.
+/// - `client`: MongoDB client data.
+/// - `ms_client`: MeiliSearch client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())`, or `Ok(HttpResponse::InternalServerError())`.
+#[post("/api/product/{pid}/stock/threshold")]
+pub async fn set_threshold(
+    path: web::Path<(String,)>,
+    json: web::Json<ThresholdRequest>,
+    actor: Actor,
+    client: web::Data<Client>,
+    ms_client: web::Data<meilisearch_sdk::Client>,
+) -> Result<HttpResponse, Error> {
+    match stock::update_threshold(
+        path.0.clone(),
+        json.into_inner().threshold,
+        actor,
+        client.get_ref().clone(),
+        ms_client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Subscribe a shopper to be notified when a product is back in stock.
+///
+/// This function is an Actix web handler for the "notify me when back in stock" button of an out of stock product.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `json`: The user ID of the shopper.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())` if the product is not listed, `Ok(HttpResponse::BadRequest())` if the product is in stock, or `Ok(HttpResponse::InternalServerError())`.
+#[post("/api/product/{pid}/restock/subscription")]
+pub async fn subscribe(
+    path: web::Path<(String,)>,
+    json: web::Json<SubscriptionRequest>,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match stock::subscribe(
+        path.0.clone(),
+        json.into_inner().uid,
+        client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Unsubscribe a shopper from the back in stock notification of a product.
+///
+/// This function is an Actix web handler for cancelling a "notify me when back in stock" request.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the product ID.
+/// - `query`: Query parameters containing the user ID of the shopper.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())` if the shopper was not subscribed, or `Ok(HttpResponse::InternalServerError())`.
+#[delete("/api/product/{pid}/restock/subscription")]
+pub async fn unsubscribe(
+    path: web::Path<(String,)>,
+    query: web::Query<SubscriptionRequest>,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match stock::unsubscribe(
+        path.0.clone(),
+        query.into_inner().uid,
+        client.get_ref().clone(),
+    )
+    .await
+    {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
@@ -0,0 +1,81 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud;
+use crate::trending::{ListCache, ListKind};
+
+use actix_web::{web, Error, HttpResponse};
+use mongodb::Client;
+use serde::Deserialize;
+
+/// Represents the query parameters for a homepage product list.
+#[derive(Deserialize)]
+pub struct ListQuery {
+    /// The category to rank the products of, or empty for every category.
+    #[serde(default)]
+    pub category: String,
+
+    /// The maximum number of products.
+    pub limit: Option<u32>,
+}
+
+/// Retrieve the trending products.
+///
+/// This function is an Actix web handler for fetching the products with the most clicks and purchases over the last hours, optionally within a category.
+///
+/// # Parameters
+///
+/// - `query`: Query parameters containing the optional category and number of products.
+/// - `cache`: The product list cache data.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the product views in JSON format if successful, an `HttpResponse::BadRequest()` if the limit is invalid, or an `HttpResponse::InternalServerError()` in case of an error.
+#[get("/api/product/trending")]
+pub async fn trending(
+    query: web::Query<ListQuery>,
+    cache: web::Data<ListCache>,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    list(ListKind::Trending, query.into_inner(), &cache, &client).await
+}
+
+/// Retrieve the best selling products.
+///
+/// This function is an Actix web handler for fetching the products with the most purchases over the last days, optionally within a category.
+///
+/// # Parameters
+///
+/// - `query`: Query parameters containing the optional category and number of products.
+/// - `cache`: The product list cache data.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the product views in JSON format if successful, an `HttpResponse::BadRequest()` if the limit is invalid, or an `HttpResponse::InternalServerError()` in case of an error.
+#[get("/api/product/bestsellers")]
+pub async fn bestsellers(
+    query: web::Query<ListQuery>,
+    cache: web::Data<ListCache>,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    list(ListKind::BestSellers, query.into_inner(), &cache, &client).await
+}
+
+/// Respond with a homepage product list.
+async fn list(
+    kind: ListKind,
+    query: ListQuery,
+    cache: &ListCache,
+    client: &Client,
+) -> Result<HttpResponse, Error> {
+    match crud::trending::retrieve_list(kind, query, cache, client).await {
+        Ok(products) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(products)),
+        Err(error) => Ok(error.error_response()),
+    }
+}
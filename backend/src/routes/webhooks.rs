@@ -0,0 +1,180 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::crud::webhooks;
+use crate::routes::admin::Admin;
+use crate::structures::WebhookEvent;
+
+use actix_web::{web, Error, HttpResponse};
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+
+/// Represents the request body for subscribing to product lifecycle events.
+#[derive(Deserialize)]
+pub struct WebhookRequest {
+    /// The URL the events are posted to.
+    pub url: String,
+
+    /// The secret the deliveries are signed with.
+    pub secret: String,
+
+    /// The events to deliver.
+    pub events: Vec<WebhookEvent>,
+}
+
+/// Represents a webhook subscription as returned to admins, without its secret.
+#[derive(Serialize)]
+pub struct WebhookView {
+    /// The subscription ID.
+    pub id: String,
+
+    /// The URL the events are posted to.
+    pub url: String,
+
+    /// The events delivered to the subscription.
+    pub events: Vec<WebhookEvent>,
+
+    /// The subscription time in milliseconds since the Unix epoch.
+    pub created_at: i64,
+}
+
+/// Represents the query parameters for paginating the dead letters.
+#[derive(Deserialize)]
+pub struct DeadLetterQuery {
+    /// The page number for pagination, starting at 1.
+    #[serde(default)]
+    pub page: u32,
+}
+
+/// Subscribe to product lifecycle events.
+///
+/// This function is an Actix web handler for registering the URL another module receives product events on.
+///
+/// # Parameters
+///
+/// - `json`: The URL, secret and events of the subscription.
+/// - `_admin`: The admin authorization of the request.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the created subscription in JSON format, an `HttpResponse::BadRequest()` if the subscription is invalid, or an `HttpResponse::InternalServerError()` in case of an error.
+#[post("/api/admin/webhooks")]
+pub async fn create_subscription(
+    json: web::Json<WebhookRequest>,
+    _admin: Admin,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match webhooks::create_subscription(json.into_inner(), client.get_ref().clone()).await {
+        Ok(subscription) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(subscription)),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Retrieve the webhook subscriptions.
+///
+/// This function is an Actix web handler for listing the modules subscribed to product events.
+///
+/// # Parameters
+///
+/// - `_admin`: The admin authorization of the request.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the subscriptions in JSON format, or an `HttpResponse::InternalServerError()` in case of an error.
+#[get("/api/admin/webhooks")]
+pub async fn subscriptions(
+    _admin: Admin,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match webhooks::retrieve_subscriptions(client.get_ref().clone()).await {
+        Ok(subscriptions) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(subscriptions)),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Delete a webhook subscription.
+///
+/// This function is an Actix web handler for unsubscribing a module from product events, dropping the deliveries still pending for it.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the subscription ID.
+/// - `_admin`: The admin authorization of the request.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())` if the subscription does not exist, or `Ok(HttpResponse::InternalServerError())`.
+#[delete("/api/admin/webhooks/{id}")]
+pub async fn delete_subscription(
+    path: web::Path<(String,)>,
+    _admin: Admin,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match webhooks::delete_subscription(path.0.clone(), client.get_ref().clone()).await {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Retrieve the webhook dead letters.
+///
+/// This function is an Actix web handler for paging through the deliveries given up after failing every attempt.
+///
+/// # Parameters
+///
+/// - `query`: Query parameters containing the page number.
+/// - `_admin`: The admin authorization of the request.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` with the dead letters in JSON format, or an `HttpResponse::InternalServerError()` in case of an error.
+#[get("/api/admin/webhooks/dead")]
+pub async fn dead_letters(
+    query: web::Query<DeadLetterQuery>,
+    _admin: Admin,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match webhooks::retrieve_dead_letters(query.into_inner(), client.get_ref().clone()).await {
+        Ok(dead_letters) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(dead_letters)),
+        Err(error) => Ok(error.error_response()),
+    }
+}
+
+/// Retry a webhook dead letter.
+///
+/// This function is an Actix web handler for queueing a dead letter for delivery again, once its subscriber is fixed.
+///
+/// # Parameters
+///
+/// - `path`: Path parameter containing the delivery ID.
+/// - `_admin`: The admin authorization of the request.
+/// - `client`: MongoDB client data.
+///
+/// # Returns
+///
+/// - Returns an `HttpResponse` indicating success or an error, such as `Ok(HttpResponse::Ok())`, `Ok(HttpResponse::NoContent())` if there is no such dead letter, `Ok(HttpResponse::BadRequest())` if its subscription was deleted, or `Ok(HttpResponse::InternalServerError())`.
+#[post("/api/admin/webhooks/dead/{id}/retry")]
+pub async fn retry_dead_letter(
+    path: web::Path<(String,)>,
+    _admin: Admin,
+    client: web::Data<Client>,
+) -> Result<HttpResponse, Error> {
+    match webhooks::retry_dead_letter(path.0.clone(), client.get_ref().clone()).await {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NoContent().finish()),
+        Err(error) => Ok(error.error_response()),
+    }
+}
@@ -0,0 +1,270 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::clicks::ClickBuffer;
+use crate::crud::{analytics, pricing, recommendations, stock, trending, webhooks};
+use crate::ranking::PopularitySettings;
+use crate::trending::ListCache;
+
+use log::{error, info};
+use mongodb::Client;
+use std::env;
+use std::future::Future;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The default number of seconds between two pricing runs.
+const DEFAULT_PRICING_INTERVAL: u64 = 60;
+
+/// The default number of seconds between two click flushes.
+const DEFAULT_CLICK_FLUSH_INTERVAL: u64 = 10;
+
+/// The default number of seconds between two popularity refreshes.
+const DEFAULT_POPULARITY_INTERVAL: u64 = 3600;
+
+/// The default number of seconds between two bought together computations.
+const DEFAULT_RECOMMENDATIONS_INTERVAL: u64 = 21_600;
+
+/// The default number of days of purchases counted in the bought together suggestions.
+const DEFAULT_RECOMMENDATIONS_WINDOW: i64 = 90;
+
+/// The default number of seconds between two refreshes of the trending and best selling products.
+const DEFAULT_TRENDING_INTERVAL: u64 = 300;
+
+/// The default number of seconds between two stock checks.
+const DEFAULT_STOCK_ALERT_INTERVAL: u64 = 60;
+
+/// The default number of seconds between two webhook delivery runs.
+const DEFAULT_WEBHOOK_INTERVAL: u64 = 5;
+
+/// Spawn a job run periodically, starting right away.
+///
+/// The interval between two runs is read from the given environment variable in seconds, and falls back to the default if it is missing or not a positive number. A run that takes longer than the interval delays the next one.
+///
+/// # Parameters
+///
+/// - `variable`: The name of the environment variable holding the interval.
+/// - `default`: The default interval in seconds.
+/// - `job`: The job, creating the future of each run.
+fn spawn_every<F, Fut>(variable: &str, default: u64, mut job: F)
+where
+    F: FnMut() -> Fut + 'static,
+    Fut: Future<Output = ()>,
+{
+    let seconds = env::var(variable)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(default);
+
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(Duration::from_secs(seconds));
+
+        loop {
+            interval.tick().await;
+            job().await;
+        }
+    });
+}
+
+/// Periodically apply the sales and scheduled price changes that are due.
+///
+/// The interval between two runs is read from the `PRICING_INTERVAL` environment variable in seconds and defaults to one minute.
+///
+/// # Parameters
+///
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+pub fn spawn_pricing(client: Client, ms_client: meilisearch_sdk::Client) {
+    spawn_every("PRICING_INTERVAL", DEFAULT_PRICING_INTERVAL, move || {
+        let (client, ms_client) = (client.clone(), ms_client.clone());
+
+        async move {
+            match pricing::apply_due_prices(&client, &ms_client).await {
+                Ok(0) => {}
+                Ok(count) => info!("Repriced {} products", count),
+                Err(error) => error!("Failed to apply scheduled prices: {}", error),
+            }
+        }
+    });
+}
+
+/// Periodically flush the buffered clicks to the database and the search index.
+///
+/// The interval between two flushes is read from the `CLICK_FLUSH_INTERVAL` environment variable in seconds and defaults to ten seconds.
+///
+/// # Parameters
+///
+/// - `clicks`: The buffer of click increments.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+pub fn spawn_click_flush(
+    clicks: Arc<ClickBuffer>,
+    client: Client,
+    ms_client: meilisearch_sdk::Client,
+) {
+    spawn_every(
+        "CLICK_FLUSH_INTERVAL",
+        DEFAULT_CLICK_FLUSH_INTERVAL,
+        move || {
+            let (clicks, client, ms_client) = (clicks.clone(), client.clone(), ms_client.clone());
+
+            async move { flush_clicks(&clicks, &client, &ms_client).await }
+        },
+    );
+}
+
+/// Flush the buffered clicks once, logging the outcome.
+///
+/// # Parameters
+///
+/// - `clicks`: The buffer of click increments.
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+pub async fn flush_clicks(
+    clicks: &ClickBuffer,
+    client: &Client,
+    ms_client: &meilisearch_sdk::Client,
+) {
+    match analytics::flush_clicks(clicks, client, ms_client).await {
+        Ok(0) => {}
+        Ok(count) => info!("Flushed the clicks of {} products", count),
+        Err(error) => error!("Failed to flush clicks: {}", error),
+    }
+}
+
+/// Periodically recompute the popularity score of the products, starting right away.
+///
+/// The interval between two runs is read from the `POPULARITY_INTERVAL` environment variable in seconds and defaults to one hour.
+///
+/// # Parameters
+///
+/// - `client`: MongoDB client instance used for database access.
+/// - `ms_client`: MeiliSearch client instance used for indexing.
+pub fn spawn_popularity(client: Client, ms_client: meilisearch_sdk::Client) {
+    let settings = Rc::new(PopularitySettings::from_env());
+
+    spawn_every(
+        "POPULARITY_INTERVAL",
+        DEFAULT_POPULARITY_INTERVAL,
+        move || {
+            let (settings, client, ms_client) =
+                (settings.clone(), client.clone(), ms_client.clone());
+
+            async move {
+                match analytics::refresh_popularity(&settings, &client, &ms_client).await {
+                    Ok(0) => {}
+                    Ok(count) => info!("Updated the popularity of {} products", count),
+                    Err(error) => error!("Failed to refresh popularity: {}", error),
+                }
+            }
+        },
+    );
+}
+
+/// Periodically recompute the products frequently bought together, starting right away.
+///
+/// The interval between two runs is read from the `RECOMMENDATIONS_INTERVAL` environment variable in seconds and defaults to six hours. The number of days of purchases counted is read from the `RECOMMENDATIONS_WINDOW` environment variable and defaults to 90 days.
+///
+/// # Parameters
+///
+/// - `client`: MongoDB client instance used for database access.
+pub fn spawn_recommendations(client: Client) {
+    let window = env::var("RECOMMENDATIONS_WINDOW")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(DEFAULT_RECOMMENDATIONS_WINDOW);
+
+    spawn_every(
+        "RECOMMENDATIONS_INTERVAL",
+        DEFAULT_RECOMMENDATIONS_INTERVAL,
+        move || {
+            let client = client.clone();
+
+            async move {
+                match recommendations::compute_bought_together(window, &client).await {
+                    Ok(count) => info!(
+                        "Computed the bought together suggestions of {} products",
+                        count
+                    ),
+                    Err(error) => {
+                        error!("Failed to compute bought together suggestions: {}", error)
+                    }
+                }
+            }
+        },
+    );
+}
+
+/// Periodically rank the trending and best selling products, starting right away.
+///
+/// The interval between two runs is read from the `TRENDING_INTERVAL` environment variable in seconds and defaults to five minutes.
+///
+/// # Parameters
+///
+/// - `cache`: The cache of the product lists.
+/// - `client`: MongoDB client instance used for database access.
+pub fn spawn_lists(cache: Arc<ListCache>, client: Client) {
+    spawn_every("TRENDING_INTERVAL", DEFAULT_TRENDING_INTERVAL, move || {
+        let (cache, client) = (cache.clone(), client.clone());
+
+        async move {
+            if let Err(error) = trending::refresh_lists(&cache, &client).await {
+                error!(
+                    "Failed to rank the trending and best selling products: {}",
+                    error
+                );
+            }
+        }
+    });
+}
+
+/// Periodically check the stock levels of the listed products and deliver the stock alerts, starting right away.
+///
+/// The interval between two checks is read from the `STOCK_ALERT_INTERVAL` environment variable in seconds and defaults to one minute.
+///
+/// # Parameters
+///
+/// - `client`: MongoDB client instance used for database access.
+pub fn spawn_stock_alerts(client: Client) {
+    spawn_every(
+        "STOCK_ALERT_INTERVAL",
+        DEFAULT_STOCK_ALERT_INTERVAL,
+        move || {
+            let client = client.clone();
+
+            async move {
+                match stock::check_stock(&client).await {
+                    Ok(0) => {}
+                    Ok(count) => info!("Emitted {} stock alerts", count),
+                    Err(error) => error!("Failed to check stock levels: {}", error),
+                }
+            }
+        },
+    );
+}
+
+/// Periodically attempt the webhook deliveries that are due.
+///
+/// The interval between two runs is read from the `WEBHOOK_INTERVAL` environment variable in seconds and defaults to five seconds.
+///
+/// # Parameters
+///
+/// - `client`: MongoDB client instance used for database access.
+pub fn spawn_webhooks(client: Client) {
+    spawn_every("WEBHOOK_INTERVAL", DEFAULT_WEBHOOK_INTERVAL, move || {
+        let client = client.clone();
+
+        async move {
+            match webhooks::deliver_webhooks(&client).await {
+                Ok(0) => {}
+                Ok(count) => info!("Delivered {} webhooks", count),
+                Err(error) => error!("Failed to deliver webhooks: {}", error),
+            }
+        }
+    });
+}
@@ -0,0 +1,222 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::structures::Image;
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageFormat};
+use log::error;
+use std::fs;
+use std::io::{self, Cursor, Error};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The maximum size of an uploaded image in bytes.
+pub const MAX_IMAGE_SIZE: usize = 5 * 1024 * 1024;
+
+/// The width and height bound of a generated thumbnail in pixels.
+const THUMBNAIL_SIZE: u32 = 320;
+
+/// A trait representing a storage backend for uploaded files.
+pub trait Storage: Send + Sync {
+    /// Store the given bytes under the given key.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the public URL of the stored file, or an `Error` if the file could not be stored.
+    fn store(&self, key: &str, bytes: &[u8]) -> Result<String, Error>;
+
+    /// Remove the file stored under the given key.
+    fn remove(&self, key: &str) -> Result<(), Error>;
+
+    /// Resolve the public URL of a stored file back to its key, or `None` if the URL is not served by this storage.
+    fn key(&self, url: &str) -> Option<String>;
+}
+
+/// A storage backend keeping files on the local filesystem.
+pub struct LocalStorage {
+    /// The directory the files are stored in.
+    root: PathBuf,
+
+    /// The URL path the directory is served under.
+    base_url: String,
+}
+
+impl LocalStorage {
+    /// Create a local storage backend, creating its directory if it does not exist.
+    pub fn new(root: impl Into<PathBuf>, base_url: &str) -> Result<Self, Error> {
+        let root = root.into();
+
+        fs::create_dir_all(&root)?;
+
+        Ok(Self {
+            root,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// The directory the files are stored in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolve a key to a path inside the storage directory, rejecting keys that escape it.
+    fn path(&self, key: &str) -> Result<PathBuf, Error> {
+        if key
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+        {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid key"));
+        }
+
+        Ok(self.root.join(key))
+    }
+}
+
+impl Storage for LocalStorage {
+    fn store(&self, key: &str, bytes: &[u8]) -> Result<String, Error> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&path, bytes)?;
+
+        Ok(format!("{}/{}", self.base_url, key))
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Error> {
+        fs::remove_file(self.path(key)?)
+    }
+
+    fn key(&self, url: &str) -> Option<String> {
+        url.strip_prefix(self.base_url.as_str())?
+            .strip_prefix('/')
+            .map(|key| key.to_string())
+    }
+}
+
+/// Validate, resize and store an uploaded product image.
+///
+/// This function checks that the upload is a supported image within the size limit, then stores the original alongside a thumbnail and WebP variants of both.
+///
+/// # Parameters
+///
+/// - `pid`: The product ID the image belongs to, used as the storage key prefix.
+/// - `bytes`: The uploaded image.
+/// - `storage`: The storage backend the files are stored in.
+///
+/// # Returns
+///
+/// Returns a `Result` containing the gallery `Image` pointing at the stored original, or an `Error` of kind `InvalidData` if the upload is rejected, or any other kind if storing fails.
+pub fn store_image(pid: &str, bytes: &[u8], storage: &dyn Storage) -> Result<Image, Error> {
+    if bytes.len() > MAX_IMAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Image too large",
+        ));
+    }
+
+    let format = match image::guess_format(bytes) {
+        Ok(
+            format @ (ImageFormat::Gif | ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP),
+        ) => format,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unsupported image type",
+            ))
+        }
+    };
+
+    let decoded = image::load_from_memory_with_format(bytes, format)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    let (width, height) = decoded.dimensions();
+    let thumbnail = decoded.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Lanczos3);
+
+    let stem = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let extension = format.extensions_str().first().copied().unwrap_or("img");
+
+    let url = storage.store(&format!("{}/{}.{}", pid, stem, extension), bytes)?;
+    storage.store(
+        &format!("{}/{}-thumbnail.{}", pid, stem, extension),
+        &encode(&thumbnail, format)?,
+    )?;
+
+    if format != ImageFormat::WebP {
+        storage.store(
+            &format!("{}/{}.webp", pid, stem),
+            &encode(&decoded, ImageFormat::WebP)?,
+        )?;
+        storage.store(
+            &format!("{}/{}-thumbnail.webp", pid, stem),
+            &encode(&thumbnail, ImageFormat::WebP)?,
+        )?;
+    }
+
+    Ok(Image {
+        url,
+        width,
+        height,
+        primary: true,
+        ..Image::default()
+    })
+}
+
+/// Remove an uploaded product image along with its thumbnail and WebP variants.
+///
+/// Images that are not served by the given storage, such as externally hosted URLs, are left untouched.
+///
+/// # Parameters
+///
+/// - `url`: The public URL of the original image.
+/// - `storage`: The storage backend the files are stored in.
+pub fn remove_image(url: &str, storage: &dyn Storage) {
+    let key = match storage.key(url) {
+        Some(key) => key,
+        None => return,
+    };
+
+    let stem = match key.rsplit_once('.') {
+        Some((stem, _)) => stem,
+        None => key.as_str(),
+    };
+    let extension = &key[stem.len()..];
+
+    let mut variants = vec![key.clone(), format!("{}-thumbnail{}", stem, extension)];
+
+    if extension != ".webp" {
+        variants.push(format!("{}.webp", stem));
+        variants.push(format!("{}-thumbnail.webp", stem));
+    }
+
+    for variant in variants {
+        if let Err(error) = storage.remove(&variant) {
+            error!("Failed to remove image {}: {}", variant, error);
+        }
+    }
+}
+
+/// Encode an image in the given format.
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, Error> {
+    let mut bytes = Cursor::new(Vec::new());
+
+    // The WebP and JPEG encoders do not accept every color type, so convert to RGBA or RGB first.
+    let image = match format {
+        ImageFormat::WebP => DynamicImage::ImageRgba8(image.to_rgba8()),
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+        _ => image.clone(),
+    };
+
+    image.write_to(&mut bytes, format).map_err(|error| {
+        error!("Failed to encode image: {}", error);
+        io::Error::other("Encoding error")
+    })?;
+
+    Ok(bytes.into_inner())
+}
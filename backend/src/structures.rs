@@ -3,7 +3,9 @@
 //! This software may be modified and distributed under the terms of the MIT license.
 //! See the LICENSE file for details.
 
+use mongodb::bson::{doc, Bson, DateTime};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /// A struct representing a product.
 #[derive(Clone, Default, Deserialize, Serialize)]
@@ -20,16 +22,20 @@ pub struct Product {
     /// The product description.
     pub description: String,
 
-    /// The product image as a URL.
+    /// The product image as a URL, kept in sync with the primary gallery image.
     pub image: String,
 
+    /// The product image gallery in display order.
+    #[serde(default)]
+    pub images: Vec<Image>,
+
     /// The product category.
     pub category: String,
 
-    /// The product price.
+    /// The product effective price, which is the sale price while a sale is running.
     pub price: f32,
 
-    /// The product stock level.
+    /// The product stock level, aggregated from the variants if there are any.
     pub stock: u32,
 
     /// The product total number of sales.
@@ -40,4 +46,1169 @@ pub struct Product {
 
     /// The product number of clicks.
     pub clicks: u32,
+
+    /// The product popularity score, computed from its recent clicks and purchases with older events weighing less.
+    #[serde(default)]
+    pub popularity: f32,
+
+    /// The product option axes (e.g. size, color).
+    #[serde(default)]
+    pub options: Vec<String>,
+
+    /// The product variants, one per combination of option values.
+    #[serde(default)]
+    pub variants: Vec<Variant>,
+
+    /// The lowest price across the product variants.
+    #[serde(default)]
+    pub min_price: f32,
+
+    /// The highest price across the product variants.
+    #[serde(default)]
+    pub max_price: f32,
+
+    /// The product regular price while a sale is running, shown as the original price.
+    #[serde(default)]
+    pub compare_at: Option<f32>,
+
+    /// The product sale, if one is scheduled or running.
+    #[serde(default)]
+    pub sale: Option<Sale>,
+
+    /// The product upcoming price changes.
+    #[serde(default)]
+    pub scheduled_prices: Vec<ScheduledPrice>,
+
+    /// The product specifications (e.g. brand, weight, material) keyed by attribute name.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, AttributeValue>,
+
+    /// The stock level at or below which the product is low on stock, or `None` for the default threshold.
+    #[serde(default)]
+    pub low_stock_threshold: Option<u32>,
+
+    /// The product lifecycle status.
+    #[serde(default)]
+    pub status: ProductStatus,
+
+    /// The product creation time in milliseconds since the Unix epoch.
+    #[serde(default)]
+    pub created_at: i64,
+
+    /// The product last modification time in milliseconds since the Unix epoch.
+    #[serde(default)]
+    pub updated_at: i64,
+
+    /// The product version, incremented on every modification.
+    #[serde(default)]
+    pub version: u32,
+}
+
+/// An enum representing the lifecycle status of a product.
+///
+/// Only active products are shown in the catalog and in search results.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum ProductStatus {
+    /// The product is being prepared by the seller and is not listed yet.
+    Draft,
+
+    /// The product is listed and in stock.
+    #[default]
+    Active,
+
+    /// The product is listed but has no stock left.
+    OutOfStock,
+
+    /// The product has been delisted by the seller and can be restored.
+    Archived,
+}
+
+impl ProductStatus {
+    /// The status as stored in the database and the search index.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProductStatus::Draft => "draft",
+            ProductStatus::Active => "active",
+            ProductStatus::OutOfStock => "out_of_stock",
+            ProductStatus::Archived => "archived",
+        }
+    }
+
+    /// The database filter selecting the products with the status.
+    pub fn filter(&self) -> Bson {
+        match self {
+            // Documents stored before statuses were introduced are active.
+            ProductStatus::Active => {
+                Bson::Document(doc! { "$in": [ProductStatus::Active.as_str(), Bson::Null] })
+            }
+            status => Bson::String(status.as_str().to_string()),
+        }
+    }
+}
+
+/// A struct representing a product variant.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Variant {
+    /// The variant stock keeping unit, unique within the product.
+    pub sku: String,
+
+    /// The variant option values keyed by option axis.
+    pub options: BTreeMap<String, String>,
+
+    /// The variant price, overriding the product price if set, including during a sale.
+    #[serde(default)]
+    pub price: Option<f32>,
+
+    /// The variant stock level.
+    #[serde(default)]
+    pub stock: u32,
+
+    /// The variant image as a URL, falling back to the product image if empty.
+    #[serde(default)]
+    pub image: String,
+}
+
+/// A struct representing a product sale.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Sale {
+    /// The sale price.
+    pub price: f32,
+
+    /// The sale start time in milliseconds since the Unix epoch.
+    pub start: i64,
+
+    /// The sale end time in milliseconds since the Unix epoch.
+    pub end: i64,
+}
+
+/// A struct representing a scheduled change of the product regular price.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct ScheduledPrice {
+    /// The new regular price.
+    pub price: f32,
+
+    /// The time the price takes effect in milliseconds since the Unix epoch.
+    pub at: i64,
+}
+
+/// A struct representing a product gallery image.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Image {
+    /// The image as a URL, unique within the product gallery.
+    pub url: String,
+
+    /// The image alternative text.
+    #[serde(default)]
+    pub alt: String,
+
+    /// The image width in pixels.
+    #[serde(default)]
+    pub width: u32,
+
+    /// The image height in pixels.
+    #[serde(default)]
+    pub height: u32,
+
+    /// Whether the image is the primary product image.
+    #[serde(default)]
+    pub primary: bool,
+}
+
+/// An enum representing the value of a product attribute.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    /// A yes or no value.
+    Boolean(bool),
+
+    /// A numeric value, expressed in the unit of the attribute definition.
+    Number(f64),
+
+    /// A free text value.
+    Text(String),
+}
+
+/// An enum representing the type of a product attribute.
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AttributeKind {
+    Boolean,
+    Number,
+    Text,
+}
+
+/// A struct representing the definition of a product attribute.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AttributeDefinition {
+    /// The attribute name.
+    pub name: String,
+
+    /// The attribute type.
+    pub kind: AttributeKind,
+
+    /// The attribute unit (e.g. kg, GB), if any.
+    #[serde(default)]
+    pub unit: String,
+
+    /// Whether every product of the category must have the attribute.
+    #[serde(default)]
+    pub required: bool,
+
+    /// Whether the attribute can be used to filter and facet search results.
+    #[serde(default)]
+    pub filterable: bool,
+}
+
+/// A struct representing the attribute schema of a product category.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AttributeSchema {
+    /// The product category the schema applies to.
+    pub category: String,
+
+    /// The attribute definitions.
+    pub attributes: Vec<AttributeDefinition>,
+}
+
+impl AttributeSchema {
+    /// Convert text attribute values to the type of their definition, such as values read from a CSV file.
+    ///
+    /// Values that do not parse as the defined type are left untouched so that validation reports them.
+    pub fn coerce(&self, attributes: &mut BTreeMap<String, AttributeValue>) {
+        for definition in self.attributes.iter() {
+            let value = match attributes.get_mut(&definition.name) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let text = match value {
+                AttributeValue::Text(text) => text.trim(),
+                _ => continue,
+            };
+
+            let coerced = match definition.kind {
+                AttributeKind::Boolean => text.parse().ok().map(AttributeValue::Boolean),
+                AttributeKind::Number => text.parse().ok().map(AttributeValue::Number),
+                AttributeKind::Text => None,
+            };
+
+            if let Some(coerced) = coerced {
+                *value = coerced;
+            }
+        }
+    }
+
+    /// Validate product attributes against the schema.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if every attribute is defined with a matching type and every required attribute is present, or an `Err` describing the first violation.
+    pub fn validate(&self, attributes: &BTreeMap<String, AttributeValue>) -> Result<(), String> {
+        for (name, value) in attributes.iter() {
+            let definition = match self
+                .attributes
+                .iter()
+                .find(|definition| &definition.name == name)
+            {
+                Some(definition) => definition,
+                None => return Err(format!("Unknown attribute `{}`", name)),
+            };
+
+            let matches = matches!(
+                (definition.kind, value),
+                (AttributeKind::Boolean, AttributeValue::Boolean(_))
+                    | (AttributeKind::Number, AttributeValue::Number(_))
+                    | (AttributeKind::Text, AttributeValue::Text(_))
+            );
+
+            if !matches {
+                return Err(format!("Attribute `{}` has the wrong type", name));
+            }
+        }
+
+        match self
+            .attributes
+            .iter()
+            .find(|definition| definition.required && !attributes.contains_key(&definition.name))
+        {
+            Some(definition) => Err(format!("Missing attribute `{}`", definition.name)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A struct representing an entry of the product audit log.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AuditEntry {
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The ID of the actor who modified the product.
+    pub actor: String,
+
+    /// The endpoint the modification was made through (e.g. PATCH /api/product/{pid}).
+    pub endpoint: String,
+
+    /// The modification time in milliseconds since the Unix epoch.
+    pub timestamp: i64,
+
+    /// The modified fields keyed by field name.
+    pub changes: BTreeMap<String, Change>,
+}
+
+/// A struct representing the change of a product field.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Change {
+    /// The field value before the modification, `null` if the product was created.
+    pub before: Bson,
+
+    /// The field value after the modification, `null` if the product was deleted.
+    pub after: Bson,
+}
+
+/// An enum representing the kind of a product analytics event.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// The product was displayed to a shopper, in a listing or on its page.
+    View,
+
+    /// The product was clicked in a listing.
+    Click,
+
+    /// The product was added to a cart.
+    AddToCart,
+
+    /// The product was added to a wishlist.
+    Wishlist,
+
+    /// The product was purchased.
+    Purchase,
+}
+
+impl EventKind {
+    /// The name of the event kind as stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::View => "view",
+            EventKind::Click => "click",
+            EventKind::AddToCart => "add_to_cart",
+            EventKind::Wishlist => "wishlist",
+            EventKind::Purchase => "purchase",
+        }
+    }
+}
+
+/// A struct representing a product analytics event.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Event {
+    /// The kind of the event.
+    pub kind: EventKind,
+
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The seller ID formatted as (S0000...).
+    pub sid: String,
+
+    /// The anonymous ID of the browsing session the event occurred in.
+    pub session: Option<String>,
+
+    /// The number of units added to a cart or purchased, 1 for other events.
+    #[serde(default = "default_quantity")]
+    pub quantity: u32,
+
+    /// The effective unit price of the product when it was purchased.
+    #[serde(default)]
+    pub price: Option<f32>,
+
+    /// The event time in milliseconds since the Unix epoch.
+    pub timestamp: i64,
+}
+
+/// The quantity of events stored before quantities were recorded.
+fn default_quantity() -> u32 {
+    1
+}
+
+/// An enum representing the length of an analytics time bucket.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    /// One hour.
+    Hour,
+
+    /// One day, starting at midnight UTC.
+    #[default]
+    Day,
+}
+
+impl Granularity {
+    /// The length of a bucket in milliseconds.
+    pub fn millis(&self) -> i64 {
+        match self {
+            Granularity::Hour => 3_600_000,
+            Granularity::Day => 86_400_000,
+        }
+    }
+
+    /// The start of the bucket containing a time in milliseconds since the Unix epoch.
+    pub fn bucket(&self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.millis())
+    }
+
+    /// The name of the granularity as stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Hour => "hour",
+            Granularity::Day => "day",
+        }
+    }
+}
+
+/// A struct representing the number of analytics events of each kind.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct EventCounts {
+    /// The number of product page views.
+    #[serde(default)]
+    pub view: u64,
+
+    /// The number of clicks in listings.
+    #[serde(default)]
+    pub click: u64,
+
+    /// The number of additions to a cart.
+    #[serde(default)]
+    pub add_to_cart: u64,
+
+    /// The number of additions to a wishlist.
+    #[serde(default)]
+    pub wishlist: u64,
+
+    /// The number of purchases.
+    #[serde(default)]
+    pub purchase: u64,
+}
+
+impl EventCounts {
+    /// Add the counts of another bucket.
+    pub fn add(&mut self, other: &EventCounts) {
+        self.view += other.view;
+        self.click += other.click;
+        self.add_to_cart += other.add_to_cart;
+        self.wishlist += other.wishlist;
+        self.purchase += other.purchase;
+    }
+}
+
+/// A struct representing the analytics events of a product in a time bucket, kept up to date as events are recorded.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Rollup {
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The seller ID formatted as (S0000...).
+    pub sid: String,
+
+    /// The length of the bucket.
+    pub granularity: Granularity,
+
+    /// The start of the bucket in milliseconds since the Unix epoch.
+    pub start: i64,
+
+    /// The number of events of each kind in the bucket.
+    #[serde(default)]
+    pub counts: EventCounts,
+}
+
+/// A struct representing the products most often bought together with a product, computed periodically from the purchase events.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BoughtTogether {
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The products bought in the same sessions, most often bought together first.
+    pub products: Vec<CoPurchase>,
+
+    /// The computation time in milliseconds since the Unix epoch.
+    pub computed_at: i64,
+}
+
+/// A struct representing a product bought in the same sessions as another product.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CoPurchase {
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The number of sessions in which both products were purchased.
+    pub count: u64,
+}
+
+/// A struct representing the products recently viewed by a shopper.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct RecentViews {
+    /// The user ID of the shopper, or the anonymous ID of their browsing session.
+    pub viewer: String,
+
+    /// The viewed products, most recently viewed first, each product appearing once.
+    pub products: Vec<RecentView>,
+
+    /// The time the history is deleted unless the shopper views another product.
+    pub expires_at: DateTime,
+}
+
+/// A struct representing a recently viewed product.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct RecentView {
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The last view time in milliseconds since the Unix epoch.
+    pub viewed_at: i64,
+}
+
+impl Product {
+    /// Record a modification of the product by bumping its version and modification time.
+    ///
+    /// Products without a creation time, such as newly listed ones, are also given one.
+    pub fn touch(&mut self) {
+        let now = DateTime::now().timestamp_millis();
+
+        if self.created_at == 0 {
+            self.created_at = now;
+        }
+
+        self.updated_at = now;
+        self.version += 1;
+    }
+
+    /// Aggregate the effective price, the variant prices, stock levels and the primary gallery image into the product.
+    ///
+    /// Products without variants use their own price for both bounds and keep their stock level, products without a gallery keep their image. Listed products move between active and out of stock depending on their stock level.
+    pub fn aggregate(&mut self) {
+        self.apply_pricing(DateTime::now().timestamp_millis());
+        self.set_primary_image(None);
+
+        if self.variants.is_empty() {
+            self.min_price = self.price;
+            self.max_price = self.price;
+        } else {
+            self.aggregate_variants();
+        }
+
+        self.status = match (self.status, self.stock) {
+            (ProductStatus::Active, 0) => ProductStatus::OutOfStock,
+            (ProductStatus::OutOfStock, stock) if stock > 0 => ProductStatus::Active,
+            (status, _) => status,
+        };
+    }
+
+    /// The product regular price, regardless of any running sale.
+    pub fn regular_price(&self) -> f32 {
+        self.compare_at.unwrap_or(self.price)
+    }
+
+    /// Change the product regular price, keeping the sale price if a sale is running.
+    pub fn set_regular_price(&mut self, price: f32) {
+        match self.compare_at.as_mut() {
+            Some(regular) => *regular = price,
+            None => self.price = price,
+        }
+    }
+
+    /// Apply the scheduled price changes that are due and open or close the sale at the given time.
+    ///
+    /// While a sale is running, the sale price is the effective price and the regular price is kept in `compare_at`. Ended sales are removed.
+    pub fn apply_pricing(&mut self, now: i64) {
+        self.scheduled_prices.sort_by_key(|scheduled| scheduled.at);
+
+        let due = self
+            .scheduled_prices
+            .iter()
+            .take_while(|scheduled| scheduled.at <= now)
+            .count();
+
+        // Only the latest due price change matters, the earlier ones have been superseded.
+        let latest = self.scheduled_prices.drain(..due).next_back();
+
+        if let Some(scheduled) = latest {
+            self.set_regular_price(scheduled.price);
+        }
+
+        let regular = self.regular_price();
+
+        match &self.sale {
+            Some(sale) if sale.start <= now && now < sale.end => {
+                self.compare_at = Some(regular);
+                self.price = sale.price;
+            }
+            Some(sale) if sale.end <= now => {
+                self.sale = None;
+                self.compare_at = None;
+                self.price = regular;
+            }
+            _ => {
+                self.compare_at = None;
+                self.price = regular;
+            }
+        }
+    }
+
+    /// Aggregate the variant prices and stock levels into the product.
+    ///
+    /// Variants without a price override sell at the effective product price, so they follow its sales and scheduled changes, while a price override is the price of its variant whatever the product pricing.
+    fn aggregate_variants(&mut self) {
+        let prices = self
+            .variants
+            .iter()
+            .map(|variant| variant.price.unwrap_or(self.price));
+
+        self.min_price = prices.clone().fold(f32::MAX, f32::min);
+        self.max_price = prices.fold(f32::MIN, f32::max);
+        self.stock = self.variants.iter().map(|variant| variant.stock).sum();
+    }
+
+    /// Mark the gallery image with the given URL as primary, or ensure exactly one primary image if none is given.
+    ///
+    /// Returns `false` if the given URL is not part of the gallery.
+    pub fn set_primary_image(&mut self, url: Option<&str>) -> bool {
+        let primary = match url {
+            Some(url) => self.images.iter().position(|image| image.url == url),
+            None => Some(
+                self.images
+                    .iter()
+                    .position(|image| image.primary)
+                    .unwrap_or(0),
+            ),
+        };
+
+        match primary {
+            Some(index) if index < self.images.len() => {
+                for (position, image) in self.images.iter_mut().enumerate() {
+                    image.primary = position == index;
+                }
+
+                self.image = self.images[index].url.clone();
+                true
+            }
+            _ => url.is_none(),
+        }
+    }
+}
+
+/// An enum representing the stock level of a listed product, as last seen by the stock checker.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StockLevel {
+    /// The stock level is above the low stock threshold.
+    InStock,
+
+    /// The stock level is at or below the low stock threshold.
+    Low,
+
+    /// The product has no stock left.
+    Out,
+}
+
+impl StockLevel {
+    /// The stock level of a stock quantity given the low stock threshold.
+    pub fn of(stock: u32, threshold: u32) -> Self {
+        if stock == 0 {
+            StockLevel::Out
+        } else if stock <= threshold {
+            StockLevel::Low
+        } else {
+            StockLevel::InStock
+        }
+    }
+
+    /// The name of the stock level as stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StockLevel::InStock => "in_stock",
+            StockLevel::Low => "low",
+            StockLevel::Out => "out",
+        }
+    }
+}
+
+/// A struct representing the stock level of a product as last seen by the stock checker.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct StockState {
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The stock level.
+    pub level: StockLevel,
+
+    /// The time the stock level was first seen in milliseconds since the Unix epoch.
+    pub since: i64,
+}
+
+/// An enum representing the kind of a stock alert.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StockAlertKind {
+    /// The stock level crossed below the low stock threshold.
+    Low,
+
+    /// The product ran out of stock.
+    Out,
+
+    /// The product is back in stock after running out.
+    Restocked,
+}
+
+/// A struct representing a stock alert, delivered to the stock alert webhooks.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct StockAlert {
+    /// The kind of the alert.
+    pub kind: StockAlertKind,
+
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The seller ID formatted as (S0000...).
+    pub sid: String,
+
+    /// The product name.
+    pub name: String,
+
+    /// The product stock level.
+    pub stock: u32,
+
+    /// The low stock threshold of the product.
+    pub threshold: u32,
+
+    /// The user IDs of the shoppers to notify that the product is back in stock, empty for other alerts.
+    pub subscribers: Vec<String>,
+
+    /// The alert time in milliseconds since the Unix epoch.
+    pub timestamp: i64,
+}
+
+/// A struct representing the request of a shopper to be notified when a product is back in stock.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct RestockSubscription {
+    /// The product ID formatted as (P0000...).
+    pub pid: String,
+
+    /// The user ID of the shopper.
+    pub uid: String,
+
+    /// The subscription time in milliseconds since the Unix epoch.
+    pub created_at: i64,
+}
+
+/// An enum representing a product lifecycle event delivered to webhook subscriptions.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub enum WebhookEvent {
+    /// A product was listed.
+    #[serde(rename = "product.created")]
+    ProductCreated,
+
+    /// A product was modified.
+    #[serde(rename = "product.updated")]
+    ProductUpdated,
+
+    /// A product was delisted.
+    #[serde(rename = "product.delisted")]
+    ProductDelisted,
+
+    /// The stock level of a product changed.
+    #[serde(rename = "stock.changed")]
+    StockChanged,
+
+    /// The effective price of a product changed.
+    #[serde(rename = "price.changed")]
+    PriceChanged,
+}
+
+impl WebhookEvent {
+    /// The name of the event as stored in the database and sent in the `X-Webhook-Event` header.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::ProductCreated => "product.created",
+            WebhookEvent::ProductUpdated => "product.updated",
+            WebhookEvent::ProductDelisted => "product.delisted",
+            WebhookEvent::StockChanged => "stock.changed",
+            WebhookEvent::PriceChanged => "price.changed",
+        }
+    }
+}
+
+/// A struct representing a webhook subscription of another module.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct WebhookSubscription {
+    /// The subscription ID.
+    pub id: String,
+
+    /// The URL the events are posted to.
+    pub url: String,
+
+    /// The secret the deliveries are signed with.
+    pub secret: String,
+
+    /// The events delivered to the subscription.
+    pub events: Vec<WebhookEvent>,
+
+    /// The subscription time in milliseconds since the Unix epoch.
+    pub created_at: i64,
+}
+
+/// A struct representing the delivery of an event to a webhook subscription, pending or dead-lettered.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct WebhookDelivery {
+    /// The delivery ID.
+    pub id: String,
+
+    /// The ID of the subscription the event is delivered to.
+    pub subscription: String,
+
+    /// The product ID formatted as (P0000...).
+    #[serde(default)]
+    pub pid: String,
+
+    /// The URL of the subscription when the event was emitted.
+    pub url: String,
+
+    /// The delivered event.
+    pub event: WebhookEvent,
+
+    /// The JSON body of the delivery, signed as is.
+    pub payload: String,
+
+    /// The number of failed delivery attempts.
+    pub attempts: u32,
+
+    /// The time of the next delivery attempt in milliseconds since the Unix epoch.
+    pub next_attempt: i64,
+
+    /// The error of the last failed delivery attempt.
+    pub last_error: Option<String>,
+
+    /// The time the event was emitted in milliseconds since the Unix epoch.
+    pub created_at: i64,
+
+    /// The time the delivery was given up in milliseconds since the Unix epoch, if dead-lettered.
+    #[serde(default)]
+    pub failed_at: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{test, variant};
+
+    fn product(price: f32) -> Product {
+        crate::testing::product("P1", price)
+    }
+
+    fn on_sale(price: f32, sale_price: f32) -> Product {
+        Product {
+            sale: Some(Sale {
+                price: sale_price,
+                start: 100,
+                end: 200,
+            }),
+            ..product(price)
+        }
+    }
+
+    #[test]
+    fn sale_applies_within_its_window() {
+        let mut product = on_sale(50.0, 40.0);
+
+        product.apply_pricing(99);
+        assert_eq!(product.price, 50.0);
+        assert_eq!(product.compare_at, None);
+
+        product.apply_pricing(100);
+        assert_eq!(product.price, 40.0);
+        assert_eq!(product.compare_at, Some(50.0));
+
+        product.apply_pricing(199);
+        assert_eq!(product.price, 40.0);
+        assert_eq!(product.regular_price(), 50.0);
+    }
+
+    #[test]
+    fn ended_sale_is_removed() {
+        let mut product = on_sale(50.0, 40.0);
+
+        product.apply_pricing(150);
+        product.apply_pricing(200);
+
+        assert_eq!(product.price, 50.0);
+        assert_eq!(product.compare_at, None);
+        assert!(product.sale.is_none());
+    }
+
+    #[test]
+    fn latest_due_scheduled_price_wins() {
+        let mut product = Product {
+            scheduled_prices: vec![
+                ScheduledPrice {
+                    price: 30.0,
+                    at: 20,
+                },
+                ScheduledPrice {
+                    price: 20.0,
+                    at: 10,
+                },
+                ScheduledPrice {
+                    price: 60.0,
+                    at: 300,
+                },
+            ],
+            ..product(50.0)
+        };
+
+        product.apply_pricing(100);
+
+        assert_eq!(product.price, 30.0);
+        assert_eq!(product.scheduled_prices.len(), 1);
+        assert_eq!(product.scheduled_prices[0].at, 300);
+    }
+
+    #[test]
+    fn scheduled_price_during_sale_changes_regular_price() {
+        let mut product = Product {
+            scheduled_prices: vec![ScheduledPrice {
+                price: 70.0,
+                at: 150,
+            }],
+            ..on_sale(50.0, 40.0)
+        };
+
+        product.apply_pricing(120);
+        product.apply_pricing(160);
+
+        assert_eq!(product.price, 40.0);
+        assert_eq!(product.compare_at, Some(70.0));
+
+        product.apply_pricing(250);
+
+        assert_eq!(product.price, 70.0);
+        assert_eq!(product.compare_at, None);
+    }
+
+    #[test]
+    fn product_without_variants_keeps_its_price_and_stock() {
+        let mut product = product(25.0);
+
+        product.aggregate();
+
+        assert_eq!(product.min_price, 25.0);
+        assert_eq!(product.max_price, 25.0);
+        assert_eq!(product.stock, 10);
+        assert_eq!(product.status, ProductStatus::Active);
+    }
+
+    #[test]
+    fn variants_set_price_bounds_and_stock() {
+        let mut product = Product {
+            variants: vec![
+                variant("S", None, 2),
+                variant("M", Some(30.0), 0),
+                variant("L", Some(15.0), 3),
+            ],
+            ..product(20.0)
+        };
+
+        product.aggregate();
+
+        assert_eq!(product.min_price, 15.0);
+        assert_eq!(product.max_price, 30.0);
+        assert_eq!(product.stock, 5);
+    }
+
+    #[test]
+    fn sale_applies_to_variants_without_override() {
+        let mut product = Product {
+            variants: vec![variant("S", None, 1), variant("M", Some(45.0), 1)],
+            ..on_sale(50.0, 40.0)
+        };
+
+        product.apply_pricing(150);
+        product.aggregate_variants();
+
+        assert_eq!(product.min_price, 40.0);
+        assert_eq!(product.max_price, 45.0);
+        assert_eq!(product.variants[1].price, Some(45.0));
+    }
+
+    #[test]
+    fn stock_moves_status_between_active_and_out_of_stock() {
+        let mut product = Product {
+            variants: vec![variant("S", None, 0)],
+            ..product(20.0)
+        };
+
+        product.aggregate();
+        assert_eq!(product.status, ProductStatus::OutOfStock);
+
+        product.variants[0].stock = 4;
+        product.aggregate();
+        assert_eq!(product.status, ProductStatus::Active);
+
+        product.status = ProductStatus::Draft;
+        product.variants[0].stock = 0;
+        product.aggregate();
+        assert_eq!(product.status, ProductStatus::Draft);
+    }
+
+    fn gallery(urls: &[&str]) -> Product {
+        Product {
+            images: urls
+                .iter()
+                .map(|url| Image {
+                    url: url.to_string(),
+                    ..Image::default()
+                })
+                .collect(),
+            ..product(10.0)
+        }
+    }
+
+    fn primary_images(product: &Product) -> Vec<&str> {
+        product
+            .images
+            .iter()
+            .filter(|image| image.primary)
+            .map(|image| image.url.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn first_image_becomes_primary_by_default() {
+        let mut product = gallery(&["a", "b"]);
+
+        assert!(product.set_primary_image(None));
+        assert_eq!(primary_images(&product), ["a"]);
+        assert_eq!(product.image, "a");
+    }
+
+    #[test]
+    fn primary_image_is_kept_and_made_unique() {
+        let mut product = gallery(&["a", "b", "c"]);
+        product.images[1].primary = true;
+        product.images[2].primary = true;
+
+        assert!(product.set_primary_image(None));
+        assert_eq!(primary_images(&product), ["b"]);
+        assert_eq!(product.image, "b");
+    }
+
+    #[test]
+    fn primary_image_can_be_chosen_by_url() {
+        let mut product = gallery(&["a", "b"]);
+
+        assert!(product.set_primary_image(Some("b")));
+        assert_eq!(primary_images(&product), ["b"]);
+        assert_eq!(product.image, "b");
+
+        assert!(!product.set_primary_image(Some("c")));
+        assert_eq!(primary_images(&product), ["b"]);
+    }
+
+    #[test]
+    fn product_without_gallery_keeps_its_image() {
+        let mut product = Product {
+            image: "legacy".to_string(),
+            ..product(10.0)
+        };
+
+        assert!(product.set_primary_image(None));
+        assert!(!product.set_primary_image(Some("legacy")));
+        assert_eq!(product.image, "legacy");
+    }
+
+    fn schema() -> AttributeSchema {
+        let definition = |name: &str, kind, required| AttributeDefinition {
+            name: name.to_string(),
+            kind,
+            unit: String::new(),
+            required,
+            filterable: false,
+        };
+
+        AttributeSchema {
+            category: "laptops".to_string(),
+            attributes: vec![
+                definition("ram", AttributeKind::Number, true),
+                definition("touch", AttributeKind::Boolean, false),
+                definition("cpu", AttributeKind::Text, false),
+            ],
+        }
+    }
+
+    fn attributes(values: &[(&str, AttributeValue)]) -> BTreeMap<String, AttributeValue> {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn valid_attributes_pass() {
+        let attributes = attributes(&[
+            ("ram", AttributeValue::Number(16.0)),
+            ("touch", AttributeValue::Boolean(false)),
+        ]);
+
+        assert_eq!(schema().validate(&attributes), Ok(()));
+    }
+
+    #[test]
+    fn unknown_attribute_is_rejected() {
+        let attributes = attributes(&[
+            ("ram", AttributeValue::Number(16.0)),
+            ("color", AttributeValue::Text("red".to_string())),
+        ]);
+
+        assert_eq!(
+            schema().validate(&attributes),
+            Err("Unknown attribute `color`".to_string())
+        );
+    }
+
+    #[test]
+    fn mistyped_attribute_is_rejected() {
+        let attributes = attributes(&[("ram", AttributeValue::Text("16".to_string()))]);
+
+        assert_eq!(
+            schema().validate(&attributes),
+            Err("Attribute `ram` has the wrong type".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_required_attribute_is_rejected() {
+        let attributes = attributes(&[("touch", AttributeValue::Boolean(true))]);
+
+        assert_eq!(
+            schema().validate(&attributes),
+            Err("Missing attribute `ram`".to_string())
+        );
+    }
+
+    #[test]
+    fn text_values_are_coerced_to_their_type() {
+        let mut attributes = attributes(&[
+            ("ram", AttributeValue::Text(" 16 ".to_string())),
+            ("touch", AttributeValue::Text("maybe".to_string())),
+            ("cpu", AttributeValue::Text("8".to_string())),
+        ]);
+
+        schema().coerce(&mut attributes);
+
+        assert_eq!(attributes["ram"], AttributeValue::Number(16.0));
+        assert_eq!(
+            attributes["touch"],
+            AttributeValue::Text("maybe".to_string())
+        );
+        assert_eq!(attributes["cpu"], AttributeValue::Text("8".to_string()));
+    }
 }
@@ -0,0 +1,13 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Lock in-memory state shared between requests and background jobs.
+///
+/// The state guarded this way is only counters and timestamps, which a panic while the lock was held cannot leave inconsistent, so poisoning is ignored.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
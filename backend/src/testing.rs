@@ -0,0 +1,31 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::structures::{Product, Variant};
+
+/// The built-in `test` attribute, which the `test` macro of Actix web imported into every module by `#[macro_use]` shadows. Test modules import it from here.
+pub use std::prelude::v1::test;
+
+/// A listed product with the given ID and price, and stock left.
+pub fn product(pid: &str, price: f32) -> Product {
+    Product {
+        pid: pid.to_string(),
+        price,
+        min_price: price,
+        max_price: price,
+        stock: 10,
+        ..Product::default()
+    }
+}
+
+/// A variant with the given stock keeping unit, price override and stock level.
+pub fn variant(sku: &str, price: Option<f32>, stock: u32) -> Variant {
+    Variant {
+        sku: sku.to_string(),
+        price,
+        stock,
+        ..Variant::default()
+    }
+}
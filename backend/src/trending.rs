@@ -0,0 +1,91 @@
+//! Copyright (c) 2023 Mostafa Elbasiouny
+//!
+//! This software may be modified and distributed under the terms of the MIT license.
+//! See the LICENSE file for details.
+
+use crate::routes::product::ProductView;
+
+use mongodb::bson::DateTime;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// The default number of seconds the product lists are served before they expire.
+const DEFAULT_TTL: i64 = 900;
+
+/// An enum representing a homepage product list.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListKind {
+    /// The products with the most clicks and purchases over the last hours.
+    Trending,
+
+    /// The products with the most purchases over the last days.
+    BestSellers,
+}
+
+/// The homepage product lists, ranked per category.
+#[derive(Default)]
+pub struct ProductLists {
+    /// The trending products keyed by category, the empty category holding the products of every category.
+    pub trending: HashMap<String, Vec<ProductView>>,
+
+    /// The best selling products keyed by category, the empty category holding the products of every category.
+    pub bestsellers: HashMap<String, Vec<ProductView>>,
+
+    /// The computation time in milliseconds since the Unix epoch.
+    pub computed_at: i64,
+}
+
+impl ProductLists {
+    /// The ranked products of a list for a category, or of every category if the category is empty.
+    pub fn get(&self, kind: ListKind, category: &str) -> &[ProductView] {
+        let lists = match kind {
+            ListKind::Trending => &self.trending,
+            ListKind::BestSellers => &self.bestsellers,
+        };
+
+        lists.get(category).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// A cache of the homepage product lists, refreshed in the background so that requests are served from memory.
+pub struct ListCache {
+    /// The number of milliseconds the lists are served before they expire.
+    ttl: i64,
+
+    /// The last computed lists.
+    lists: RwLock<Option<Arc<ProductLists>>>,
+}
+
+impl ListCache {
+    /// Create an empty cache whose lists expire after the number of seconds read from the `TRENDING_TTL` environment variable.
+    pub fn from_env() -> Self {
+        let ttl = env::var("TRENDING_TTL")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|seconds| *seconds > 0)
+            .unwrap_or(DEFAULT_TTL);
+
+        ListCache {
+            ttl: ttl * 1000,
+            lists: RwLock::new(None),
+        }
+    }
+
+    /// The cached lists, or `None` if they were never computed or have expired.
+    pub fn get(&self) -> Option<Arc<ProductLists>> {
+        let now = DateTime::now().timestamp_millis();
+
+        self.lists
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .filter(|lists| now - lists.computed_at < self.ttl)
+            .cloned()
+    }
+
+    /// Replace the cached lists.
+    pub fn set(&self, lists: Arc<ProductLists>) {
+        *self.lists.write().unwrap_or_else(PoisonError::into_inner) = Some(lists);
+    }
+}